@@ -0,0 +1,10 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    // 使用仓库内的链接脚本（导出段边界符号，并固定链接基址）
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let linker_script = manifest_dir.join("linker.ld");
+    println!("cargo:rustc-link-arg-bins=-T{}", linker_script.display());
+    println!("cargo:rerun-if-changed={}", linker_script.display());
+}
@@ -0,0 +1,527 @@
+//! ACPI 表解析模块
+//! 从 RSDP 找到 RSDT/XSDT，校验并定位各系统描述表，解析 MADT 中的中断控制器信息、HPET 表与 FADT 中的 CMOS 信息
+
+use core::mem::size_of;
+use core::ptr::read_unaligned;
+use spin::Once;
+use x86_64::structures::paging::PageTableFlags;
+use crate::boot_info::BootInfo;
+use crate::constants::acpi::*;
+use crate::constants::smp::MAX_CPUS;
+use crate::error::{KernelResult, KernelError};
+
+/// RSDP 结构（ACPI 2.0 起包含扩展字段）
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// ACPI 1.0 的 RSDP 长度
+const RSDP_V1_LENGTH: usize = 20;
+
+/// 系统描述表通用表头
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// 根表信息
+struct RootTable {
+    /// RSDT 或 XSDT 的虚拟地址
+    header: &'static SdtHeader,
+    /// 为 true 时表项为 64 位（XSDT）
+    extended: bool,
+}
+
+static ROOT: Once<RootTable> = Once::new();
+static MADT: Once<MadtInfo> = Once::new();
+static HPET: Once<HpetInfo> = Once::new();
+static FADT: Once<FadtInfo> = Once::new();
+
+/// MADT 表项类型
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_NMI_SOURCE: u8 = 3;
+const MADT_LOCAL_APIC_NMI: u8 = 4;
+const MADT_LOCAL_APIC_OVERRIDE: u8 = 5;
+const MADT_LOCAL_X2APIC: u8 = 9;
+const MADT_LOCAL_X2APIC_NMI: u8 = 10;
+
+/// MADT 固定部分（表头之后）：LAPIC 地址与标志
+const MADT_ENTRIES_OFFSET: usize = size_of::<SdtHeader>() + 8;
+
+/// 中断极性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// 由总线决定（ISA 为高电平有效）
+    BusDefault,
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// 触发方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// 由总线决定（ISA 为边沿触发）
+    BusDefault,
+    Edge,
+    Level,
+}
+
+/// MPS INTI 标志（ISO、NMI 表项共用）
+#[derive(Debug, Clone, Copy)]
+pub struct IntiFlags(u16);
+
+impl IntiFlags {
+    pub fn polarity(self) -> Polarity {
+        match self.0 & 0b11 {
+            0b01 => Polarity::ActiveHigh,
+            0b11 => Polarity::ActiveLow,
+            _ => Polarity::BusDefault,
+        }
+    }
+
+    pub fn trigger_mode(self) -> TriggerMode {
+        match (self.0 >> 2) & 0b11 {
+            0b01 => TriggerMode::Edge,
+            0b11 => TriggerMode::Level,
+            _ => TriggerMode::BusDefault,
+        }
+    }
+}
+
+/// 处理器本地 APIC
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApic {
+    /// ACPI 处理器 UID
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    /// 处理器已启用或可以上线
+    pub usable: bool,
+}
+
+/// I/O APIC
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u64,
+    /// 第一条输入引脚对应的全局系统中断号
+    pub gsi_base: u32,
+}
+
+/// 中断源覆盖（ISA IRQ 到 GSI 的重新映射）
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: IntiFlags,
+}
+
+/// 连接到 I/O APIC 的 NMI 源
+#[derive(Debug, Clone, Copy)]
+pub struct NmiSource {
+    pub gsi: u32,
+    pub flags: IntiFlags,
+}
+
+/// 连接到本地 APIC LINT 引脚的 NMI
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicNmi {
+    /// ACPI 处理器 UID，`u32::MAX` 表示所有处理器
+    pub processor_uid: u32,
+    /// LINT0 或 LINT1
+    pub lint: u8,
+    pub flags: IntiFlags,
+}
+
+/// 定长表（没有堆分配器）
+#[derive(Clone, Copy)]
+struct FixedList<T: Copy, const N: usize> {
+    items: [Option<T>; N],
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> FixedList<T, N> {
+    const fn new() -> Self {
+        Self { items: [None; N], len: 0 }
+    }
+
+    fn push(&mut self, item: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[self.len] = Some(item);
+        self.len += 1;
+        true
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items[..self.len].iter().flatten()
+    }
+}
+
+/// MADT 解析结果
+pub struct MadtInfo {
+    /// 本地 APIC 的物理地址
+    pub local_apic_address: u64,
+    /// 系统同时带有 8259 PIC
+    pub pcat_compat: bool,
+    local_apics: FixedList<LocalApic, MAX_CPUS>,
+    io_apics: FixedList<IoApic, MAX_IOAPICS>,
+    overrides: FixedList<InterruptOverride, MAX_INTERRUPT_OVERRIDES>,
+    nmi_sources: FixedList<NmiSource, MAX_NMI_SOURCES>,
+    local_apic_nmis: FixedList<LocalApicNmi, MAX_NMI_SOURCES>,
+}
+
+impl MadtInfo {
+    pub fn local_apics(&self) -> impl Iterator<Item = &LocalApic> {
+        self.local_apics.iter()
+    }
+
+    pub fn io_apics(&self) -> impl Iterator<Item = &IoApic> {
+        self.io_apics.iter()
+    }
+
+    pub fn overrides(&self) -> impl Iterator<Item = &InterruptOverride> {
+        self.overrides.iter()
+    }
+
+    pub fn nmi_sources(&self) -> impl Iterator<Item = &NmiSource> {
+        self.nmi_sources.iter()
+    }
+
+    pub fn local_apic_nmis(&self) -> impl Iterator<Item = &LocalApicNmi> {
+        self.local_apic_nmis.iter()
+    }
+
+    /// 查找 ISA IRQ 的中断源覆盖
+    pub fn isa_override(&self, irq: u8) -> Option<&InterruptOverride> {
+        self.overrides().find(|iso| iso.bus == 0 && iso.source == irq)
+    }
+}
+
+/// HPET 表描述的一个高精度事件定时器块
+#[derive(Debug, Clone, Copy)]
+pub struct HpetInfo {
+    /// 寄存器块的物理地址（系统内存空间）
+    pub address: u64,
+    /// 定时器块序号
+    pub number: u8,
+    /// 周期模式下不丢中断的最小计数间隔
+    pub min_tick: u16,
+    /// 事件定时器块 ID（与 GCAP_ID 低 32 位相同）
+    pub block_id: u32,
+}
+
+/// HPET 表字段偏移（表头之后）：块 ID、基址（GAS）、序号、最小间隔
+const HPET_BLOCK_ID_OFFSET: usize = size_of::<SdtHeader>();
+const HPET_ADDRESS_OFFSET: usize = size_of::<SdtHeader>() + 4;
+const HPET_NUMBER_OFFSET: usize = size_of::<SdtHeader>() + 16;
+const HPET_MIN_TICK_OFFSET: usize = size_of::<SdtHeader>() + 17;
+const HPET_TABLE_LENGTH: usize = size_of::<SdtHeader>() + 20;
+
+/// FADT 中与 CMOS 实时时钟相关的字段
+#[derive(Debug, Clone, Copy)]
+pub struct FadtInfo {
+    /// 表修订号
+    pub revision: u8,
+    /// RTC 世纪寄存器在 CMOS 中的索引（0 表示不支持）
+    pub century: u8,
+    /// IA-PC 启动架构标志（ACPI 2.0 之前的表为 0）
+    pub boot_arch: u16,
+}
+
+impl FadtInfo {
+    /// 平台是否带有 CMOS RTC
+    pub fn has_cmos_rtc(&self) -> bool {
+        self.boot_arch & FADT_BOOT_ARCH_NO_CMOS_RTC == 0
+    }
+}
+
+/// FADT 字段偏移（含表头）：世纪寄存器索引、IA-PC 启动架构标志
+const FADT_CENTURY_OFFSET: usize = 108;
+const FADT_BOOT_ARCH_OFFSET: usize = 109;
+
+/// IAPC_BOOT_ARCH：平台没有 CMOS RTC（ACPI 5.0）
+const FADT_BOOT_ARCH_NO_CMOS_RTC: u16 = 1 << 5;
+
+/// GAS 地址空间：系统内存
+const GAS_SYSTEM_MEMORY: u8 = 0;
+
+/// 计算字节校验和（合法的 ACPI 结构各字节之和为 0）
+fn checksum(ptr: *const u8, len: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// 映射一段 ACPI 数据（ACPI 表可能位于直接映射未覆盖的保留区）
+fn map(phys: u64, len: usize) -> KernelResult<*const u8> {
+    crate::memory::map_physical(phys, len as u64, PageTableFlags::empty())
+        .map(|virt| virt.as_ptr())
+        .map_err(|_| KernelError::AcpiError)
+}
+
+/// 映射并校验一张系统描述表
+fn map_table(phys: u64) -> KernelResult<&'static SdtHeader> {
+    let header = map(phys, size_of::<SdtHeader>())? as *const SdtHeader;
+    let length = unsafe { read_unaligned(core::ptr::addr_of!((*header).length)) } as usize;
+    if length < size_of::<SdtHeader>() {
+        return Err(KernelError::AcpiError);
+    }
+    let ptr = map(phys, length)?;
+    if !checksum(ptr, length) {
+        return Err(KernelError::AcpiError);
+    }
+    Ok(unsafe { &*(ptr as *const SdtHeader) })
+}
+
+/// 初始化 ACPI：定位根表并解析 MADT
+pub fn init(boot_info: &dyn BootInfo) -> KernelResult<()> {
+    let rsdp_phys = boot_info.rsdp_address().ok_or(KernelError::AcpiError)?;
+    let rsdp = map(rsdp_phys, size_of::<Rsdp>())? as *const Rsdp;
+    let rsdp = unsafe { read_unaligned(rsdp) };
+    if &rsdp.signature != b"RSD PTR " || !checksum(&rsdp as *const Rsdp as *const u8, RSDP_V1_LENGTH) {
+        return Err(KernelError::AcpiError);
+    }
+
+    // ACPI 2.0 及以上优先使用 XSDT
+    let (root_phys, extended) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, true)
+    } else {
+        (rsdp.rsdt_address as u64, false)
+    };
+    let header = map_table(root_phys)?;
+    ROOT.call_once(|| RootTable { header, extended });
+
+    let oem = rsdp.oem_id;
+    log::info!(
+        "ACPI: revision {}, OEM \"{}\", {} at {:#x}",
+        rsdp.revision,
+        core::str::from_utf8(&oem).unwrap_or("?").trim_end(),
+        if extended { "XSDT" } else { "RSDT" },
+        root_phys
+    );
+
+    let madt = find_table(b"APIC").ok_or(KernelError::AcpiError)?;
+    let madt = MADT.call_once(|| parse_madt(madt));
+    log::info!(
+        "MADT: LAPIC at {:#x}, {} usable CPU(s), {} IOAPIC(s), {} override(s), {} NMI source(s), {} LAPIC NMI(s){}",
+        madt.local_apic_address,
+        madt.local_apics().filter(|lapic| lapic.usable).count(),
+        madt.io_apics().count(),
+        madt.overrides().count(),
+        madt.nmi_sources().count(),
+        madt.local_apic_nmis().count(),
+        if madt.pcat_compat { ", dual 8259 present" } else { "" }
+    );
+    for iso in madt.overrides() {
+        log::info!(
+            "MADT: IRQ {} -> GSI {} ({:?}, {:?})",
+            iso.source,
+            iso.gsi,
+            iso.flags.polarity(),
+            iso.flags.trigger_mode()
+        );
+    }
+
+    if let Some(hpet) = find_table(b"HPET").and_then(parse_hpet) {
+        let hpet = HPET.call_once(|| hpet);
+        log::info!(
+            "HPET table: timer block {} at {:#x}, id {:#010x}, minimum tick {}",
+            hpet.number,
+            hpet.address,
+            hpet.block_id,
+            hpet.min_tick
+        );
+    }
+
+    if let Some(fadt) = find_table(b"FACP").map(parse_fadt) {
+        let fadt = FADT.call_once(|| fadt);
+        log::info!(
+            "FADT: revision {}, RTC century register {:#x}{}",
+            fadt.revision,
+            fadt.century,
+            if fadt.has_cmos_rtc() { "" } else { ", no CMOS RTC" }
+        );
+    }
+    Ok(())
+}
+
+/// 按签名查找系统描述表
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+    let root = ROOT.r#try()?;
+    let entry_size = if root.extended { 8 } else { 4 };
+    let base = root.header as *const SdtHeader as *const u8;
+    let count = (root.header.length as usize - size_of::<SdtHeader>()) / entry_size;
+
+    (0..count).find_map(|i| {
+        let entry = unsafe { base.add(size_of::<SdtHeader>() + i * entry_size) };
+        let phys = unsafe {
+            if root.extended {
+                read_unaligned(entry as *const u64)
+            } else {
+                read_unaligned(entry as *const u32) as u64
+            }
+        };
+        let table = map_table(phys).ok()?;
+        (&table.signature == signature).then_some(table)
+    })
+}
+
+/// 获取 MADT 解析结果（ACPI 未初始化时返回 None）
+pub fn madt() -> Option<&'static MadtInfo> {
+    MADT.r#try()
+}
+
+/// 获取 HPET 表（固件未提供时返回 None）
+pub fn hpet() -> Option<&'static HpetInfo> {
+    HPET.r#try()
+}
+
+/// 获取 FADT 中的 CMOS 信息（固件未提供时返回 None）
+pub fn fadt() -> Option<&'static FadtInfo> {
+    FADT.r#try()
+}
+
+/// 解析 FADT，较旧的短表缺少的字段视为 0
+fn parse_fadt(table: &'static SdtHeader) -> FadtInfo {
+    let base = table as *const SdtHeader as *const u8;
+    let length = table.length as usize;
+    let field = |offset: usize, size: usize| offset + size <= length;
+    FadtInfo {
+        revision: table.revision,
+        century: if field(FADT_CENTURY_OFFSET, 1) { read::<u8>(base, FADT_CENTURY_OFFSET) } else { 0 },
+        boot_arch: if table.revision >= 2 && field(FADT_BOOT_ARCH_OFFSET, 2) {
+            read::<u16>(base, FADT_BOOT_ARCH_OFFSET)
+        } else {
+            0
+        },
+    }
+}
+
+/// 解析 HPET 表，寄存器块不在系统内存空间时忽略
+fn parse_hpet(table: &'static SdtHeader) -> Option<HpetInfo> {
+    if (table.length as usize) < HPET_TABLE_LENGTH {
+        return None;
+    }
+    let base = table as *const SdtHeader as *const u8;
+    if read::<u8>(base, HPET_ADDRESS_OFFSET) != GAS_SYSTEM_MEMORY {
+        log::warn!("HPET table: register block not in system memory, ignored");
+        return None;
+    }
+    Some(HpetInfo {
+        address: read::<u64>(base, HPET_ADDRESS_OFFSET + 4),
+        number: read::<u8>(base, HPET_NUMBER_OFFSET),
+        min_tick: read::<u16>(base, HPET_MIN_TICK_OFFSET),
+        block_id: read::<u32>(base, HPET_BLOCK_ID_OFFSET),
+    })
+}
+
+/// 从表中按偏移读取一个值
+fn read<T: Copy>(base: *const u8, offset: usize) -> T {
+    unsafe { read_unaligned(base.add(offset) as *const T) }
+}
+
+/// 解析 MADT 表项
+fn parse_madt(table: &'static SdtHeader) -> MadtInfo {
+    let base = table as *const SdtHeader as *const u8;
+    let length = table.length as usize;
+
+    let mut info = MadtInfo {
+        local_apic_address: read::<u32>(base, size_of::<SdtHeader>()) as u64,
+        pcat_compat: read::<u32>(base, size_of::<SdtHeader>() + 4) & 1 != 0,
+        local_apics: FixedList::new(),
+        io_apics: FixedList::new(),
+        overrides: FixedList::new(),
+        nmi_sources: FixedList::new(),
+        local_apic_nmis: FixedList::new(),
+    };
+
+    let mut offset = MADT_ENTRIES_OFFSET;
+    while offset + 2 <= length {
+        let entry_type = read::<u8>(base, offset);
+        let entry_length = read::<u8>(base, offset + 1) as usize;
+        if entry_length < 2 || offset + entry_length > length {
+            log::warn!("MADT: malformed entry at offset {offset:#x}");
+            break;
+        }
+        let entry = unsafe { base.add(offset) };
+        let stored = match entry_type {
+            MADT_LOCAL_APIC => {
+                let flags = read::<u32>(entry, 4);
+                info.local_apics.push(LocalApic {
+                    processor_uid: read::<u8>(entry, 2) as u32,
+                    apic_id: read::<u8>(entry, 3) as u32,
+                    // bit 0：已启用；bit 1：可上线
+                    usable: flags & 0b11 != 0,
+                })
+            }
+            MADT_IO_APIC => info.io_apics.push(IoApic {
+                id: read(entry, 2),
+                address: read::<u32>(entry, 4) as u64,
+                gsi_base: read(entry, 8),
+            }),
+            MADT_INTERRUPT_OVERRIDE => info.overrides.push(InterruptOverride {
+                bus: read(entry, 2),
+                source: read(entry, 3),
+                gsi: read(entry, 4),
+                flags: IntiFlags(read(entry, 8)),
+            }),
+            MADT_NMI_SOURCE => info.nmi_sources.push(NmiSource {
+                flags: IntiFlags(read(entry, 2)),
+                gsi: read(entry, 4),
+            }),
+            MADT_LOCAL_APIC_NMI => {
+                let uid = read::<u8>(entry, 2);
+                info.local_apic_nmis.push(LocalApicNmi {
+                    processor_uid: if uid == 0xff { u32::MAX } else { uid as u32 },
+                    flags: IntiFlags(read(entry, 3)),
+                    lint: read(entry, 5),
+                })
+            }
+            // APIC ID 超过 255 的处理器只出现在 x2APIC 表项中
+            MADT_LOCAL_X2APIC => {
+                let flags = read::<u32>(entry, 8);
+                info.local_apics.push(LocalApic {
+                    processor_uid: read(entry, 12),
+                    apic_id: read(entry, 4),
+                    usable: flags & 0b11 != 0,
+                })
+            }
+            MADT_LOCAL_X2APIC_NMI => info.local_apic_nmis.push(LocalApicNmi {
+                flags: IntiFlags(read(entry, 2)),
+                processor_uid: read(entry, 4),
+                lint: read(entry, 8),
+            }),
+            MADT_LOCAL_APIC_OVERRIDE => {
+                info.local_apic_address = read(entry, 4);
+                true
+            }
+            _ => true,
+        };
+        if !stored {
+            log::warn!("MADT: too many entries of type {entry_type}, ignoring the rest");
+        }
+        offset += entry_length;
+    }
+    info
+}
@@ -2,6 +2,7 @@
 //! 支持多种引导加载程序（bootloader_api 和 limine）
 
 use core::fmt;
+use crate::constants::memory::MAX_MEMORY_REGIONS;
 
 /// 帧缓冲区信息
 #[derive(Debug, Clone, Copy)]
@@ -36,6 +37,20 @@ pub enum MemoryRegionType {
     Framebuffer,
 }
 
+impl MemoryRegionType {
+    /// 是否为 RAM（直接映射需要覆盖的区域）
+    pub fn is_ram(self) -> bool {
+        matches!(
+            self,
+            MemoryRegionType::Usable
+                | MemoryRegionType::AcpiReclaimable
+                | MemoryRegionType::AcpiNvs
+                | MemoryRegionType::BootloaderReclaimable
+                | MemoryRegionType::KernelAndModules
+        )
+    }
+}
+
 /// 内存区域
 #[derive(Debug, Clone, Copy)]
 pub struct MemoryRegion {
@@ -44,6 +59,46 @@ pub struct MemoryRegion {
     pub region_type: MemoryRegionType,
 }
 
+impl MemoryRegion {
+    /// 空区域（用于初始化静态数组）
+    pub const EMPTY: MemoryRegion = MemoryRegion {
+        start: 0,
+        end: 0,
+        region_type: MemoryRegionType::Reserved,
+    };
+}
+
+/// 内存映射的静态存储
+/// 各引导加载程序的内存映射格式不同，统一转换后保存在这里，多余的条目会被丢弃
+pub struct MemoryMapStorage {
+    regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+    len: usize,
+}
+
+impl MemoryMapStorage {
+    /// 从区域迭代器构建
+    pub fn from_regions(regions: impl Iterator<Item = MemoryRegion>) -> Self {
+        let mut storage = Self {
+            regions: [MemoryRegion::EMPTY; MAX_MEMORY_REGIONS],
+            len: 0,
+        };
+        for region in regions.take(MAX_MEMORY_REGIONS) {
+            storage.regions[storage.len] = region;
+            storage.len += 1;
+        }
+        storage
+    }
+
+    pub fn as_slice(&self) -> &[MemoryRegion] {
+        &self.regions[..self.len]
+    }
+
+    /// 按起始地址排序
+    pub fn sort(&mut self) {
+        self.regions[..self.len].sort_unstable_by_key(|region| region.start);
+    }
+}
+
 /// 启动信息 trait
 /// 抽象不同引导加载程序的差异
 pub trait BootInfo {
@@ -56,11 +111,20 @@ pub trait BootInfo {
     /// 获取内存映射迭代器
     fn memory_regions(&self) -> &[MemoryRegion];
 
-    /// 获取 RSDP 地址（ACPI）
+    /// 获取 RSDP 物理地址（ACPI）
     fn rsdp_address(&self) -> Option<u64>;
 
     /// 获取命令行参数
     fn command_line(&self) -> Option<&str>;
+
+    /// 获取内核映像实际加载的虚拟基址（启用 KASLR 时为随机地址）
+    fn kernel_virtual_base(&self) -> Option<u64>;
+
+    /// 获取内核映像实际加载的物理基址
+    fn kernel_physical_base(&self) -> Option<u64>;
+
+    /// 获取引导加载程序建立的物理内存映射偏移（物理地址 + 偏移 = 虚拟地址）
+    fn physical_memory_offset(&self) -> Option<u64>;
 }
 
 /// 启动信息包装类型
@@ -118,10 +182,18 @@ impl BootInfo for BootInfoWrapper {
     fn memory_regions(&self) -> &[MemoryRegion] {
         match self {
             #[cfg(feature = "bootloader_api")]
-            BootInfoWrapper::BootloaderApi(_info) => {
+            BootInfoWrapper::BootloaderApi(info) => {
                 // 将 bootloader_api 的内存映射转换为通用格式
-                // 这里简化处理，实际需要动态分配或静态数组
-                &[]
+                static MEMORY_MAP: spin::Once<MemoryMapStorage> = spin::Once::new();
+                MEMORY_MAP
+                    .call_once(|| {
+                        MemoryMapStorage::from_regions(info.memory_regions.iter().map(|region| MemoryRegion {
+                            start: region.start,
+                            end: region.end,
+                            region_type: bootloader_region_type(region.kind),
+                        }))
+                    })
+                    .as_slice()
             }
             #[cfg(feature = "limine")]
             BootInfoWrapper::Limine(info) => info.memory_regions(),
@@ -151,6 +223,63 @@ impl BootInfo for BootInfoWrapper {
             BootInfoWrapper::Multiboot2(info) => info.command_line(),
         }
     }
+
+    fn kernel_virtual_base(&self) -> Option<u64> {
+        match self {
+            // bootloader_api 对 PIE 内核整体平移 kernel_image_offset
+            #[cfg(feature = "bootloader_api")]
+            BootInfoWrapper::BootloaderApi(info) => {
+                Some(info.kernel_image_offset + crate::constants::kernel::KERNEL_LINK_BASE)
+            }
+            #[cfg(feature = "limine")]
+            BootInfoWrapper::Limine(info) => info.kernel_virtual_base(),
+            #[cfg(feature = "multiboot2")]
+            BootInfoWrapper::Multiboot2(info) => info.kernel_virtual_base(),
+        }
+    }
+
+    fn kernel_physical_base(&self) -> Option<u64> {
+        match self {
+            // kernel_addr 是内核 ELF 映像所在的物理地址
+            #[cfg(feature = "bootloader_api")]
+            BootInfoWrapper::BootloaderApi(info) => Some(info.kernel_addr),
+            #[cfg(feature = "limine")]
+            BootInfoWrapper::Limine(info) => info.kernel_physical_base(),
+            #[cfg(feature = "multiboot2")]
+            BootInfoWrapper::Multiboot2(info) => info.kernel_physical_base(),
+        }
+    }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        match self {
+            #[cfg(feature = "bootloader_api")]
+            BootInfoWrapper::BootloaderApi(info) => info.physical_memory_offset.into_option(),
+            #[cfg(feature = "limine")]
+            BootInfoWrapper::Limine(info) => info.physical_memory_offset(),
+            #[cfg(feature = "multiboot2")]
+            BootInfoWrapper::Multiboot2(info) => info.physical_memory_offset(),
+        }
+    }
+}
+
+/// 转换 bootloader_api 的内存区域类型
+/// 未知类型保留了固件原始编号：UEFI 内存类型或 BIOS E820 类型
+#[cfg(feature = "bootloader_api")]
+fn bootloader_region_type(kind: bootloader_api::info::MemoryRegionKind) -> MemoryRegionType {
+    use bootloader_api::info::MemoryRegionKind;
+    match kind {
+        MemoryRegionKind::Usable => MemoryRegionType::Usable,
+        MemoryRegionKind::Bootloader => MemoryRegionType::BootloaderReclaimable,
+        // EfiLoaderCode/Data 与 EfiBootServicesCode/Data
+        MemoryRegionKind::UnknownUefi(1..=4) => MemoryRegionType::BootloaderReclaimable,
+        MemoryRegionKind::UnknownUefi(8) => MemoryRegionType::BadMemory,
+        MemoryRegionKind::UnknownUefi(9) => MemoryRegionType::AcpiReclaimable,
+        MemoryRegionKind::UnknownUefi(10) => MemoryRegionType::AcpiNvs,
+        MemoryRegionKind::UnknownBios(3) => MemoryRegionType::AcpiReclaimable,
+        MemoryRegionKind::UnknownBios(4) => MemoryRegionType::AcpiNvs,
+        MemoryRegionKind::UnknownBios(5) => MemoryRegionType::BadMemory,
+        _ => MemoryRegionType::Reserved,
+    }
 }
 
 /// Limine 启动信息结构
@@ -160,6 +289,9 @@ pub struct LimineBootInfo {
     pub memory_map: &'static [MemoryRegion],
     pub rsdp: Option<u64>,
     pub cmdline: Option<&'static str>,
+    pub kernel_virtual_base: Option<u64>,
+    pub kernel_physical_base: Option<u64>,
+    pub hhdm_offset: Option<u64>,
 }
 
 #[cfg(feature = "limine")]
@@ -216,4 +348,16 @@ impl LimineBootInfo {
     fn command_line(&self) -> Option<&str> {
         self.cmdline
     }
+
+    fn kernel_virtual_base(&self) -> Option<u64> {
+        self.kernel_virtual_base
+    }
+
+    fn kernel_physical_base(&self) -> Option<u64> {
+        self.kernel_physical_base
+    }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        self.hhdm_offset
+    }
 }
@@ -0,0 +1,195 @@
+//! 时钟事件模块
+//! 每个 CPU 使用一个可编程的定时中断设备（`ClockEvent`）：通常是本地 APIC 定时器，
+//! 其不可用时 BSP 退而使用 HPET 比较器 1。
+//! 设备以 `LOCAL_TIMER_HZ` 周期性产生本地节拍，驱动看门狗、MCE 轮询等周期性工作，
+//! 将来的调度器与定时器子系统也通过这里编程下一次事件。
+//!
+//! 动态节拍：CPU 空闲时停止周期节拍，改为最长 `NOHZ_MAX_IDLE_MS` 后的单次事件；
+//! 醒来后按单调时钟补记空闲期间错过的节拍。命令行参数 `nohz=off` 关闭。
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use x86_64::instructions::interrupts;
+use crate::constants::apic::LOCAL_TIMER_HZ;
+use crate::constants::clockevent::NOHZ_MAX_IDLE_MS;
+use crate::error::{KernelResult, KernelError};
+use crate::interrupts::TrapFrame;
+use crate::per_cpu;
+
+/// 时钟事件设备的工作方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventMode {
+    /// 停止，不再产生中断
+    Shutdown,
+    /// 按固定频率周期性触发
+    Periodic,
+    /// 到期触发一次
+    OneShot,
+}
+
+/// 可编程的定时中断设备，到期时调用 `handle_event`
+pub trait ClockEvent: Sync {
+    /// 设备名称
+    fn name(&self) -> &'static str;
+    /// 是否支持某种工作方式
+    fn supports(&self, mode: EventMode) -> bool;
+    /// 以 `hz` 的频率周期性触发
+    fn set_periodic(&self, hz: u64) -> KernelResult<()>;
+    /// 在 `delta_ns` 纳秒后触发一次（覆盖之前的设置）
+    fn set_next_event(&self, delta_ns: u64) -> KernelResult<()>;
+    /// 停止设备
+    fn shutdown(&self);
+}
+
+/// CPU 使用的设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Device {
+    None = 0,
+    Lapic = 1,
+    Hpet = 2,
+}
+
+/// 全局的动态节拍开关
+static NOHZ: AtomicBool = AtomicBool::new(true);
+
+per_cpu! {
+    // 本 CPU 使用的设备
+    static DEVICE: AtomicU8 = AtomicU8::new(Device::None as u8);
+    // 节拍数（含空闲期间补记的节拍）
+    static TICKS: AtomicU64 = AtomicU64::new(0);
+    // 时钟事件中断次数
+    static EVENTS: AtomicU64 = AtomicU64::new(0);
+    // 空闲期间周期节拍已停止
+    static TICK_STOPPED: AtomicBool = AtomicBool::new(false);
+    // 停止节拍时的单调时钟（纳秒）与节拍数
+    static IDLE_START_NS: AtomicU64 = AtomicU64::new(0);
+    static IDLE_START_TICKS: AtomicU64 = AtomicU64::new(0);
+    // 进入动态节拍空闲的次数
+    static NOHZ_ENTRIES: AtomicU64 = AtomicU64::new(0);
+}
+
+fn device_of(cpu: usize) -> Option<&'static dyn ClockEvent> {
+    match DEVICE.for_cpu(cpu).load(Ordering::Relaxed) {
+        1 => Some(&crate::lapic::LAPIC_TIMER),
+        2 => Some(&crate::hpet::HPET_EVENT),
+        _ => None,
+    }
+}
+
+/// 当前 CPU 的时钟事件设备
+pub fn device() -> Option<&'static dyn ClockEvent> {
+    device_of(crate::smp::cpu_index())
+}
+
+/// 解析命令行（在 BSP 上、启动本地节拍之前调用）
+pub fn init() {
+    if crate::cmdline::value("nohz") == Some("off") {
+        NOHZ.store(false, Ordering::Relaxed);
+    }
+}
+
+/// 为当前 CPU 选择设备并启动周期节拍（BSP 在打开中断后调用，AP 在上线时调用）
+pub fn setup_cpu() {
+    let cpu = crate::smp::cpu_index();
+    let device = if crate::lapic::LAPIC_TIMER.supports(EventMode::Periodic) {
+        Device::Lapic
+    } else if cpu == 0 && crate::hpet::HPET_EVENT.supports(EventMode::Periodic) {
+        // HPET 中断只投递到 BSP
+        crate::hpet::set_event_handler(|| handle_event(None));
+        Device::Hpet
+    } else {
+        log::warn!("clockevent: CPU{cpu} has no timer device, local tick disabled");
+        return;
+    };
+    DEVICE.get().store(device as u8, Ordering::Relaxed);
+    let Some(event) = device_of(cpu) else { return };
+    match event.set_periodic(LOCAL_TIMER_HZ) {
+        Ok(()) if cpu == 0 => log::info!(
+            "clockevent: CPU{} tick {} Hz via {}, dynamic ticks {}",
+            cpu,
+            LOCAL_TIMER_HZ,
+            event.name(),
+            if nohz_usable(event) { "enabled" } else { "disabled" }
+        ),
+        Ok(()) => {}
+        Err(e) => {
+            event.shutdown();
+            DEVICE.get().store(Device::None as u8, Ordering::Relaxed);
+            log::warn!("clockevent: CPU{cpu} cannot start {} ({e})", event.name());
+        }
+    }
+}
+
+/// 在当前 CPU 上于 `delta_ns` 纳秒后产生一次时钟事件（供定时器子系统使用，会停止周期节拍）
+#[allow(dead_code)]
+pub fn program_next_event(delta_ns: u64) -> KernelResult<()> {
+    let event = device().ok_or(KernelError::HardwareError)?;
+    if !event.supports(EventMode::OneShot) {
+        return Err(KernelError::InvalidParameter);
+    }
+    event.set_next_event(delta_ns)
+}
+
+/// 时钟事件中断处理（本地 APIC 定时器或 HPET 中断中调用，此时中断已关闭）
+///
+/// `frame` 为被打断的现场，经普通中断线投递时没有。
+pub fn handle_event(frame: Option<&TrapFrame>) {
+    EVENTS.get().fetch_add(1, Ordering::Relaxed);
+    let ticks = TICKS.get().fetch_add(1, Ordering::Relaxed) + 1;
+    crate::watchdog::timer_tick(frame, ticks);
+    crate::mce::timer_tick(ticks);
+}
+
+/// 某个 CPU 的节拍数
+pub fn ticks(cpu: usize) -> u64 {
+    TICKS.for_cpu(cpu).load(Ordering::Relaxed)
+}
+
+fn nohz_usable(event: &dyn ClockEvent) -> bool {
+    NOHZ.load(Ordering::Relaxed) && event.supports(EventMode::OneShot)
+}
+
+/// 即将进入空闲（hlt）时调用，中断必须已关闭：停止周期节拍，只保留一次最长空闲唤醒
+pub fn idle_enter() {
+    let Some(event) = device().filter(|event| nohz_usable(*event)) else { return };
+    if event.set_next_event(NOHZ_MAX_IDLE_MS * 1_000_000).is_err() {
+        return;
+    }
+    IDLE_START_NS.get().store(crate::time::monotonic_ns(), Ordering::Relaxed);
+    IDLE_START_TICKS.get().store(TICKS.get().load(Ordering::Relaxed), Ordering::Relaxed);
+    TICK_STOPPED.get().store(true, Ordering::Relaxed);
+    NOHZ_ENTRIES.get().fetch_add(1, Ordering::Relaxed);
+}
+
+/// 从空闲中醒来后调用：补记错过的节拍并恢复周期节拍
+pub fn idle_exit() {
+    interrupts::without_interrupts(|| {
+        if !TICK_STOPPED.get().swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let elapsed = crate::time::monotonic_ns().saturating_sub(IDLE_START_NS.get().load(Ordering::Relaxed));
+        let expected = elapsed * LOCAL_TIMER_HZ / 1_000_000_000;
+        let taken = TICKS.get().load(Ordering::Relaxed) - IDLE_START_TICKS.get().load(Ordering::Relaxed);
+        TICKS.get().fetch_add(expected.saturating_sub(taken), Ordering::Relaxed);
+        if let Some(event) = device() {
+            if let Err(e) = event.set_periodic(LOCAL_TIMER_HZ) {
+                log::warn!("clockevent: cannot restart tick on {} ({e})", event.name());
+            }
+        }
+    });
+}
+
+/// 输出时钟事件统计（由 irq::Stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    write!(f, "\nLOC:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", EVENTS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Local timer interrupts")?;
+    write!(f, "\nNOH:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", NOHZ_ENTRIES.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Dynamic tick idle entries")
+}
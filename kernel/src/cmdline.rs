@@ -0,0 +1,55 @@
+//! 内核命令行解析模块
+//! 保存引导加载程序传入的命令行，并提供开关与键值参数查询
+
+use spin::Once;
+use crate::constants::cmdline::CMDLINE_MAX_LEN;
+
+/// 内核命令行副本
+/// 引导加载程序提供的字符串只在启动早期保证有效，因此复制到内核自己的缓冲区中
+struct CommandLine {
+    buffer: [u8; CMDLINE_MAX_LEN],
+    len: usize,
+}
+
+impl CommandLine {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.len]).unwrap_or("")
+    }
+}
+
+static CMDLINE: Once<CommandLine> = Once::new();
+
+/// 保存命令行（只有第一次调用生效）
+pub fn init(cmdline: Option<&str>) {
+    CMDLINE.call_once(|| {
+        let mut buffer = [0u8; CMDLINE_MAX_LEN];
+        let src = cmdline.unwrap_or("").trim();
+        // 截断时退回到字符边界，保证缓冲区始终是合法的 UTF-8
+        let mut len = src.len().min(CMDLINE_MAX_LEN);
+        while !src.is_char_boundary(len) {
+            len -= 1;
+        }
+        buffer[..len].copy_from_slice(&src.as_bytes()[..len]);
+        CommandLine { buffer, len }
+    });
+}
+
+/// 获取完整的命令行字符串
+pub fn get() -> &'static str {
+    CMDLINE.r#try().map(CommandLine::as_str).unwrap_or("")
+}
+
+/// 检查是否存在某个开关参数（如 `nokaslr`）
+pub fn has_flag(name: &str) -> bool {
+    flag_in(get(), name)
+}
+
+/// 检查给定命令行中是否存在某个开关参数（命令行保存之前的入口代码使用）
+pub fn flag_in(cmdline: &str, name: &str) -> bool {
+    cmdline.split_whitespace().any(|arg| arg == name)
+}
+
+/// 查询键值参数（如 `watchdog_thresh=10`）的值，同名参数以最后一个为准
+pub fn value(name: &str) -> Option<&'static str> {
+    get().split_whitespace().rev().find_map(|arg| arg.strip_prefix(name)?.strip_prefix('='))
+}
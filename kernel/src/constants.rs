@@ -7,6 +7,243 @@ pub mod serial {
     pub const COM1_BASE: u16 = 0x3F8;
 }
 
+/// 内核映像相关常量
+pub mod kernel {
+    /// 内核链接基址（必须与 linker.ld 中的起始地址一致）
+    pub const KERNEL_LINK_BASE: u64 = 0x100000;
+}
+
+/// 内存管理相关常量
+pub mod memory {
+    /// 内存映射最多保存的区域数量
+    pub const MAX_MEMORY_REGIONS: usize = 256;
+    /// 内核直接映射区的首选起始虚拟地址（PML4 第 273 项）
+    pub const DIRECT_MAP_BASE: u64 = 0xffff_8880_0000_0000;
+    /// 帧分配器不使用的低端物理内存（保留给 BIOS 数据与实模式代码）
+    pub const LOW_MEMORY_LIMIT: u64 = 0x100000;
+}
+
+/// GDT/TSS 相关常量
+pub mod gdt {
+    /// 双重错误使用的 IST 索引
+    pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+    /// NMI 使用的 IST 索引
+    pub const NMI_IST_INDEX: u16 = 1;
+    /// 机器检查使用的 IST 索引
+    pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+    /// 每个 IST 栈的大小（字节）
+    pub const IST_STACK_SIZE: usize = 4096 * 5;
+    /// 每个 CPU 使用的 IST 栈数量
+    pub const IST_STACK_COUNT: usize = 3;
+}
+
+/// 8259 PIC 相关常量
+pub mod pic {
+    /// 主片重映射后的起始向量
+    pub const PIC_1_OFFSET: u8 = 32;
+    /// 从片重映射后的起始向量
+    pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+    /// 主片命令端口
+    pub const PIC_1_COMMAND: u16 = 0x20;
+    /// 从片命令端口
+    pub const PIC_2_COMMAND: u16 = 0xa0;
+    /// 两片 PIC 的 IRQ 线总数
+    pub const PIC_IRQ_COUNT: usize = 16;
+}
+
+/// ACPI 相关常量
+pub mod acpi {
+    /// 最多记录的 I/O APIC 数量
+    pub const MAX_IOAPICS: usize = 8;
+    /// 最多记录的中断源覆盖数量
+    pub const MAX_INTERRUPT_OVERRIDES: usize = 16;
+    /// 最多记录的 NMI 表项数量
+    pub const MAX_NMI_SOURCES: usize = 16;
+}
+
+/// APIC 相关常量
+pub mod apic {
+    /// 本地 APIC 伪中断向量
+    pub const LAPIC_SPURIOUS_VECTOR: u8 = 0xff;
+    /// 本地 APIC 定时器向量
+    pub const LOCAL_TIMER_VECTOR: u8 = 0xec;
+    /// 本地定时器中断频率（Hz）
+    pub const LOCAL_TIMER_HZ: u64 = 4;
+    /// 远程函数调用 IPI 向量
+    pub const CALL_FUNCTION_VECTOR: u8 = 0xf0;
+    /// I/O APIC 路由的 ISA IRQ 起始向量（与仍保持重映射的 8259 错开，便于识别其伪中断）
+    pub const IOAPIC_IRQ_BASE: u8 = 48;
+    /// ISA IRQ 数量
+    pub const ISA_IRQ_COUNT: usize = 16;
+}
+
+/// 动态中断向量相关常量
+pub mod vectors {
+    /// 动态分配区的起始向量（位于 I/O APIC 中断线之后）
+    pub const DYNAMIC_VECTOR_START: u8 = 0x50;
+    /// 动态分配区的结束向量（不含），更高的向量留给 IPI 与本地 APIC
+    pub const DYNAMIC_VECTOR_END: u8 = 0xe0;
+}
+
+/// PCI 相关常量
+pub mod pci {
+    /// 配置地址端口
+    pub const CONFIG_ADDRESS: u16 = 0xcf8;
+    /// 配置数据端口
+    pub const CONFIG_DATA: u16 = 0xcfc;
+    /// 最多记录的设备数量
+    pub const MAX_PCI_DEVICES: usize = 64;
+    /// 能力链表最多遍历的项数（防止链表成环）
+    pub const MAX_CAPABILITIES: usize = 48;
+}
+
+/// 通用中断相关常量
+pub mod irq {
+    /// 中断线数量（ISA IRQ 0–15 与 PCI GSI 16–23）
+    pub const NR_IRQ_LINES: usize = 24;
+    /// 每条中断线最多共享的处理函数数量
+    pub const MAX_SHARED_HANDLERS: usize = 4;
+    /// 无人处理检测的统计窗口（中断次数）
+    pub const STORM_WINDOW: u32 = 1000;
+    /// 窗口内无人处理的中断达到该数量时关闭中断线
+    pub const STORM_UNHANDLED_LIMIT: u32 = 990;
+    /// 连续伪中断达到该数量时关闭中断线
+    pub const SPURIOUS_STORM_LIMIT: u32 = 1000;
+}
+
+/// 延迟工作相关常量
+pub mod softirq {
+    /// 一次中断退出时软中断最多重新执行的轮数
+    pub const MAX_SOFTIRQ_RESTART: usize = 10;
+    /// 每个 CPU 的 tasklet 队列长度
+    pub const TASKLET_QUEUE_LEN: usize = 32;
+    /// 工作队列长度
+    pub const WORK_QUEUE_LEN: usize = 64;
+}
+
+/// 日志相关常量
+pub mod logging {
+    /// 中断上下文日志暂存区大小（字节）
+    pub const LOG_BUFFER_SIZE: usize = 8192;
+    /// 刷新暂存区时每次写出的字节数
+    pub const LOG_FLUSH_CHUNK: usize = 256;
+}
+
+/// CPU 识别相关常量
+pub mod cpu {
+    /// 最多记录的缓存描述数量
+    pub const MAX_CACHES: usize = 8;
+}
+
+/// FPU 相关常量
+pub mod fpu {
+    /// 每份 FPU 上下文预留的保存区大小（字节），足以容纳 x87/SSE/AVX/AVX-512 状态
+    pub const FPU_STATE_SIZE: usize = 4096;
+    /// FXSAVE 保存区大小（字节）
+    pub const FXSAVE_AREA_SIZE: usize = 512;
+    /// MXCSR 的默认值：屏蔽全部 SIMD 浮点异常，就近舍入
+    pub const MXCSR_DEFAULT: u32 = 0x1f80;
+}
+
+/// 锁依赖验证相关常量
+#[cfg(feature = "lockdep")]
+pub mod lockdep {
+    /// 最多跟踪的锁类数量
+    pub const MAX_LOCK_CLASSES: usize = 64;
+    /// 每个 CPU 最多同时持有的锁数量
+    pub const MAX_HELD_LOCKS: usize = 16;
+}
+
+/// 8253/8254 PIT 相关常量
+pub mod pit {
+    /// PIT 输入时钟频率（Hz）
+    pub const PIT_FREQUENCY: u32 = 1_193_182;
+    /// 通道 0 数据端口
+    pub const PIT_CHANNEL0: u16 = 0x40;
+    /// 通道 2 数据端口
+    pub const PIT_CHANNEL2: u16 = 0x42;
+    /// 模式/命令端口
+    pub const PIT_COMMAND: u16 = 0x43;
+    /// 系统控制端口 B（通道 2 门控与输出状态）
+    pub const SYSTEM_CONTROL_PORT_B: u16 = 0x61;
+    /// 默认节拍频率（Hz）
+    pub const DEFAULT_PIT_HZ: u64 = 100;
+    /// 可配置的最低节拍频率（Hz），受 16 位分频系数限制
+    pub const MIN_PIT_HZ: u64 = 19;
+    /// 可配置的最高节拍频率（Hz）
+    pub const MAX_PIT_HZ: u64 = 10_000;
+    /// 通道 2 单次延迟的最大时长（微秒）
+    pub const MAX_ONESHOT_US: u64 = 50_000;
+    /// 校准 TSC 与本地 APIC 定时器的测量时长（微秒）
+    pub const CALIBRATION_US: u64 = 10_000;
+}
+
+/// 时钟事件相关常量
+pub mod clockevent {
+    /// 动态节拍下空闲 CPU 最长的无中断时间（毫秒），保证看门狗等周期性检查仍能进行
+    pub const NOHZ_MAX_IDLE_MS: u64 = 1000;
+}
+
+/// CMOS 实时时钟相关常量
+pub mod rtc {
+    /// CMOS 索引端口
+    pub const CMOS_ADDRESS: u16 = 0x70;
+    /// CMOS 数据端口
+    pub const CMOS_DATA: u16 = 0x71;
+    /// 等待更新结束的最长时间（微秒），一次更新周期不超过约 2 毫秒
+    pub const RTC_UIP_TIMEOUT_US: u64 = 10_000;
+    /// 两次连续读数不一致时的最多重读次数
+    pub const RTC_READ_RETRIES: usize = 5;
+}
+
+/// HPET 相关常量
+pub mod hpet {
+    /// 寄存器块映射大小
+    pub const HPET_MMIO_SIZE: u64 = 0x400;
+    /// 规范允许的最大计数周期（飞秒，即 100 纳秒）
+    pub const MAX_HPET_PERIOD_FS: u64 = 100_000_000;
+}
+
+/// 看门狗相关常量
+pub mod watchdog {
+    /// 默认硬死锁阈值（秒），软死锁阈值为其两倍
+    pub const DEFAULT_WATCHDOG_THRESH: u64 = 10;
+    /// 回溯输出的最大栈帧数
+    pub const BACKTRACE_DEPTH: usize = 16;
+}
+
+/// 机器检查相关常量
+pub mod mce {
+    /// 轮询可纠正错误的间隔（秒）
+    pub const MCE_POLL_INTERVAL: u64 = 5;
+    /// 最多处理的 MCi 寄存器组数量
+    pub const MAX_MCE_BANKS: usize = 32;
+}
+
+/// TLB 相关常量
+pub mod tlb {
+    /// 一次击落最多逐页刷新的页数，超过后改为全部刷新
+    pub const TLB_BATCH_SIZE: usize = 32;
+}
+
+/// SMP 相关常量
+pub mod smp {
+    /// 支持的最大 CPU 数量
+    pub const MAX_CPUS: usize = 64;
+    /// 每个 AP 的内核栈大小（字节）
+    pub const AP_STACK_SIZE: usize = 4096 * 16;
+    /// 实模式启动代码所在页的最低物理地址（避开实模式中断向量表与 BIOS 数据区）
+    pub const TRAMPOLINE_MIN_ADDRESS: u64 = 0x8000;
+    /// 等待 AP 上线的最长时间（微秒）
+    pub const AP_STARTUP_TIMEOUT_US: u64 = 200_000;
+}
+
+/// 内核命令行相关常量
+pub mod cmdline {
+    /// 命令行最大长度（字节），超出部分会被截断
+    pub const CMDLINE_MAX_LEN: usize = 512;
+}
+
 /// VGA 显示相关常量
 pub mod vga {
     /// 字符宽度（像素）
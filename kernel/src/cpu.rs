@@ -0,0 +1,560 @@
+//! CPU 识别模块
+//! 通过 CPUID 枚举厂商、型号、品牌字符串、缓存拓扑与特性位，结果缓存供全内核查询
+
+use core::arch::x86_64::{CpuidResult, __cpuid_count};
+use core::fmt;
+use spin::Once;
+use crate::constants::cpu::MAX_CACHES;
+
+/// 启动时枚举并缓存的 CPU 信息
+static FEATURES: Once<CpuFeatures> = Once::new();
+
+/// CPUID 输出寄存器
+#[derive(Clone, Copy)]
+enum Reg {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// 可选 CPU 特性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// 本地 APIC
+    Apic,
+    /// 2MiB 大页（PSE）
+    Pse,
+    /// 全局页（PGE）
+    Pge,
+    /// 时间戳计数器
+    Tsc,
+    /// 机器检查异常（#MC）
+    Mce,
+    /// 机器检查架构（MCG/MCi 寄存器组）
+    Mca,
+    /// FXSAVE/FXRSTOR
+    Fxsr,
+    Sse,
+    Sse2,
+    Sse3,
+    Ssse3,
+    Sse41,
+    Sse42,
+    Avx,
+    Avx2,
+    Avx512f,
+    /// XSAVE/XRSTOR 及 XCR0
+    Xsave,
+    /// XSAVEOPT
+    XsaveOpt,
+    /// 不可执行位（EFER.NXE）
+    Nx,
+    /// 进程上下文标识符
+    Pcid,
+    /// INVPCID 指令
+    Invpcid,
+    /// 禁止内核执行用户页
+    Smep,
+    /// 禁止内核访问用户页
+    Smap,
+    /// 禁止用户态执行 SGDT/SIDT 等指令
+    Umip,
+    /// RDRAND 指令
+    Rdrand,
+    /// RDSEED 指令
+    Rdseed,
+    /// 本地 APIC 定时器的 TSC-deadline 模式
+    TscDeadline,
+    /// 不变 TSC（频率不随 P/C 状态变化）
+    InvariantTsc,
+    /// RDTSCP 指令
+    Rdtscp,
+    /// x2APIC 模式
+    X2apic,
+    /// 1GiB 大页
+    Gib1Pages,
+    /// 运行在虚拟机监控器之下
+    Hypervisor,
+}
+
+impl Feature {
+    /// 全部特性，按启动日志中的输出顺序排列（与枚举顺序相同）
+    pub const ALL: [Feature; 32] = [
+        Feature::Apic,
+        Feature::Pse,
+        Feature::Pge,
+        Feature::Tsc,
+        Feature::Mce,
+        Feature::Mca,
+        Feature::Fxsr,
+        Feature::Sse,
+        Feature::Sse2,
+        Feature::Sse3,
+        Feature::Ssse3,
+        Feature::Sse41,
+        Feature::Sse42,
+        Feature::Avx,
+        Feature::Avx2,
+        Feature::Avx512f,
+        Feature::Xsave,
+        Feature::XsaveOpt,
+        Feature::Nx,
+        Feature::Pcid,
+        Feature::Invpcid,
+        Feature::Smep,
+        Feature::Smap,
+        Feature::Umip,
+        Feature::Rdrand,
+        Feature::Rdseed,
+        Feature::TscDeadline,
+        Feature::InvariantTsc,
+        Feature::Rdtscp,
+        Feature::X2apic,
+        Feature::Gib1Pages,
+        Feature::Hypervisor,
+    ];
+
+    /// 与 /proc/cpuinfo 一致的特性名
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Apic => "apic",
+            Feature::Pse => "pse",
+            Feature::Pge => "pge",
+            Feature::Tsc => "tsc",
+            Feature::Mce => "mce",
+            Feature::Mca => "mca",
+            Feature::Fxsr => "fxsr",
+            Feature::Sse => "sse",
+            Feature::Sse2 => "sse2",
+            Feature::Sse3 => "pni",
+            Feature::Ssse3 => "ssse3",
+            Feature::Sse41 => "sse4_1",
+            Feature::Sse42 => "sse4_2",
+            Feature::Avx => "avx",
+            Feature::Avx2 => "avx2",
+            Feature::Avx512f => "avx512f",
+            Feature::Xsave => "xsave",
+            Feature::XsaveOpt => "xsaveopt",
+            Feature::Nx => "nx",
+            Feature::Pcid => "pcid",
+            Feature::Invpcid => "invpcid",
+            Feature::Smep => "smep",
+            Feature::Smap => "smap",
+            Feature::Umip => "umip",
+            Feature::Rdrand => "rdrand",
+            Feature::Rdseed => "rdseed",
+            Feature::TscDeadline => "tsc_deadline_timer",
+            Feature::InvariantTsc => "constant_tsc",
+            Feature::Rdtscp => "rdtscp",
+            Feature::X2apic => "x2apic",
+            Feature::Gib1Pages => "pdpe1gb",
+            Feature::Hypervisor => "hypervisor",
+        }
+    }
+
+    /// 特性位所在的 CPUID 叶、子叶、寄存器与位号
+    fn location(self) -> (u32, u32, Reg, u32) {
+        match self {
+            Feature::Pse => (1, 0, Reg::Edx, 3),
+            Feature::Tsc => (1, 0, Reg::Edx, 4),
+            Feature::Mce => (1, 0, Reg::Edx, 7),
+            Feature::Apic => (1, 0, Reg::Edx, 9),
+            Feature::Pge => (1, 0, Reg::Edx, 13),
+            Feature::Mca => (1, 0, Reg::Edx, 14),
+            Feature::Fxsr => (1, 0, Reg::Edx, 24),
+            Feature::Sse => (1, 0, Reg::Edx, 25),
+            Feature::Sse2 => (1, 0, Reg::Edx, 26),
+            Feature::Sse3 => (1, 0, Reg::Ecx, 0),
+            Feature::Ssse3 => (1, 0, Reg::Ecx, 9),
+            Feature::Pcid => (1, 0, Reg::Ecx, 17),
+            Feature::Sse41 => (1, 0, Reg::Ecx, 19),
+            Feature::Sse42 => (1, 0, Reg::Ecx, 20),
+            Feature::X2apic => (1, 0, Reg::Ecx, 21),
+            Feature::TscDeadline => (1, 0, Reg::Ecx, 24),
+            Feature::Xsave => (1, 0, Reg::Ecx, 26),
+            Feature::Avx => (1, 0, Reg::Ecx, 28),
+            Feature::Rdrand => (1, 0, Reg::Ecx, 30),
+            Feature::Hypervisor => (1, 0, Reg::Ecx, 31),
+            Feature::Avx2 => (7, 0, Reg::Ebx, 5),
+            Feature::Smep => (7, 0, Reg::Ebx, 7),
+            Feature::Invpcid => (7, 0, Reg::Ebx, 10),
+            Feature::Avx512f => (7, 0, Reg::Ebx, 16),
+            Feature::Rdseed => (7, 0, Reg::Ebx, 18),
+            Feature::Smap => (7, 0, Reg::Ebx, 20),
+            Feature::Umip => (7, 0, Reg::Ecx, 2),
+            Feature::XsaveOpt => (0xd, 1, Reg::Eax, 0),
+            Feature::Nx => (0x8000_0001, 0, Reg::Edx, 20),
+            Feature::Gib1Pages => (0x8000_0001, 0, Reg::Edx, 26),
+            Feature::Rdtscp => (0x8000_0001, 0, Reg::Edx, 27),
+            Feature::InvariantTsc => (0x8000_0007, 0, Reg::Edx, 8),
+        }
+    }
+}
+
+/// 缓存类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+/// 一级缓存的描述
+#[derive(Debug, Clone, Copy)]
+pub struct CacheInfo {
+    /// 缓存级别（1 起）
+    pub level: u8,
+    /// 缓存类型
+    pub kind: CacheKind,
+    /// 容量（字节）
+    pub size: u64,
+    /// 相联度（0 表示未知或全相联）
+    pub ways: u32,
+    /// 缓存行大小（字节）
+    pub line_size: u32,
+    /// 共享该缓存的逻辑处理器数量上限（0 表示未知）
+    pub shared_by: u32,
+}
+
+impl fmt::Display for CacheInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = match self.kind {
+            CacheKind::Data => "d",
+            CacheKind::Instruction => "i",
+            CacheKind::Unified => "",
+        };
+        write!(f, "L{}{} ", self.level, suffix)?;
+        if self.size >= 1 << 20 && self.size % (1 << 20) == 0 {
+            write!(f, "{}M", self.size >> 20)?;
+        } else {
+            write!(f, "{}K", self.size >> 10)?;
+        }
+        if self.ways != 0 {
+            write!(f, " {}-way", self.ways)?;
+        }
+        write!(f, " {}B", self.line_size)?;
+        if self.shared_by > 1 {
+            write!(f, " x{}", self.shared_by)?;
+        }
+        Ok(())
+    }
+}
+
+/// 架构性能监控能力（CPUID 叶 0AH）
+#[derive(Debug, Clone, Copy)]
+pub struct PerfMon {
+    /// 架构性能监控版本
+    pub version: u8,
+    /// 每个逻辑处理器的通用计数器数量
+    pub counters: u8,
+    /// 通用计数器位宽
+    pub width: u8,
+    /// 支持“未停机核心周期”事件
+    pub cycles_event: bool,
+}
+
+/// 缓存的 CPU 识别结果
+pub struct CpuFeatures {
+    /// 厂商字符串（如 GenuineIntel、AuthenticAMD）
+    vendor: [u8; 12],
+    /// 品牌字符串
+    brand: [u8; 48],
+    /// 虚拟机监控器厂商字符串（CPUID 0x40000000）
+    hypervisor: [u8; 12],
+    /// 族（已合并扩展族）
+    pub family: u32,
+    /// 型号（已合并扩展型号）
+    pub model: u32,
+    /// 步进
+    pub stepping: u32,
+    /// 最大基本叶
+    pub max_leaf: u32,
+    /// 最大扩展叶
+    pub max_extended_leaf: u32,
+    /// 最大虚拟机监控器叶（不在虚拟机中时为 0）
+    pub max_hypervisor_leaf: u32,
+    /// 缓存拓扑
+    caches: [Option<CacheInfo>; MAX_CACHES],
+    /// 架构性能监控（不支持时为 None）
+    pub perfmon: Option<PerfMon>,
+    /// 特性位图（以 Feature 的序号为位号）
+    flags: u64,
+}
+
+/// 执行 CPUID
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    unsafe { __cpuid_count(leaf, subleaf) }
+}
+
+/// 把寄存器按小端字节序写入字符串缓冲区
+fn put_regs(buf: &mut [u8], regs: &[u32]) {
+    for (chunk, reg) in buf.chunks_mut(4).zip(regs) {
+        chunk.copy_from_slice(&reg.to_le_bytes());
+    }
+}
+
+/// 去掉首尾空白与填充的 NUL
+fn trimmed(bytes: &[u8]) -> &str {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..end]).unwrap_or("").trim()
+}
+
+impl CpuFeatures {
+    /// 在当前 CPU 上执行 CPUID 枚举
+    fn detect() -> Self {
+        let leaf0 = cpuid(0, 0);
+        let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+        let mut cpu = CpuFeatures {
+            vendor: [0; 12],
+            brand: [0; 48],
+            hypervisor: [0; 12],
+            family: 0,
+            model: 0,
+            stepping: 0,
+            max_leaf: leaf0.eax,
+            max_extended_leaf,
+            max_hypervisor_leaf: 0,
+            caches: [None; MAX_CACHES],
+            perfmon: None,
+            flags: 0,
+        };
+        put_regs(&mut cpu.vendor, &[leaf0.ebx, leaf0.edx, leaf0.ecx]);
+
+        let signature = cpuid(1, 0).eax;
+        let base_family = (signature >> 8) & 0xf;
+        let base_model = (signature >> 4) & 0xf;
+        cpu.stepping = signature & 0xf;
+        cpu.family = base_family;
+        cpu.model = base_model;
+        if base_family == 0xf {
+            cpu.family += (signature >> 20) & 0xff;
+        }
+        if base_family == 0x6 || base_family == 0xf {
+            cpu.model += ((signature >> 16) & 0xf) << 4;
+        }
+
+        if max_extended_leaf >= 0x8000_0004 {
+            for (i, leaf) in (0x8000_0002..=0x8000_0004).enumerate() {
+                let r = cpuid(leaf, 0);
+                put_regs(&mut cpu.brand[i * 16..(i + 1) * 16], &[r.eax, r.ebx, r.ecx, r.edx]);
+            }
+        }
+
+        for feature in Feature::ALL {
+            let (leaf, subleaf, reg, shift) = feature.location();
+            let available = if leaf & 0x8000_0000 != 0 {
+                leaf <= max_extended_leaf
+            } else {
+                leaf <= cpu.max_leaf
+            };
+            if !available {
+                continue;
+            }
+            let r = cpuid(leaf, subleaf);
+            let value = match reg {
+                Reg::Eax => r.eax,
+                Reg::Ebx => r.ebx,
+                Reg::Ecx => r.ecx,
+                Reg::Edx => r.edx,
+            };
+            // 以枚举值为位号，与 `has` 一致
+            if value & (1 << shift) != 0 {
+                cpu.flags |= 1 << feature as u32;
+            }
+        }
+
+        if cpu.has(Feature::Hypervisor) {
+            let r = cpuid(0x4000_0000, 0);
+            cpu.max_hypervisor_leaf = r.eax;
+            put_regs(&mut cpu.hypervisor, &[r.ebx, r.ecx, r.edx]);
+        }
+
+        if cpu.max_leaf >= 0xa {
+            let r = cpuid(0xa, 0);
+            let version = (r.eax & 0xff) as u8;
+            // EBX 中置位表示对应事件不可用，EAX[31:24] 为 EBX 的有效位数
+            let events = (r.eax >> 24) & 0xff;
+            if version > 0 {
+                cpu.perfmon = Some(PerfMon {
+                    version,
+                    counters: ((r.eax >> 8) & 0xff) as u8,
+                    width: ((r.eax >> 16) & 0xff) as u8,
+                    cycles_event: events > 0 && r.ebx & 1 == 0,
+                });
+            }
+        }
+
+        cpu.detect_caches();
+        cpu
+    }
+
+    /// 枚举缓存拓扑：优先使用确定性缓存参数叶（Intel 叶 4 / AMD 叶 0x8000001D），
+    /// 否则退回 AMD 传统的 0x80000005/0x80000006 叶
+    fn detect_caches(&mut self) {
+        let amd_topology = self.max_extended_leaf >= 0x8000_001d
+            && cpuid(0x8000_0001, 0).ecx & (1 << 22) != 0;
+        if self.vendor() == "GenuineIntel" && self.max_leaf >= 4 {
+            self.deterministic_caches(4);
+        } else if amd_topology {
+            self.deterministic_caches(0x8000_001d);
+        } else if self.max_extended_leaf >= 0x8000_0006 {
+            self.legacy_amd_caches();
+        }
+    }
+
+    /// 追加一项缓存描述，超出容量时丢弃
+    fn push_cache(&mut self, cache: CacheInfo) {
+        if let Some(slot) = self.caches.iter_mut().find(|c| c.is_none()) {
+            *slot = Some(cache);
+        }
+    }
+
+    /// 按确定性缓存参数叶逐个子叶枚举，直到类型为空
+    fn deterministic_caches(&mut self, leaf: u32) {
+        for subleaf in 0..MAX_CACHES as u32 {
+            let r = cpuid(leaf, subleaf);
+            let kind = match r.eax & 0x1f {
+                1 => CacheKind::Data,
+                2 => CacheKind::Instruction,
+                3 => CacheKind::Unified,
+                _ => break,
+            };
+            let line_size = (r.ebx & 0xfff) + 1;
+            let partitions = ((r.ebx >> 12) & 0x3ff) + 1;
+            let ways = (r.ebx >> 22) + 1;
+            let sets = r.ecx as u64 + 1;
+            let fully_associative = r.eax & (1 << 9) != 0;
+            self.push_cache(CacheInfo {
+                level: ((r.eax >> 5) & 0x7) as u8,
+                kind,
+                size: ways as u64 * partitions as u64 * line_size as u64 * sets,
+                ways: if fully_associative { 0 } else { ways },
+                line_size,
+                shared_by: ((r.eax >> 14) & 0xfff) + 1,
+            });
+        }
+    }
+
+    /// AMD 传统缓存叶：L1 相联度为实际路数，L2/L3 为编码值，此处不解码
+    fn legacy_amd_caches(&mut self) {
+        let l1 = cpuid(0x8000_0005, 0);
+        for (reg, kind) in [(l1.ecx, CacheKind::Data), (l1.edx, CacheKind::Instruction)] {
+            if reg >> 24 != 0 {
+                self.push_cache(CacheInfo {
+                    level: 1,
+                    kind,
+                    size: ((reg >> 24) as u64) << 10,
+                    ways: (reg >> 16) & 0xff,
+                    line_size: reg & 0xff,
+                    shared_by: 0,
+                });
+            }
+        }
+        let l2 = cpuid(0x8000_0006, 0);
+        if l2.ecx >> 16 != 0 {
+            self.push_cache(CacheInfo {
+                level: 2,
+                kind: CacheKind::Unified,
+                size: ((l2.ecx >> 16) as u64) << 10,
+                ways: 0,
+                line_size: l2.ecx & 0xff,
+                shared_by: 0,
+            });
+        }
+        if l2.edx >> 18 != 0 {
+            self.push_cache(CacheInfo {
+                level: 3,
+                kind: CacheKind::Unified,
+                size: ((l2.edx >> 18) as u64) << 19,
+                ways: 0,
+                line_size: l2.edx & 0xff,
+                shared_by: 0,
+            });
+        }
+    }
+
+    /// 是否支持某项特性
+    pub fn has(&self, feature: Feature) -> bool {
+        self.flags & (1 << feature as u32) != 0
+    }
+
+    /// 厂商字符串
+    pub fn vendor(&self) -> &str {
+        trimmed(&self.vendor)
+    }
+
+    /// 品牌字符串（CPU 不提供时为空）
+    pub fn brand(&self) -> &str {
+        trimmed(&self.brand)
+    }
+
+    /// 虚拟机监控器厂商字符串（不在虚拟机中或未提供时为 None）
+    pub fn hypervisor(&self) -> Option<&str> {
+        Some(trimmed(&self.hypervisor)).filter(|s| !s.is_empty())
+    }
+
+    /// 缓存拓扑
+    pub fn caches(&self) -> impl Iterator<Item = &CacheInfo> {
+        self.caches.iter().flatten()
+    }
+
+    /// 已支持特性名的列表，以空格分隔
+    pub fn flags(&self) -> FeatureList<'_> {
+        FeatureList(self)
+    }
+}
+
+/// 特性名列表的显示包装
+pub struct FeatureList<'a>(&'a CpuFeatures);
+
+impl fmt::Display for FeatureList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for feature in Feature::ALL.iter().filter(|&&feature| self.0.has(feature)) {
+            if !first {
+                f.write_str(" ")?;
+            }
+            f.write_str(feature.name())?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// 缓存的 CPU 识别结果（首次调用时枚举）
+pub fn features() -> &'static CpuFeatures {
+    FEATURES.call_once(CpuFeatures::detect)
+}
+
+/// 当前系统是否支持某项特性
+pub fn has(feature: Feature) -> bool {
+    features().has(feature)
+}
+
+/// 在 BSP 上枚举 CPU 并输出到启动日志
+pub fn init() {
+    let cpu = features();
+    log::info!(
+        "CPU: {} family {:#x} model {:#x} stepping {} \"{}\"",
+        cpu.vendor(),
+        cpu.family,
+        cpu.model,
+        cpu.stepping,
+        cpu.brand()
+    );
+    if let Some(hypervisor) = cpu.hypervisor() {
+        log::info!("CPU: running under hypervisor \"{hypervisor}\"");
+    }
+    for cache in cpu.caches() {
+        log::info!("CPU: {cache}");
+    }
+    log::info!("CPU: flags {}", cpu.flags());
+}
+
+#[test_case]
+fn test_feature_all_in_enum_order() {
+    for (i, feature) in Feature::ALL.iter().enumerate() {
+        assert_eq!(*feature as usize, i, "{feature:?}");
+    }
+}
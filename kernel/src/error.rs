@@ -16,6 +16,12 @@ pub enum KernelError {
     InvalidParameter,
     /// 硬件错误
     HardwareError,
+    /// 页表操作失败
+    PagingFailed,
+    /// ACPI 表缺失或无效
+    AcpiError,
+    /// 没有可用的资源（如处理函数槽位、中断向量）
+    NoResources,
 }
 
 impl fmt::Display for KernelError {
@@ -26,6 +32,9 @@ impl fmt::Display for KernelError {
             KernelError::WriteFailed => write!(f, "Write operation failed"),
             KernelError::InvalidParameter => write!(f, "Invalid parameter"),
             KernelError::HardwareError => write!(f, "Hardware error"),
+            KernelError::PagingFailed => write!(f, "Page table operation failed"),
+            KernelError::AcpiError => write!(f, "ACPI table missing or invalid"),
+            KernelError::NoResources => write!(f, "No free resources"),
         }
     }
 }
@@ -0,0 +1,304 @@
+//! FPU/SSE/AVX 上下文管理模块
+//! 启用 OSFXSR/OSXSAVE，按 CPUID 给出的大小用 XSAVE/XSAVEOPT（或 FXSAVE）保存扩展状态。
+//!
+//! 内核以软浮点编译，普通内核代码不会触碰 x87/SSE 寄存器；
+//! 需要 SIMD 的代码必须放在 `kernel_fpu_begin`/`kernel_fpu_end` 之间。
+//! 采用积极（eager）切换：每个任务持有完整的 `FpuState`，切换时无条件保存与恢复，
+//! 不依赖 CR0.TS 与 #NM 的惰性切换。
+//!
+//! 目前的限制：
+//! - 目标规格关闭了 SSE（`-mmx,-sse,+soft-float`），编译器在区段内也不会生成 SIMD 指令，
+//!   区段只保护手写的内联汇编
+//! - 还没有调度器与任务切换路径，`FpuState::new` 与 `switch` 尚无调用者，
+//!   由将来的任务切换代码接入
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use x86_64::registers::xcontrol::{XCr0, XCr0Flags};
+use crate::constants::fpu::{FPU_STATE_SIZE, FXSAVE_AREA_SIZE, MXCSR_DEFAULT};
+use crate::cpu::{self, Feature};
+use crate::per_cpu;
+use crate::sync::{pop_irq_off, push_irq_off};
+
+/// 保存指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum SaveMode {
+    Fxsave,
+    Xsave,
+    XsaveOpt,
+}
+
+/// 保存区中 XMM0 的偏移（FXSAVE 与 XSAVE 标准格式的传统区相同），每个 XMM 寄存器 16 字节
+const XMM0_OFFSET: usize = 160;
+/// XSAVE 头部 XSTATE_BV 的偏移：置位的分量从保存区载入，清零的分量恢复为初始值
+const XSTATE_BV_OFFSET: usize = 512;
+/// SSE 状态分量
+const XSTATE_SSE: u64 = 1 << 1;
+
+/// 当前使用的保存指令
+static MODE: AtomicU8 = AtomicU8::new(SaveMode::Fxsave as u8);
+
+/// 写入 XCR0 的状态分量位图
+static XFEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// 保存区的实际大小（字节）
+static STATE_SIZE: AtomicUsize = AtomicUsize::new(FXSAVE_AREA_SIZE);
+
+/// 干净的初始状态镜像（FNINIT 后、MXCSR 为默认值）
+static INIT_STATE: Once<FpuState> = Once::new();
+
+per_cpu! {
+    // kernel_fpu_begin 的嵌套深度
+    static DEPTH: AtomicUsize = AtomicUsize::new(0);
+    // 进入内核 FPU 区段前被打断的寄存器状态
+    static SAVED: Mutex<FpuState> = Mutex::new(FpuState::zeroed());
+}
+
+fn mode() -> SaveMode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => SaveMode::Xsave,
+        2 => SaveMode::XsaveOpt,
+        _ => SaveMode::Fxsave,
+    }
+}
+
+/// 一份完整的 FPU/SSE/AVX 寄存器状态（XSAVE 要求 64 字节对齐）
+#[repr(C, align(64))]
+#[derive(Clone)]
+pub struct FpuState {
+    area: [u8; FPU_STATE_SIZE],
+}
+
+impl FpuState {
+    const fn zeroed() -> Self {
+        Self { area: [0; FPU_STATE_SIZE] }
+    }
+
+    /// 新任务的初始状态：所有分量处于初始值，MXCSR 屏蔽全部异常
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        INIT_STATE.r#try().cloned().unwrap_or_else(Self::zeroed)
+    }
+
+    /// 保存区中的 XMM 寄存器 `n`
+    fn xmm(&self, n: usize) -> [u8; 16] {
+        let offset = XMM0_OFFSET + 16 * n;
+        self.area[offset..offset + 16].try_into().unwrap()
+    }
+
+    /// 修改保存区中的 XMM 寄存器 `n`，恢复时生效
+    fn set_xmm(&mut self, n: usize, value: [u8; 16]) {
+        let offset = XMM0_OFFSET + 16 * n;
+        self.area[offset..offset + 16].copy_from_slice(&value);
+        if mode() != SaveMode::Fxsave {
+            let bv = &mut self.area[XSTATE_BV_OFFSET..XSTATE_BV_OFFSET + 8];
+            let value = u64::from_le_bytes(bv.try_into().unwrap()) | XSTATE_SSE;
+            bv.copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// 把当前 CPU 的寄存器状态保存到本结构
+    pub fn save(&mut self) {
+        let (low, high) = xfeature_mask();
+        let area = self.area.as_mut_ptr();
+        unsafe {
+            match mode() {
+                SaveMode::XsaveOpt => {
+                    asm!("xsaveopt64 [{}]", in(reg) area, in("eax") low, in("edx") high, options(nostack, preserves_flags))
+                }
+                SaveMode::Xsave => {
+                    asm!("xsave64 [{}]", in(reg) area, in("eax") low, in("edx") high, options(nostack, preserves_flags))
+                }
+                SaveMode::Fxsave => asm!("fxsave64 [{}]", in(reg) area, options(nostack, preserves_flags)),
+            }
+        }
+    }
+
+    /// 从本结构恢复当前 CPU 的寄存器状态
+    pub fn restore(&self) {
+        let (low, high) = xfeature_mask();
+        let area = self.area.as_ptr();
+        unsafe {
+            match mode() {
+                SaveMode::Xsave | SaveMode::XsaveOpt => {
+                    asm!("xrstor64 [{}]", in(reg) area, in("eax") low, in("edx") high, options(nostack, preserves_flags, readonly))
+                }
+                SaveMode::Fxsave => asm!("fxrstor64 [{}]", in(reg) area, options(nostack, preserves_flags, readonly)),
+            }
+        }
+    }
+}
+
+/// 任务切换时的 FPU 上下文切换：保存 `prev`，载入 `next`
+#[allow(dead_code)]
+pub fn switch(prev: &mut FpuState, next: &FpuState) {
+    prev.save();
+    next.restore();
+}
+
+/// XSAVE/XRSTOR 的请求位图（EDX:EAX）
+fn xfeature_mask() -> (u32, u32) {
+    let mask = XFEATURES.load(Ordering::Relaxed);
+    (mask as u32, (mask >> 32) as u32)
+}
+
+/// 选择要启用的 XSAVE 状态分量，受 CPUID.(EAX=0DH,ECX=0) 支持位图与保存区大小限制
+fn select_xfeatures() -> (u64, usize) {
+    let leaf = unsafe { core::arch::x86_64::__cpuid_count(0xd, 0) };
+    let supported = leaf.eax as u64 | (leaf.edx as u64) << 32;
+
+    let mut wanted = XCr0Flags::X87 | XCr0Flags::SSE;
+    if cpu::has(Feature::Avx) {
+        wanted |= XCr0Flags::AVX;
+        let avx512 = XCr0Flags::OPMASK | XCr0Flags::ZMM_HI256 | XCr0Flags::HI16_ZMM;
+        if cpu::has(Feature::Avx512f) && supported & avx512.bits() == avx512.bits() {
+            wanted |= avx512;
+        }
+    }
+    let mut mask = wanted.bits() & supported;
+    write_xcr0(mask);
+    // EBX 给出当前 XCR0 所启用分量需要的保存区大小
+    let mut size = unsafe { core::arch::x86_64::__cpuid_count(0xd, 0) }.ebx as usize;
+    if size > FPU_STATE_SIZE {
+        log::warn!("FPU: XSAVE area of {size} bytes too large, AVX-512 state disabled");
+        mask &= (XCr0Flags::X87 | XCr0Flags::SSE | XCr0Flags::AVX).bits();
+        write_xcr0(mask);
+        size = unsafe { core::arch::x86_64::__cpuid_count(0xd, 0) }.ebx as usize;
+    }
+    (mask, size)
+}
+
+fn write_xcr0(mask: u64) {
+    unsafe { XCr0::write_raw(mask) };
+}
+
+/// 复位 x87/SSE 寄存器为初始值
+fn reset_registers() {
+    unsafe {
+        asm!("fninit", options(nomem, nostack));
+        asm!("ldmxcsr [{}]", in(reg) &MXCSR_DEFAULT, options(nostack, readonly));
+    }
+}
+
+/// 在 BSP 上启用 FPU/SSE 与 XSAVE（AP 启动时沿用 BSP 的 CR0/CR4，再调用 `init_ap`）
+pub fn init() {
+    if !cpu::has(Feature::Fxsr) || !cpu::has(Feature::Sse) {
+        log::warn!("FPU: CPU lacks FXSR/SSE, SIMD state management disabled");
+        return;
+    }
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR | Cr0Flags::NUMERIC_ERROR);
+        });
+        Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+    }
+
+    if cpu::has(Feature::Xsave) {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::OSXSAVE)) };
+        let (mask, size) = select_xfeatures();
+        XFEATURES.store(mask, Ordering::Relaxed);
+        STATE_SIZE.store(size, Ordering::Relaxed);
+        let mode = if cpu::has(Feature::XsaveOpt) { SaveMode::XsaveOpt } else { SaveMode::Xsave };
+        MODE.store(mode as u8, Ordering::Relaxed);
+    }
+
+    reset_registers();
+    INIT_STATE.call_once(|| {
+        let mut state = FpuState::zeroed();
+        state.save();
+        state
+    });
+    log::info!(
+        "FPU: {:?}, state components {:#x}, {} bytes per context",
+        mode(),
+        XFEATURES.load(Ordering::Relaxed),
+        STATE_SIZE.load(Ordering::Relaxed)
+    );
+}
+
+/// 在 AP 上设置 XCR0（每个 CPU 各有一份）并复位寄存器
+pub fn init_ap() {
+    if mode() != SaveMode::Fxsave {
+        write_xcr0(XFEATURES.load(Ordering::Relaxed));
+    }
+    if INIT_STATE.r#try().is_some() {
+        reset_registers();
+    }
+}
+
+/// 当前 CPU 能否进入内核 FPU 区段
+pub fn kernel_fpu_usable() -> bool {
+    INIT_STATE.r#try().is_some()
+}
+
+/// 开始一段使用 SIMD 的内核代码
+///
+/// 关闭本地中断，保存被打断的寄存器状态并载入干净的初始状态；可以嵌套。
+/// 区段内不得睡眠，应尽量短小。
+pub fn kernel_fpu_begin() {
+    let Some(init) = INIT_STATE.r#try() else {
+        panic!("kernel_fpu_begin before fpu::init");
+    };
+    push_irq_off();
+    if DEPTH.get().fetch_add(1, Ordering::Relaxed) == 0 {
+        SAVED.get().lock().save();
+        init.restore();
+    }
+}
+
+/// 结束内核 FPU 区段，恢复进入前的寄存器状态与中断开关
+pub fn kernel_fpu_end() {
+    let depth = DEPTH.get().fetch_sub(1, Ordering::Relaxed);
+    assert!(depth > 0, "kernel_fpu_end without kernel_fpu_begin");
+    if depth == 1 {
+        SAVED.get().lock().restore();
+    }
+    pop_irq_off();
+}
+
+/// 自检：在内核 FPU 区段中做一次 SSE2 向量加法，并确认区段外的 XMM0 被原样恢复
+///
+/// 区段外不能直接写 XMM 寄存器，标记经保存区载入与取回。
+pub fn self_test() -> bool {
+    let Some(init) = INIT_STATE.r#try() else {
+        return false;
+    };
+    let a: [u32; 4] = [1, 2, 3, 4];
+    let b: [u32; 4] = [10, 20, 30, 40];
+    let marker = [0xa5u8; 16];
+    let mut sum = [0u32; 4];
+
+    // 在区段外的 XMM0 中放入标记，模拟被打断任务的寄存器内容
+    let mut outer = init.clone();
+    outer.set_xmm(0, marker);
+    outer.restore();
+    kernel_fpu_begin();
+    unsafe {
+        asm!(
+            "movdqu xmm0, [{a}]",
+            "movdqu xmm1, [{b}]",
+            "paddd xmm0, xmm1",
+            "movdqu [{out}], xmm0",
+            a = in(reg) &a,
+            b = in(reg) &b,
+            out = in(reg) &mut sum,
+            options(nostack),
+        );
+    }
+    kernel_fpu_end();
+    outer.save();
+    let after = outer.xmm(0);
+    init.restore();
+
+    let ok = sum == [11, 22, 33, 44] && after == marker;
+    if ok {
+        log::info!("FPU self-test: SIMD section ran and outer XMM state was preserved");
+    } else {
+        log::error!("FPU self-test failed: sum {sum:?}, restored xmm0 {after:x?}");
+    }
+    ok
+}
@@ -0,0 +1,97 @@
+//! 全局描述符表模块
+//! 建立内核自己的 GDT 与 TSS，并为关键异常提供独立的中断栈（IST）
+
+use spin::Once;
+use x86_64::instructions::segmentation::{Segment, CS, DS, ES, SS};
+use x86_64::instructions::tables::load_tss;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+use crate::constants::gdt::*;
+use crate::constants::smp::MAX_CPUS;
+
+/// 中断栈（16 字节对齐）
+#[repr(C, align(16))]
+struct Stack([u8; IST_STACK_SIZE]);
+
+/// 双重错误、NMI、机器检查各自独立的栈
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+static mut NMI_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+static mut MACHINE_CHECK_STACK: Stack = Stack([0; IST_STACK_SIZE]);
+
+/// 段选择子
+#[derive(Debug, Clone, Copy)]
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+/// 每个 CPU 各自的 TSS 与 GDT（TSS 描述符不能在 CPU 之间共享）
+static TSS: [Once<TaskStateSegment>; MAX_CPUS] = [const { Once::new() }; MAX_CPUS];
+static GDT: [Once<(GlobalDescriptorTable, Selectors)>; MAX_CPUS] = [const { Once::new() }; MAX_CPUS];
+
+/// 获取栈顶地址（栈向下增长）
+fn stack_top(stack: *const Stack) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + IST_STACK_SIZE
+}
+
+/// 初始化并加载 BSP 的 GDT 与 TSS
+pub fn init() {
+    let selectors = load(
+        0,
+        [
+            stack_top(core::ptr::addr_of!(DOUBLE_FAULT_STACK)),
+            stack_top(core::ptr::addr_of!(NMI_STACK)),
+            stack_top(core::ptr::addr_of!(MACHINE_CHECK_STACK)),
+        ],
+    );
+
+    log::info!(
+        "GDT loaded: kernel CS {:#x} SS {:#x}, user CS {:#x} SS {:#x}, TSS {:#x}",
+        selectors.kernel_code.0,
+        selectors.kernel_data.0,
+        selectors.user_code.0,
+        selectors.user_data.0,
+        selectors.tss.0
+    );
+}
+
+/// 初始化并加载 AP 的 GDT 与 TSS，`ist` 为按 IST 索引排列的栈顶地址
+pub fn init_ap(cpu: usize, ist: [VirtAddr; IST_STACK_COUNT]) {
+    load(cpu, ist);
+}
+
+/// 建立指定 CPU 的 GDT 与 TSS 并在当前 CPU 上加载
+fn load(cpu: usize, ist: [VirtAddr; IST_STACK_COUNT]) -> Selectors {
+    let tss = TSS[cpu].call_once(|| {
+        let mut tss = TaskStateSegment::new();
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = ist[DOUBLE_FAULT_IST_INDEX as usize];
+        tss.interrupt_stack_table[NMI_IST_INDEX as usize] = ist[NMI_IST_INDEX as usize];
+        tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] = ist[MACHINE_CHECK_IST_INDEX as usize];
+        tss
+    });
+
+    let (gdt, selectors) = GDT[cpu].call_once(|| {
+        let mut gdt = GlobalDescriptorTable::new();
+        // 用户数据段必须位于用户代码段之前（SYSRET 的要求）
+        let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code = gdt.add_entry(Descriptor::user_code_segment());
+        let tss = gdt.add_entry(Descriptor::tss_segment(tss));
+        (gdt, Selectors { kernel_code, kernel_data, user_data, user_code, tss })
+    });
+
+    gdt.load();
+    unsafe {
+        CS::set_reg(selectors.kernel_code);
+        SS::set_reg(selectors.kernel_data);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
+    }
+    *selectors
+}
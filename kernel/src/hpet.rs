@@ -0,0 +1,275 @@
+//! 高精度事件定时器（HPET）模块
+//! 按 ACPI HPET 表映射寄存器块，启动主计数器作为高精度时钟源；
+//! 在支持传统替换路由时：
+//! - 比较器 0 以周期模式接管 IRQ0 上的全局节拍（频率与 PIT 节拍相同，PIT 通道 2 仍用于校准）
+//! - 比较器 1 经 IRQ8 作为单次/周期时钟事件设备，供本地 APIC 定时器不可用时使用
+//!
+//! 命令行参数 `hpet=disable` 关闭 HPET。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::clockevent::{ClockEvent, EventMode};
+use crate::constants::hpet::*;
+use crate::error::{KernelResult, KernelError};
+use crate::irq::{self, IrqReturn};
+use crate::sync::RwLock;
+
+/// 寄存器偏移
+const REG_CAPABILITIES: u64 = 0x000;
+const REG_CONFIG: u64 = 0x010;
+const REG_MAIN_COUNTER: u64 = 0x0f0;
+
+/// 比较器 `n` 的配置与比较值寄存器
+const fn timer_config(n: u8) -> u64 {
+    0x100 + 0x20 * n as u64
+}
+
+const fn timer_comparator(n: u8) -> u64 {
+    0x108 + 0x20 * n as u64
+}
+
+/// GCAP_ID：比较器数量减一、64 位计数器、支持传统替换路由、计数周期（飞秒）
+const CAP_NUM_TIMERS_SHIFT: u64 = 8;
+const CAP_COUNT_SIZE_64: u64 = 1 << 13;
+const CAP_LEGACY_ROUTE: u64 = 1 << 15;
+const CAP_PERIOD_SHIFT: u64 = 32;
+
+/// GEN_CONF：启动主计数器、传统替换路由
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY: u64 = 1 << 1;
+
+/// Tn_CONF：中断使能、周期模式、支持周期模式、允许直接设置周期累加值、强制 32 位
+const TN_INT_ENABLE: u64 = 1 << 2;
+const TN_PERIODIC: u64 = 1 << 3;
+const TN_PERIODIC_CAP: u64 = 1 << 4;
+const TN_VAL_SET: u64 = 1 << 6;
+const TN_32BIT: u64 = 1 << 8;
+
+/// 传统替换路由下比较器 0、1 对应的 ISA IRQ
+const TICK_TIMER: u8 = 0;
+const EVENT_TIMER: u8 = 1;
+const EVENT_IRQ: u8 = 8;
+
+/// 飞秒每秒
+const FS_PER_SEC: u64 = 1_000_000_000_000_000;
+
+/// 寄存器块虚拟地址（0 表示 HPET 不可用）
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+/// 计数周期（飞秒）
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+
+/// 主计数器为 64 位
+static COUNTER_64: AtomicBool = AtomicBool::new(false);
+
+/// 比较器 0/1 已经以传统替换方式接管 IRQ0/IRQ8
+static LEGACY: AtomicBool = AtomicBool::new(false);
+
+/// 周期模式下的最小计数间隔（来自 ACPI 表）
+static MIN_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// 事件到来时调用的函数
+static EVENT_HANDLER: RwLock<Option<fn()>> = RwLock::new(None);
+
+fn read(reg: u64) -> u64 {
+    unsafe { core::ptr::read_volatile((BASE.load(Ordering::Relaxed) + reg) as *const u64) }
+}
+
+fn write(reg: u64, value: u64) {
+    unsafe { core::ptr::write_volatile((BASE.load(Ordering::Relaxed) + reg) as *mut u64, value) };
+}
+
+/// 按 ACPI 表初始化 HPET（在 `pit::init` 之后、开中断之前调用）
+pub fn init() {
+    if crate::cmdline::value("hpet") == Some("disable") {
+        log::info!("HPET: disabled (hpet=disable)");
+        return;
+    }
+    let Some(info) = crate::acpi::hpet() else {
+        log::info!("HPET: not present");
+        return;
+    };
+    match setup(info.address, info.min_tick as u64) {
+        Ok(()) => {}
+        Err(e) => {
+            BASE.store(0, Ordering::Relaxed);
+            log::warn!("HPET: unusable ({e})");
+        }
+    }
+}
+
+fn setup(address: u64, min_tick: u64) -> KernelResult<()> {
+    let base = crate::memory::map_mmio(address, HPET_MMIO_SIZE)?;
+    BASE.store(base.as_u64(), Ordering::Relaxed);
+    let caps = read(REG_CAPABILITIES);
+    let period = caps >> CAP_PERIOD_SHIFT;
+    if period == 0 || period > MAX_HPET_PERIOD_FS {
+        return Err(KernelError::HardwareError);
+    }
+    let timers = ((caps >> CAP_NUM_TIMERS_SHIFT) & 0x1f) as u8 + 1;
+    PERIOD_FS.store(period, Ordering::Relaxed);
+    COUNTER_64.store(caps & CAP_COUNT_SIZE_64 != 0, Ordering::Relaxed);
+    MIN_TICK.store(min_tick, Ordering::Relaxed);
+
+    // 停止计数并关闭全部比较器的中断后从 0 开始计数
+    write(REG_CONFIG, read(REG_CONFIG) & !(CONFIG_ENABLE | CONFIG_LEGACY));
+    for timer in 0..timers {
+        let config = read(timer_config(timer));
+        write(timer_config(timer), config & !(TN_INT_ENABLE | TN_PERIODIC));
+    }
+    write(REG_MAIN_COUNTER, 0);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_ENABLE);
+
+    // 确认计数器在走：以 PIT 通道 2 计时约 1 毫秒
+    let start = counter();
+    crate::pit::delay_us(1000);
+    let elapsed = counter().wrapping_sub(start);
+    if elapsed == 0 {
+        return Err(KernelError::HardwareError);
+    }
+
+    log::info!(
+        "HPET: {} comparators, {}-bit {}.{:03} MHz counter at {:#x} ({} ticks per PIT ms)",
+        timers,
+        if counter_is_64bit() { 64 } else { 32 },
+        frequency() / 1_000_000,
+        frequency() / 1000 % 1000,
+        address,
+        elapsed
+    );
+
+    if caps & CAP_LEGACY_ROUTE == 0 || timers <= EVENT_TIMER {
+        log::info!("HPET: no legacy replacement route, PIT keeps the tick");
+    } else if let Err(e) = take_over_legacy() {
+        log::warn!("HPET: cannot take over IRQ0/IRQ8 ({e}), PIT keeps the tick");
+    }
+    Ok(())
+}
+
+/// 启用传统替换路由：比较器 0 接管 IRQ0 节拍，比较器 1 作为 IRQ8 事件源
+fn take_over_legacy() -> KernelResult<()> {
+    let hz = crate::pit::frequency();
+    if hz == 0 || read(timer_config(TICK_TIMER)) & TN_PERIODIC_CAP == 0 {
+        return Err(KernelError::HardwareError);
+    }
+    irq::register(EVENT_IRQ, "hpet", event_irq)?;
+    // 参照 Linux：首次编程时停住主计数器，确保首次到期值与周期一并生效；
+    // 此后的重新编程都针对运行中的计数器，不能再停，否则时钟源会丢时间
+    write(REG_CONFIG, read(REG_CONFIG) & !CONFIG_ENABLE);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_LEGACY);
+    start_timer(TICK_TIMER, EventMode::Periodic, frequency() / hz);
+    write(REG_CONFIG, read(REG_CONFIG) | CONFIG_ENABLE);
+    LEGACY.store(true, Ordering::Relaxed);
+    log::info!("HPET: comparator 0 drives the {hz} Hz tick on IRQ0, comparator 1 events on IRQ{EVENT_IRQ}");
+    Ok(())
+}
+
+/// 编程一个比较器：周期模式下每 `ticks` 个计数触发一次，单次模式下在 `ticks` 个计数后触发
+fn start_timer(timer: u8, mode: EventMode, ticks: u64) {
+    let ticks = ticks.max(MIN_TICK.load(Ordering::Relaxed)).max(1);
+    let mut config = read(timer_config(timer)) & !(TN_INT_ENABLE | TN_PERIODIC | TN_VAL_SET | TN_32BIT);
+    match mode {
+        EventMode::Shutdown => write(timer_config(timer), config),
+        EventMode::Periodic => {
+            // 依次写入首次到期值与周期；计数器保持运行，首次到期值至少一个周期之后，不会被错过
+            config |= TN_INT_ENABLE | TN_PERIODIC | TN_VAL_SET;
+            write(timer_config(timer), config);
+            write(timer_comparator(timer), read(REG_MAIN_COUNTER).wrapping_add(ticks));
+            write(timer_comparator(timer), ticks);
+        }
+        EventMode::OneShot => {
+            config |= TN_INT_ENABLE;
+            write(timer_config(timer), config);
+            write(timer_comparator(timer), read(REG_MAIN_COUNTER).wrapping_add(ticks));
+        }
+    }
+}
+
+fn event_irq(_line: u8) -> IrqReturn {
+    if let Some(handler) = *EVENT_HANDLER.read() {
+        handler();
+    }
+    IrqReturn::Handled
+}
+
+/// HPET 主计数器是否可用
+pub fn is_available() -> bool {
+    BASE.load(Ordering::Relaxed) != 0
+}
+
+/// 比较器 1 能否作为事件源
+pub fn has_event_source() -> bool {
+    LEGACY.load(Ordering::Relaxed)
+}
+
+/// 主计数器是否为 64 位（32 位计数器几分钟内就会回绕，不能直接用作时钟源）
+pub fn counter_is_64bit() -> bool {
+    COUNTER_64.load(Ordering::Relaxed)
+}
+
+/// 主计数器频率（Hz）
+pub fn frequency() -> u64 {
+    match PERIOD_FS.load(Ordering::Relaxed) {
+        0 => 0,
+        period => FS_PER_SEC / period,
+    }
+}
+
+/// 读取主计数器
+pub fn counter() -> u64 {
+    read(REG_MAIN_COUNTER)
+}
+
+/// 把计数换算为纳秒
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    (ticks as u128 * PERIOD_FS.load(Ordering::Relaxed) as u128 / 1_000_000) as u64
+}
+
+/// 把纳秒换算为计数
+fn ns_to_ticks(ns: u64) -> u64 {
+    (ns as u128 * 1_000_000 / PERIOD_FS.load(Ordering::Relaxed).max(1) as u128) as u64
+}
+
+/// 设置事件到来时调用的函数（在中断上下文中执行）
+pub fn set_event_handler(handler: fn()) {
+    *EVENT_HANDLER.write() = Some(handler);
+}
+
+/// HPET 比较器 1 作为时钟事件设备（中断只投递到 BSP）
+pub struct HpetEvent;
+
+pub static HPET_EVENT: HpetEvent = HpetEvent;
+
+impl ClockEvent for HpetEvent {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn supports(&self, mode: EventMode) -> bool {
+        match mode {
+            EventMode::Periodic => has_event_source() && read(timer_config(EVENT_TIMER)) & TN_PERIODIC_CAP != 0,
+            EventMode::OneShot | EventMode::Shutdown => has_event_source(),
+        }
+    }
+
+    fn set_periodic(&self, hz: u64) -> KernelResult<()> {
+        if !self.supports(EventMode::Periodic) || hz == 0 {
+            return Err(KernelError::InvalidParameter);
+        }
+        start_timer(EVENT_TIMER, EventMode::Periodic, frequency() / hz);
+        Ok(())
+    }
+
+    fn set_next_event(&self, delta_ns: u64) -> KernelResult<()> {
+        if !has_event_source() {
+            return Err(KernelError::InvalidParameter);
+        }
+        start_timer(EVENT_TIMER, EventMode::OneShot, ns_to_ticks(delta_ns));
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        if has_event_source() {
+            start_timer(EVENT_TIMER, EventMode::Shutdown, 0);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! 中断描述符表模块
+//! 为全部 256 个向量建立统一的汇编入口，保存通用寄存器后交给 Rust 分发函数处理
+
+use core::arch::{asm, global_asm};
+use core::fmt;
+use spin::Once;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::{InterruptDescriptorTable, PageFaultErrorCode};
+use x86_64::VirtAddr;
+use crate::constants::apic::{CALL_FUNCTION_VECTOR, LAPIC_SPURIOUS_VECTOR, LOCAL_TIMER_VECTOR};
+use crate::constants::gdt::{DOUBLE_FAULT_IST_INDEX, MACHINE_CHECK_IST_INDEX, NMI_IST_INDEX};
+
+/// CPU 异常向量
+pub mod vector {
+    pub const DIVIDE_ERROR: u8 = 0;
+    pub const DEBUG: u8 = 1;
+    pub const NMI: u8 = 2;
+    pub const BREAKPOINT: u8 = 3;
+    pub const DOUBLE_FAULT: u8 = 8;
+    pub const PAGE_FAULT: u8 = 14;
+    pub const MACHINE_CHECK: u8 = 18;
+    pub const SIMD_FLOATING_POINT: u8 = 19;
+    /// 第一个外部中断向量
+    pub const FIRST_EXTERNAL: u8 = 32;
+}
+
+/// 异常名称（按向量号排列）
+const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide Error",
+    "Debug",
+    "Non-Maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "Bound Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection Fault",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point Exception",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point Exception",
+    "Virtualization Exception",
+    "Control Protection Exception",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection Exception",
+    "VMM Communication Exception",
+    "Security Exception",
+    "Reserved",
+];
+
+/// 入口桩之间的间距（字节）
+const STUB_SIZE: u64 = 16;
+
+/// 中断入口保存的完整现场
+/// 布局必须与 trap_common 中的压栈顺序一致
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    /// 向量号（由入口桩压入）
+    pub vector: u64,
+    /// 错误码（CPU 不提供时由入口桩压入 0）
+    pub error_code: u64,
+    // 以下由 CPU 压入（即 InterruptStackFrame）
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    /// 向量号
+    pub fn vector(&self) -> u8 {
+        self.vector as u8
+    }
+}
+
+impl fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "RIP={:#018x} CS={:#06x} RFLAGS={:#018x}", self.rip, self.cs, self.rflags)?;
+        writeln!(f, "RSP={:#018x} SS={:#06x}", self.rsp, self.ss)?;
+        writeln!(f, "RAX={:#018x} RBX={:#018x} RCX={:#018x}", self.rax, self.rbx, self.rcx)?;
+        writeln!(f, "RDX={:#018x} RSI={:#018x} RDI={:#018x}", self.rdx, self.rsi, self.rdi)?;
+        writeln!(f, "RBP={:#018x} R8 ={:#018x} R9 ={:#018x}", self.rbp, self.r8, self.r9)?;
+        writeln!(f, "R10={:#018x} R11={:#018x} R12={:#018x}", self.r10, self.r11, self.r12)?;
+        write!(f, "R13={:#018x} R14={:#018x} R15={:#018x}", self.r13, self.r14, self.r15)
+    }
+}
+
+// 入口桩：每个向量 16 字节，统一压入（错误码，向量号）后跳转到 trap_common
+// 带错误码的异常：8、10-14、17、21、29、30
+global_asm!(
+    ".section .text.trap, \"ax\"",
+    ".global trap_stubs",
+    ".p2align 4",
+    "trap_stubs:",
+    ".set trap_vector, 0",
+    ".rept 256",
+    "    .p2align 4",
+    "    .if trap_vector == 8 || (trap_vector >= 10 && trap_vector <= 14) || trap_vector == 17 || trap_vector == 21 || trap_vector == 29 || trap_vector == 30",
+    "    pushq $trap_vector",
+    "    .else",
+    "    pushq $0",
+    "    pushq $trap_vector",
+    "    .endif",
+    "    jmp trap_common",
+    "    .set trap_vector, trap_vector + 1",
+    ".endr",
+    "",
+    "trap_common:",
+    // 从用户态进入时切换到内核的 GS 基址（此时栈上依次为向量号、错误码、RIP、CS）
+    "    testb $3, 24(%rsp)",
+    "    jz 1f",
+    "    swapgs",
+    "1:",
+    "    pushq %rax",
+    "    pushq %rbx",
+    "    pushq %rcx",
+    "    pushq %rdx",
+    "    pushq %rsi",
+    "    pushq %rdi",
+    "    pushq %rbp",
+    "    pushq %r8",
+    "    pushq %r9",
+    "    pushq %r10",
+    "    pushq %r11",
+    "    pushq %r12",
+    "    pushq %r13",
+    "    pushq %r14",
+    "    pushq %r15",
+    // CPU 进入中断时已将栈对齐到 16 字节，压入 22 个 8 字节后仍保持对齐
+    "    movq %rsp, %rdi",
+    "    cld",
+    "    call trap_dispatch",
+    "    popq %r15",
+    "    popq %r14",
+    "    popq %r13",
+    "    popq %r12",
+    "    popq %r11",
+    "    popq %r10",
+    "    popq %r9",
+    "    popq %r8",
+    "    popq %rbp",
+    "    popq %rdi",
+    "    popq %rsi",
+    "    popq %rdx",
+    "    popq %rcx",
+    "    popq %rbx",
+    "    popq %rax",
+    // 返回用户态前换回用户的 GS 基址
+    "    testb $3, 24(%rsp)",
+    "    jz 2f",
+    "    swapgs",
+    "2:",
+    // 丢弃向量号与错误码
+    "    addq $16, %rsp",
+    "    iretq",
+    ".previous",
+    options(att_syntax)
+);
+
+static IDT: Once<InterruptDescriptorTable> = Once::new();
+
+/// 获取某个向量入口桩的运行时地址
+fn stub_address(vector: u8) -> VirtAddr {
+    let base: u64;
+    unsafe {
+        asm!("lea {}, [rip + trap_stubs]", out(reg) base, options(nomem, nostack, preserves_flags));
+    }
+    VirtAddr::new(base + vector as u64 * STUB_SIZE)
+}
+
+/// 初始化并加载 IDT
+pub fn init_idt() {
+    let idt = IDT.call_once(|| {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.divide_error.set_handler_addr(stub_address(vector::DIVIDE_ERROR));
+            idt.debug.set_handler_addr(stub_address(vector::DEBUG));
+            // 这些异常可能发生在内核栈已损坏（如栈溢出）时，必须切换到独立的 IST 栈
+            idt.non_maskable_interrupt
+                .set_handler_addr(stub_address(vector::NMI))
+                .set_stack_index(NMI_IST_INDEX);
+            idt.breakpoint.set_handler_addr(stub_address(vector::BREAKPOINT));
+            idt.overflow.set_handler_addr(stub_address(4));
+            idt.bound_range_exceeded.set_handler_addr(stub_address(5));
+            idt.invalid_opcode.set_handler_addr(stub_address(6));
+            idt.device_not_available.set_handler_addr(stub_address(7));
+            idt.double_fault
+                .set_handler_addr(stub_address(vector::DOUBLE_FAULT))
+                .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+            idt.invalid_tss.set_handler_addr(stub_address(10));
+            idt.segment_not_present.set_handler_addr(stub_address(11));
+            idt.stack_segment_fault.set_handler_addr(stub_address(12));
+            idt.general_protection_fault.set_handler_addr(stub_address(13));
+            idt.page_fault.set_handler_addr(stub_address(vector::PAGE_FAULT));
+            idt.x87_floating_point.set_handler_addr(stub_address(16));
+            idt.alignment_check.set_handler_addr(stub_address(17));
+            idt.machine_check
+                .set_handler_addr(stub_address(vector::MACHINE_CHECK))
+                .set_stack_index(MACHINE_CHECK_IST_INDEX);
+            idt.simd_floating_point.set_handler_addr(stub_address(19));
+            idt.virtualization.set_handler_addr(stub_address(20));
+            idt.cp_protection_exception.set_handler_addr(stub_address(21));
+            idt.hv_injection_exception.set_handler_addr(stub_address(28));
+            idt.vmm_communication_exception.set_handler_addr(stub_address(29));
+            idt.security_exception.set_handler_addr(stub_address(30));
+
+            for (i, entry) in idt.slice_mut(vector::FIRST_EXTERNAL as usize..).iter_mut().enumerate() {
+                entry.set_handler_addr(stub_address(vector::FIRST_EXTERNAL + i as u8));
+            }
+        }
+        idt
+    });
+    idt.load();
+    log::info!("IDT loaded: 256 vectors, stubs at {:#x}", stub_address(0).as_u64());
+}
+
+/// 在 AP 上加载已建立的 IDT（所有 CPU 共用同一张表）
+pub fn load_idt() {
+    if let Some(idt) = IDT.r#try() {
+        idt.load();
+    }
+}
+
+/// 所有向量的 Rust 分发入口（由 trap_common 调用）
+#[no_mangle]
+extern "C" fn trap_dispatch(frame: &mut TrapFrame) {
+    match frame.vector() {
+        vector::BREAKPOINT => breakpoint(frame),
+        vector::NMI => nmi(frame),
+        vector::PAGE_FAULT => page_fault(frame),
+        vector::MACHINE_CHECK => crate::mce::handle(frame),
+        v if v < vector::FIRST_EXTERNAL => exception_panic(frame),
+        v if crate::irq::handles(v) => crate::irq::handle(v),
+        v if crate::vectors::handles(v) => crate::vectors::handle(v),
+        CALL_FUNCTION_VECTOR => crate::ipi::handle_call(),
+        LOCAL_TIMER_VECTOR => crate::lapic::timer_interrupt(frame),
+        // 被屏蔽的 8259 仍可能产生伪中断
+        v if crate::pic::handles(v) => crate::pic::handle_stray(v),
+        // 本地 APIC 伪中断不需要 EOI
+        LAPIC_SPURIOUS_VECTOR => {}
+        v => log::warn!("Unexpected interrupt on vector {v}"),
+    }
+}
+
+/// 断点异常：输出现场后继续执行（RIP 已指向 int3 之后的指令）
+fn breakpoint(frame: &TrapFrame) {
+    log::info!("EXCEPTION: BREAKPOINT at {:#x}", frame.rip);
+    log::info!("{frame}");
+}
+
+/// NMI：看门狗未认领的记录后返回
+///
+/// NMI 可能打断持有串口锁的代码，只能走不加锁的紧急输出。
+fn nmi(frame: &TrapFrame) {
+    if crate::watchdog::nmi(frame) {
+        return;
+    }
+    crate::serial::emergency_write(format_args!("[WARN] NMI received at {:#x}\n", frame.rip));
+}
+
+/// 页错误：预期中的故障（如 W^X 自检）跳转到修复地址继续执行
+fn page_fault(frame: &mut TrapFrame) {
+    if let Some(fixup) = crate::wx::fault_fixup(frame.rip) {
+        frame.rip = fixup;
+        return;
+    }
+    exception_panic(frame);
+}
+
+/// 统一的异常 panic 路径：输出异常名称、错误码、控制寄存器与完整现场
+fn exception_panic(frame: &TrapFrame) -> ! {
+    let vector = frame.vector();
+    let name = EXCEPTION_NAMES.get(vector as usize).copied().unwrap_or("Unknown");
+
+    log::error!("EXCEPTION: {} (vector {}, error code {:#x})", name, vector, frame.error_code);
+    match vector {
+        vector::PAGE_FAULT => {
+            log::error!("Accessed Address: {:?}", Cr2::read());
+            log::error!("Error Code: {:?}", PageFaultErrorCode::from_bits_truncate(frame.error_code));
+        }
+        vector::DOUBLE_FAULT => {
+            log::error!("Stack pointer {:#x} - likely a kernel stack overflow", frame.rsp);
+        }
+        vector::SIMD_FLOATING_POINT => {
+            let mut mxcsr = 0u32;
+            unsafe { asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack)) };
+            log::error!("MXCSR={mxcsr:#x}");
+        }
+        _ => {}
+    }
+    log::error!("CR0={:?} CR3={:#x} CR4={:?}", Cr0::read(), Cr3::read().0.start_address().as_u64(), Cr4::read());
+    log::error!("{frame}");
+    panic!("Unhandled exception: {} at {:#x}", name, frame.rip);
+}
@@ -0,0 +1,245 @@
+//! I/O APIC 模块
+//! 按 MADT 编程重定向表：ISA IRQ 经中断源覆盖映射到 GSI，并设置正确的极性与触发方式；
+//! 作为 `irq` 模块的中断控制器使用
+
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use spin::Once;
+use crate::acpi::{IntiFlags, MadtInfo, Polarity, TriggerMode};
+use crate::constants::acpi::MAX_IOAPICS;
+use crate::constants::apic::{IOAPIC_IRQ_BASE, ISA_IRQ_COUNT};
+use crate::constants::irq::NR_IRQ_LINES;
+use crate::error::{KernelResult, KernelError};
+use crate::irq::IrqChip;
+use crate::sync::IrqSafeSpinlock;
+
+/// 寄存器选择与数据窗口偏移
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+
+/// 寄存器索引
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION: u32 = 0x10;
+
+/// 重定向表项字段
+const RTE_DELIVERY_NMI: u64 = 0b100 << 8;
+const RTE_ACTIVE_LOW: u64 = 1 << 13;
+const RTE_LEVEL: u64 = 1 << 15;
+const RTE_MASKED: u64 = 1 << 16;
+
+/// 一个 I/O APIC 控制器
+#[derive(Debug, Clone, Copy)]
+struct Chip {
+    /// 寄存器的虚拟地址
+    base: u64,
+    /// 第一条输入引脚的 GSI
+    gsi_base: u32,
+    /// 重定向表项数量
+    entries: u32,
+}
+
+impl Chip {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries).contains(&gsi)
+    }
+
+    /// 写入重定向表项（先写高位再写低位，避免未屏蔽时目标不完整）
+    fn write_entry(&self, gsi: u32, entry: u64) {
+        let reg = REG_REDIRECTION + (gsi - self.gsi_base) * 2;
+        self.write(reg, RTE_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+
+    fn read_entry(&self, gsi: u32) -> u64 {
+        let reg = REG_REDIRECTION + (gsi - self.gsi_base) * 2;
+        (self.read(reg + 1) as u64) << 32 | self.read(reg) as u64
+    }
+}
+
+static CHIPS: Once<[Option<Chip>; MAX_IOAPICS]> = Once::new();
+
+/// IOREGSEL/IOWIN 是一对寄存器，访问必须串行化
+static LOCK: IrqSafeSpinlock<()> = IrqSafeSpinlock::new(());
+
+/// 每条中断线对应的 GSI（`u32::MAX` 表示不可用）
+static LINE_GSI: [AtomicU32; NR_IRQ_LINES] = [const { AtomicU32::new(u32::MAX) }; NR_IRQ_LINES];
+
+/// 可用的中断线数量
+static LINE_COUNT: AtomicU8 = AtomicU8::new(0);
+
+/// 查找负责某个 GSI 的控制器
+fn chip_for(gsi: u32) -> Option<&'static Chip> {
+    CHIPS.r#try()?.iter().flatten().find(|chip| chip.handles(gsi))
+}
+
+/// 根据极性与触发方式生成重定向表项的标志位（总线默认值按 ISA 处理：高电平、边沿）
+fn inti_bits(flags: IntiFlags) -> u64 {
+    let mut bits = 0;
+    if flags.polarity() == Polarity::ActiveLow {
+        bits |= RTE_ACTIVE_LOW;
+    }
+    if flags.trigger_mode() == TriggerMode::Level {
+        bits |= RTE_LEVEL;
+    }
+    bits
+}
+
+/// 初始化所有 I/O APIC 并把中断线路由到当前 CPU
+pub fn init() -> KernelResult<()> {
+    let madt = crate::acpi::madt().ok_or(KernelError::AcpiError)?;
+    if !crate::lapic::is_enabled() {
+        return Err(KernelError::HardwareError);
+    }
+
+    let mut chips = [None; MAX_IOAPICS];
+    for (slot, ioapic) in chips.iter_mut().zip(madt.io_apics()) {
+        let base = crate::memory::map_mmio(ioapic.address, 0x20)?.as_u64();
+        let mut chip = Chip { base, gsi_base: ioapic.gsi_base, entries: 0 };
+        chip.entries = ((chip.read(REG_VERSION) >> 16) & 0xff) + 1;
+        for gsi in chip.gsi_base..chip.gsi_base + chip.entries {
+            chip.write_entry(gsi, RTE_MASKED);
+        }
+        log::info!(
+            "IOAPIC: id {}, MMIO at {:#x}, GSI {}-{}",
+            ioapic.id,
+            ioapic.address,
+            chip.gsi_base,
+            chip.gsi_base + chip.entries - 1
+        );
+        *slot = Some(chip);
+    }
+    if chips.iter().all(Option::is_none) {
+        return Err(KernelError::HardwareError);
+    }
+    CHIPS.call_once(|| chips);
+
+    // 没有中断重映射时 I/O APIC 只能以 8 位物理 ID 寻址
+    let apic_id = crate::lapic::id();
+    if apic_id > 0xff {
+        log::warn!("IOAPIC: BSP APIC id {apic_id} is not addressable without interrupt remapping");
+    }
+    let destination = ((apic_id & 0xff) as u64) << 56;
+    for line in 0..NR_IRQ_LINES as u8 {
+        let Some((gsi, bits)) = route(madt, line) else { continue };
+        let Some(chip) = chip_for(gsi) else {
+            if (line as usize) < ISA_IRQ_COUNT {
+                log::warn!("IOAPIC: no controller for IRQ {line} (GSI {gsi})");
+            }
+            continue;
+        };
+        // 全部保持屏蔽，由 irq::register 打开
+        let vector = (IOAPIC_IRQ_BASE + line) as u64;
+        chip.write_entry(gsi, destination | RTE_MASKED | bits | vector);
+        LINE_GSI[line as usize].store(gsi, Ordering::Relaxed);
+        LINE_COUNT.fetch_max(line + 1, Ordering::Relaxed);
+    }
+
+    for nmi in madt.nmi_sources() {
+        if let Some(chip) = chip_for(nmi.gsi) {
+            chip.write_entry(nmi.gsi, destination | RTE_DELIVERY_NMI | inti_bits(nmi.flags));
+            log::info!("IOAPIC: GSI {} routed as NMI", nmi.gsi);
+        }
+    }
+
+    log::info!(
+        "IOAPIC: IRQ lines 0-{} routed to vectors {}-{}",
+        IOAPIC.line_count() - 1,
+        IOAPIC_IRQ_BASE,
+        IOAPIC_IRQ_BASE + IOAPIC.line_count() - 1
+    );
+    Ok(())
+}
+
+/// 计算中断线对应的 GSI 与极性/触发方式
+/// 0–15 为 ISA IRQ（按中断源覆盖映射），16 起直接对应同号 GSI（PCI：低电平、电平触发）
+fn route(madt: &MadtInfo, line: u8) -> Option<(u32, u64)> {
+    // IRQ2 是 8259 的级联线，不会产生中断
+    if line == 2 {
+        return None;
+    }
+    if let Some(iso) = madt.isa_override(line).filter(|_| (line as usize) < ISA_IRQ_COUNT) {
+        return Some((iso.gsi, inti_bits(iso.flags)));
+    }
+    // 该 GSI 已被某个 ISA IRQ 的覆盖占用（例如 IRQ0 -> GSI2）
+    if madt.overrides().any(|iso| iso.gsi == line as u32 && iso.source != line) {
+        return None;
+    }
+    if (line as usize) < ISA_IRQ_COUNT {
+        Some((line as u32, 0))
+    } else {
+        Some((line as u32, RTE_ACTIVE_LOW | RTE_LEVEL))
+    }
+}
+
+/// 读-改-写一条中断线的重定向表项
+fn update_entry(line: u8, f: impl FnOnce(u64) -> u64) {
+    let gsi = LINE_GSI[line as usize].load(Ordering::Relaxed);
+    let Some(chip) = chip_for(gsi) else { return };
+    let _guard = LOCK.lock();
+    chip.write_entry(gsi, f(chip.read_entry(gsi)));
+}
+
+/// 把中断线改为以 NMI 方式投递到当前 CPU（如 PIT 驱动的 NMI 看门狗）
+pub fn route_nmi(line: u8) -> KernelResult<()> {
+    let gsi = LINE_GSI.get(line as usize).ok_or(KernelError::InvalidParameter)?.load(Ordering::Relaxed);
+    let chip = chip_for(gsi).ok_or(KernelError::HardwareError)?;
+    let destination = ((crate::lapic::id() & 0xff) as u64) << 56;
+    let _guard = LOCK.lock();
+    // 保留极性，NMI 只能边沿触发
+    let polarity = chip.read_entry(gsi) & RTE_ACTIVE_LOW;
+    chip.write_entry(gsi, destination | RTE_DELIVERY_NMI | polarity);
+    Ok(())
+}
+
+/// I/O APIC 中断控制器（所有 I/O APIC 合并为一组中断线）
+pub struct IoApicChip;
+
+pub static IOAPIC: IoApicChip = IoApicChip;
+
+impl IrqChip for IoApicChip {
+    fn name(&self) -> &'static str {
+        "IO-APIC"
+    }
+
+    fn line_count(&self) -> u8 {
+        LINE_COUNT.load(Ordering::Relaxed)
+    }
+
+    fn vector(&self, line: u8) -> u8 {
+        IOAPIC_IRQ_BASE + line
+    }
+
+    fn mask(&self, line: u8) {
+        update_entry(line, |entry| entry | RTE_MASKED);
+    }
+
+    fn unmask(&self, line: u8) {
+        update_entry(line, |entry| entry & !RTE_MASKED);
+    }
+
+    fn eoi(&self, _line: u8) {
+        crate::lapic::eoi();
+    }
+
+    fn trigger_mode(&self, line: u8) -> TriggerMode {
+        let gsi = LINE_GSI[line as usize].load(Ordering::Relaxed);
+        match chip_for(gsi) {
+            Some(chip) if chip.read_entry(gsi) & RTE_LEVEL != 0 => TriggerMode::Level,
+            _ => TriggerMode::Edge,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! 处理器间中断模块
+//! 提供向指定 CPU 或其他所有 CPU 发送 IPI，以及等待完成的远程函数调用
+
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
+use crate::constants::apic::CALL_FUNCTION_VECTOR;
+use crate::error::{KernelResult, KernelError};
+use crate::per_cpu;
+use crate::smp::cpu_index;
+use crate::sync::{IrqSafeSpinlock, TicketGuard, TicketLock};
+
+/// 远程调用的函数，参数由发起者传入
+pub type CallFunc = fn(data: usize);
+
+#[derive(Clone, Copy)]
+struct RemoteCall {
+    func: CallFunc,
+    data: usize,
+}
+
+/// 串行化远程调用的发起者：同一时刻只有一个请求在途
+static CALL_LOCK: TicketLock<()> = TicketLock::new(());
+
+/// 当前请求（发起者在发送 IPI 之前写入）
+static REQUEST: IrqSafeSpinlock<Option<RemoteCall>> = IrqSafeSpinlock::new(None);
+
+per_cpu! {
+    // 是否有待执行的请求（由发起者为目标 CPU 置位）
+    static CALL_PENDING: AtomicBool = AtomicBool::new(false);
+    // 远程调用中断次数
+    static CALL_COUNTS: AtomicU64 = AtomicU64::new(0);
+}
+
+/// 尚未完成的目标 CPU 数量
+static CALL_REMAINING: AtomicUsize = AtomicUsize::new(0);
+
+/// 向指定 CPU 发送固定投递的中断
+pub fn send_ipi(cpu: usize, vector: u8) -> KernelResult<()> {
+    let apic_id = crate::smp::apic_id(cpu).ok_or(KernelError::InvalidParameter)?;
+    crate::lapic::send_ipi(apic_id, vector as u32);
+    Ok(())
+}
+
+/// 向除当前 CPU 以外的所有 CPU 发送中断
+pub fn broadcast(vector: u8) {
+    crate::lapic::send_ipi_all_but_self(vector);
+}
+
+/// 在指定 CPU 上执行函数并等待完成（目标为当前 CPU 时关中断直接调用）
+#[allow(dead_code)]
+pub fn call_on(cpu: usize, func: CallFunc, data: usize) -> KernelResult<()> {
+    if cpu == cpu_index() {
+        without_interrupts(|| func(data));
+        return Ok(());
+    }
+    if crate::smp::apic_id(cpu).is_none() {
+        return Err(KernelError::InvalidParameter);
+    }
+
+    let _guard = lock_call();
+    post(RemoteCall { func, data }, 1);
+    CALL_PENDING.for_cpu(cpu).store(true, Ordering::Release);
+    send_ipi(cpu, CALL_FUNCTION_VECTOR)?;
+    wait();
+    Ok(())
+}
+
+/// 在其他所有在线 CPU 上执行函数，全部完成后返回
+pub fn call_on_others(func: CallFunc, data: usize) {
+    if crate::smp::online_count() <= 1 {
+        return;
+    }
+    let me = cpu_index();
+    let targets = (0..crate::smp::cpu_count()).filter(|&cpu| cpu != me && crate::smp::apic_id(cpu).is_some());
+
+    let _guard = lock_call();
+    post(RemoteCall { func, data }, targets.clone().count());
+    for cpu in targets {
+        CALL_PENDING.for_cpu(cpu).store(true, Ordering::Release);
+    }
+    // 未上线的 CPU 即使收到中断也没有待执行的请求，不会影响完成计数
+    broadcast(CALL_FUNCTION_VECTOR);
+    wait();
+}
+
+/// 获取发起者锁
+/// 其他 CPU 可能正关中断等待本 CPU 完成它的请求，等锁期间也要处理自己的请求以免死锁
+#[track_caller]
+fn lock_call() -> TicketGuard<'static, ()> {
+    loop {
+        if let Some(guard) = CALL_LOCK.try_lock() {
+            return guard;
+        }
+        run_pending_call();
+        spin_loop();
+    }
+}
+
+/// 发布请求
+fn post(call: RemoteCall, targets: usize) {
+    *REQUEST.lock() = Some(call);
+    CALL_REMAINING.store(targets, Ordering::Release);
+}
+
+/// 等待所有目标完成
+fn wait() {
+    while CALL_REMAINING.load(Ordering::Acquire) != 0 {
+        spin_loop();
+    }
+}
+
+/// 执行发给当前 CPU 的请求
+fn run_pending_call() {
+    if !CALL_PENDING.get().swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let call = *REQUEST.lock();
+    if let Some(call) = call {
+        (call.func)(call.data);
+    }
+    CALL_COUNTS.get().fetch_add(1, Ordering::Relaxed);
+    CALL_REMAINING.fetch_sub(1, Ordering::AcqRel);
+}
+
+/// 远程调用中断入口（由中断分发函数调用，此时中断已关闭）
+pub fn handle_call() {
+    crate::softirq::irq_enter();
+    run_pending_call();
+    crate::lapic::eoi();
+    crate::softirq::irq_exit();
+}
+
+/// 输出处理器间中断的统计（由 irq::Stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    write!(f, "\nCAL:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", CALL_COUNTS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Function call interrupts")?;
+    crate::tlb::write_stats(f, cpus)
+}
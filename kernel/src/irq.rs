@@ -0,0 +1,406 @@
+//! 通用中断注册模块
+//! 在 8259 PIC 与 I/O APIC 之上提供统一的中断线注册（支持共享）、
+//! 类似 /proc/interrupts 的统计，以及无人处理 / 伪中断风暴检测
+
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use spin::Once;
+use crate::acpi::TriggerMode;
+use crate::constants::irq::*;
+use crate::error::{KernelResult, KernelError};
+use crate::per_cpu;
+use crate::smp::cpu_count;
+use crate::sync::IrqSafeSpinlock;
+
+/// 中断处理函数的返回值（共享中断线上用于判断是谁的中断）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// 设备确实产生了中断并已处理
+    Handled,
+    /// 不是本设备的中断
+    None,
+}
+
+/// 中断处理函数，参数为中断线号
+pub type IrqHandler = fn(line: u8) -> IrqReturn;
+
+/// 中断控制器抽象（8259 PIC 或 I/O APIC）
+pub trait IrqChip: Sync {
+    /// 控制器名称
+    fn name(&self) -> &'static str;
+    /// 可用的中断线数量
+    fn line_count(&self) -> u8;
+    /// 中断线对应的 IDT 向量
+    fn vector(&self, line: u8) -> u8;
+    /// 屏蔽中断线
+    fn mask(&self, line: u8);
+    /// 打开中断线
+    fn unmask(&self, line: u8);
+    /// 发送中断结束信号
+    fn eoi(&self, line: u8);
+    /// 中断线的触发方式
+    fn trigger_mode(&self, line: u8) -> TriggerMode;
+    /// 检查是否为伪中断（控制器需要的特殊 EOI 在此完成）
+    fn is_spurious(&self, _line: u8) -> bool {
+        false
+    }
+}
+
+/// 已注册的处理函数
+#[derive(Clone, Copy)]
+struct IrqAction {
+    name: &'static str,
+    handler: IrqHandler,
+}
+
+/// 中断线描述符
+struct IrqDesc {
+    actions: [Option<IrqAction>; MAX_SHARED_HANDLERS],
+    /// 因风暴或无人处理而被自动关闭
+    disabled: bool,
+    /// 当前统计窗口内的中断数
+    window: u32,
+    /// 当前统计窗口内无人处理的中断数
+    unhandled: u32,
+    /// 连续的伪中断次数
+    spurious_run: u32,
+}
+
+impl IrqDesc {
+    const fn new() -> Self {
+        Self {
+            actions: [None; MAX_SHARED_HANDLERS],
+            disabled: false,
+            window: 0,
+            unhandled: 0,
+            spurious_run: 0,
+        }
+    }
+
+    fn has_actions(&self) -> bool {
+        self.actions.iter().any(Option::is_some)
+    }
+}
+
+static CHIP: Once<&'static dyn IrqChip> = Once::new();
+
+static DESCS: [IrqSafeSpinlock<IrqDesc>; NR_IRQ_LINES] = [const { IrqSafeSpinlock::new(IrqDesc::new()) }; NR_IRQ_LINES];
+
+/// 每条中断线上正在执行处理函数的 CPU 数
+static IN_PROGRESS: [AtomicU32; NR_IRQ_LINES] = [const { AtomicU32::new(0) }; NR_IRQ_LINES];
+
+per_cpu! {
+    // 每条中断线的中断次数
+    static COUNTS: [AtomicU64; NR_IRQ_LINES] = [const { AtomicU64::new(0) }; NR_IRQ_LINES];
+    // 伪中断次数
+    static SPURIOUS: AtomicU64 = AtomicU64::new(0);
+    // 本 CPU 正在处理的中断线号加一，0 表示没有
+    static HANDLING: AtomicU8 = AtomicU8::new(0);
+}
+
+/// 选定中断控制器（在开中断之前调用一次）
+pub fn init(chip: &'static dyn IrqChip) {
+    let chip = *CHIP.call_once(|| chip);
+    log::info!("IRQ: using {} ({} lines)", chip.name(), chip.line_count());
+}
+
+/// 获取当前中断控制器
+fn chip() -> Option<&'static dyn IrqChip> {
+    CHIP.r#try().copied()
+}
+
+/// 检查中断线号是否有效
+fn check_line(line: u8) -> KernelResult<&'static dyn IrqChip> {
+    let chip = chip().ok_or(KernelError::InvalidParameter)?;
+    if line >= chip.line_count() || line as usize >= NR_IRQ_LINES {
+        return Err(KernelError::InvalidParameter);
+    }
+    Ok(chip)
+}
+
+/// 注册中断处理函数；同一条线可以由多个设备共享
+pub fn register(line: u8, name: &'static str, handler: IrqHandler) -> KernelResult<()> {
+    let chip = check_line(line)?;
+    {
+        let mut desc = DESCS[line as usize].lock();
+        let slot = desc.actions.iter_mut().find(|slot| slot.is_none()).ok_or(KernelError::NoResources)?;
+        *slot = Some(IrqAction { name, handler });
+        // 新注册的处理函数可能正是之前缺失的那个，重新打开被自动关闭的线
+        desc.disabled = false;
+        desc.window = 0;
+        desc.unhandled = 0;
+        desc.spurious_run = 0;
+        chip.unmask(line);
+    }
+    log::info!("IRQ {line}: registered \"{name}\" on {} vector {}", chip.name(), chip.vector(line));
+    Ok(())
+}
+
+/// 注销中断处理函数；最后一个处理函数注销后屏蔽该线
+///
+/// 返回前等待其他 CPU 上正在执行的处理函数结束，之后调用者可以安全释放处理函数使用的数据。
+#[allow(dead_code)]
+pub fn unregister(line: u8, handler: IrqHandler) -> KernelResult<()> {
+    let chip = check_line(line)?;
+    let name = {
+        let mut desc = DESCS[line as usize].lock();
+        let slot = desc
+            .actions
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|action| action.handler as usize == handler as usize))
+            .ok_or(KernelError::InvalidParameter)?;
+        let name = slot.take().map(|action| action.name);
+        if !desc.has_actions() {
+            chip.mask(line);
+        }
+        name
+    };
+    synchronize(line);
+    log::info!("IRQ {line}: unregistered \"{}\"", name.unwrap_or("?"));
+    Ok(())
+}
+
+/// 等待中断线上正在其他 CPU 执行的处理函数全部结束
+///
+/// 处理函数内注销自己时，不等待当前 CPU 上的这一次执行。
+pub fn synchronize(line: u8) {
+    let own = (HANDLING.get().load(Ordering::Relaxed) == line + 1) as u32;
+    while IN_PROGRESS[line as usize].load(Ordering::Acquire) > own {
+        spin_loop();
+    }
+}
+
+/// 查找向量对应的中断线
+fn line_for_vector(chip: &dyn IrqChip, vector: u8) -> Option<u8> {
+    let line = vector.checked_sub(chip.vector(0))?;
+    (line < chip.line_count() && (line as usize) < NR_IRQ_LINES).then_some(line)
+}
+
+/// 判断向量是否属于当前中断控制器
+pub fn handles(vector: u8) -> bool {
+    chip().is_some_and(|chip| line_for_vector(chip, vector).is_some())
+}
+
+/// 外部中断入口（由中断分发函数调用，此时中断已关闭）
+pub fn handle(vector: u8) {
+    let Some(chip) = chip() else { return };
+    let Some(line) = line_for_vector(chip, vector) else { return };
+
+    if chip.is_spurious(line) {
+        count_spurious();
+        note_spurious(chip, line);
+        return;
+    }
+
+    crate::softirq::irq_enter();
+
+    COUNTS.get()[line as usize].fetch_add(1, Ordering::Relaxed);
+    // 复制处理函数列表后释放锁，允许处理函数内注销自己；
+    // 持锁期间登记为执行中，注销者在移除处理函数之后等待计数归零
+    let actions = {
+        let mut desc = DESCS[line as usize].lock();
+        desc.spurious_run = 0;
+        IN_PROGRESS[line as usize].fetch_add(1, Ordering::Relaxed);
+        desc.actions
+    };
+    HANDLING.get().store(line + 1, Ordering::Relaxed);
+
+    // 共享线上的每个处理函数都要调用，只要有一个认领即视为已处理
+    let result = actions.iter().flatten().fold(IrqReturn::None, |result, action| {
+        match (action.handler)(line) {
+            IrqReturn::Handled => IrqReturn::Handled,
+            IrqReturn::None => result,
+        }
+    });
+    HANDLING.get().store(0, Ordering::Relaxed);
+    IN_PROGRESS[line as usize].fetch_sub(1, Ordering::Release);
+    note_interrupt(chip, line, result == IrqReturn::Handled);
+    chip.eoi(line);
+    // EOI 之后再处理软中断，避免推迟同级及更低优先级的中断
+    crate::softirq::irq_exit();
+}
+
+/// 记录一次伪中断（包括被屏蔽控制器产生的伪中断）
+pub fn count_spurious() {
+    SPURIOUS.get().fetch_add(1, Ordering::Relaxed);
+}
+
+/// 关闭一条中断线
+fn disable_line(chip: &dyn IrqChip, line: u8, desc: &mut IrqDesc) {
+    desc.disabled = true;
+    chip.mask(line);
+}
+
+/// 无人处理中断检测：统计窗口内几乎全部中断都没有处理函数认领时关闭该线
+fn note_interrupt(chip: &dyn IrqChip, line: u8, handled: bool) {
+    let mut desc = DESCS[line as usize].lock();
+    if !desc.has_actions() {
+        log::warn!("IRQ {line}: no handler registered, disabling");
+        disable_line(chip, line, &mut desc);
+        return;
+    }
+
+    desc.window += 1;
+    if !handled {
+        desc.unhandled += 1;
+    }
+    if desc.window < STORM_WINDOW {
+        return;
+    }
+    if desc.unhandled >= STORM_UNHANDLED_LIMIT {
+        log::error!(
+            "IRQ {line}: {} of the last {} interrupts were not handled, disabling",
+            desc.unhandled,
+            desc.window
+        );
+        disable_line(chip, line, &mut desc);
+    }
+    desc.window = 0;
+    desc.unhandled = 0;
+}
+
+/// 伪中断风暴检测：连续出现过多伪中断时关闭该线
+fn note_spurious(chip: &dyn IrqChip, line: u8) {
+    let mut desc = DESCS[line as usize].lock();
+    desc.spurious_run += 1;
+    if desc.spurious_run >= SPURIOUS_STORM_LIMIT && !desc.disabled {
+        log::error!("IRQ {line}: spurious interrupt storm ({} in a row), disabling", desc.spurious_run);
+        disable_line(chip, line, &mut desc);
+    }
+}
+
+/// 中断统计（格式与 /proc/interrupts 类似）
+pub struct Stats;
+
+/// 获取中断统计
+pub fn stats() -> Stats {
+    Stats
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cpus = cpu_count();
+        write!(f, "    ")?;
+        for cpu in 0..cpus {
+            write!(f, " {:>7}{:<3}", "CPU", cpu)?;
+        }
+
+        if let Some(chip) = chip() {
+            for line in 0..chip.line_count().min(NR_IRQ_LINES as u8) {
+                let desc = {
+                    let desc = DESCS[line as usize].lock();
+                    (desc.actions, desc.disabled)
+                };
+                let count = |cpu: usize| COUNTS.for_cpu(cpu)[line as usize].load(Ordering::Relaxed);
+                let (actions, disabled) = desc;
+                if actions.iter().all(Option::is_none) && (0..cpus).all(|cpu| count(cpu) == 0) {
+                    continue;
+                }
+
+                write!(f, "\n{line:>3}:")?;
+                for cpu in 0..cpus {
+                    write!(f, " {:>10}", count(cpu))?;
+                }
+                let trigger = match chip.trigger_mode(line) {
+                    TriggerMode::Level => "level",
+                    _ => "edge",
+                };
+                write!(f, "  {:>8} {:>3}-{:<5}", chip.name(), chip.vector(line), trigger)?;
+                for (i, action) in actions.iter().flatten().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, action.name)?;
+                }
+                if disabled {
+                    write!(f, " (disabled)")?;
+                }
+            }
+        }
+
+        crate::vectors::write_stats(f, cpus)?;
+        crate::ipi::write_stats(f, cpus)?;
+        crate::clockevent::write_stats(f, cpus)?;
+        crate::watchdog::write_stats(f, cpus)?;
+        crate::mce::write_stats(f, cpus)?;
+
+        write!(f, "\nSPU:")?;
+        for cpu in 0..cpus {
+            write!(f, " {:>10}", SPURIOUS.for_cpu(cpu).load(Ordering::Relaxed))?;
+        }
+        Ok(())
+    }
+}
+
+/// 测试用：找一条没有处理函数的边沿触发 ISA 中断线，避免电平触发的设备在测试期间产生中断风暴
+#[cfg(test)]
+fn free_test_line(chip: &dyn IrqChip) -> u8 {
+    [10, 11, 5]
+        .into_iter()
+        .find(|&line| !DESCS[line as usize].lock().has_actions() && chip.trigger_mode(line) == TriggerMode::Edge)
+        .expect("no free IRQ line for tests")
+}
+
+#[test_case]
+fn test_shared_line_calls_every_handler() {
+    static FIRST: AtomicU32 = AtomicU32::new(0);
+    static SECOND: AtomicU32 = AtomicU32::new(0);
+    fn first(_line: u8) -> IrqReturn {
+        FIRST.fetch_add(1, Ordering::Relaxed);
+        IrqReturn::None
+    }
+    fn second(_line: u8) -> IrqReturn {
+        SECOND.fetch_add(1, Ordering::Relaxed);
+        IrqReturn::Handled
+    }
+
+    let chip = chip().expect("IRQ chip selected");
+    // 模拟的中断在关中断状态下分发，与真实入口一致
+    let _irq = crate::sync::irq_off();
+    let line = free_test_line(chip);
+    register(line, "test-first", first).unwrap();
+    register(line, "test-second", second).unwrap();
+    handle(chip.vector(line));
+    assert_eq!(FIRST.load(Ordering::Relaxed), 1);
+    assert_eq!(SECOND.load(Ordering::Relaxed), 1);
+    assert!(!DESCS[line as usize].lock().disabled);
+    assert_eq!(IN_PROGRESS[line as usize].load(Ordering::Relaxed), 0);
+
+    unregister(line, first).unwrap();
+    unregister(line, second).unwrap();
+    assert!(!DESCS[line as usize].lock().has_actions());
+}
+
+#[test_case]
+fn test_unregister_requires_a_registered_handler() {
+    fn handler(_line: u8) -> IrqReturn {
+        IrqReturn::Handled
+    }
+    fn other(_line: u8) -> IrqReturn {
+        IrqReturn::None
+    }
+
+    let chip = chip().expect("IRQ chip selected");
+    let _irq = crate::sync::irq_off();
+    let line = free_test_line(chip);
+    register(line, "test", handler).unwrap();
+    assert_eq!(unregister(line, other), Err(KernelError::InvalidParameter));
+    assert_eq!(unregister(line, handler), Ok(()));
+    assert_eq!(unregister(line, handler), Err(KernelError::InvalidParameter));
+    assert_eq!(register(NR_IRQ_LINES as u8, "test", handler), Err(KernelError::InvalidParameter));
+}
+
+#[test_case]
+fn test_handler_can_unregister_itself() {
+    fn once(line: u8) -> IrqReturn {
+        // 注销时不等待当前 CPU 上正在执行的自己
+        unregister(line, once).unwrap();
+        IrqReturn::Handled
+    }
+
+    let chip = chip().expect("IRQ chip selected");
+    let _irq = crate::sync::irq_off();
+    let line = free_test_line(chip);
+    register(line, "test-once", once).unwrap();
+    handle(chip.vector(line));
+    assert!(!DESCS[line as usize].lock().has_actions());
+    assert_eq!(IN_PROGRESS[line as usize].load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,201 @@
+//! 内核地址空间布局随机化（KASLR）支持
+//! 记录内核映像的实际加载位置，并在引导加载程序不处理重定位时自行重定位
+//!
+//! 命令行参数 `nokaslr` 让内核运行在链接地址：Multiboot 2 可重定位加载时内核把自己搬回链接地址；
+//! Limine 与 bootloader_api 在内核运行前就已选定地址，只能在引导配置中关闭随机化
+//! （limine.conf 的 `kaslr: no`、构建特性 `nokaslr`）。
+
+use core::arch::asm;
+use spin::Once;
+use crate::boot_info::BootInfo;
+use crate::constants::kernel::KERNEL_LINK_BASE;
+
+/// ELF 动态段标签
+#[cfg(feature = "multiboot2")]
+const DT_NULL: u64 = 0;
+#[cfg(feature = "multiboot2")]
+const DT_RELA: u64 = 7;
+#[cfg(feature = "multiboot2")]
+const DT_RELASZ: u64 = 8;
+#[cfg(feature = "multiboot2")]
+const DT_RELAENT: u64 = 9;
+
+/// x86_64 相对重定位类型
+#[cfg(feature = "multiboot2")]
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// 搬移映像时为当前栈向下增长预留的空间
+#[cfg(feature = "multiboot2")]
+const STACK_MARGIN: u64 = 64 * 1024;
+
+/// ELF64 动态段条目
+#[cfg(feature = "multiboot2")]
+#[repr(C)]
+struct Elf64Dyn {
+    d_tag: u64,
+    d_val: u64,
+}
+
+/// ELF64 带加数的重定位条目
+#[cfg(feature = "multiboot2")]
+#[repr(C)]
+struct Elf64Rela {
+    r_offset: u64,
+    r_info: u64,
+    r_addend: i64,
+}
+
+/// 内核映像布局
+#[derive(Debug, Clone, Copy)]
+pub struct KernelLayout {
+    /// 实际虚拟基址
+    pub virtual_base: u64,
+    /// 实际物理基址（引导加载程序未提供时为 None）
+    pub physical_base: Option<u64>,
+    /// 相对于链接地址的偏移量
+    pub slide: u64,
+    /// 映像大小（包含 BSS）
+    pub size: u64,
+}
+
+static LAYOUT: Once<KernelLayout> = Once::new();
+
+/// 获取内核映像当前运行的虚拟基址
+pub fn runtime_base() -> u64 {
+    let base: u64;
+    // 必须使用 RIP 相对寻址，重定位完成前 GOT 中仍是链接时地址
+    unsafe {
+        asm!("lea {}, [rip + __kernel_start]", out(reg) base, options(nomem, nostack, preserves_flags));
+    }
+    base
+}
+
+/// 获取内核映像大小（从 __kernel_start 到 __kernel_end）
+fn image_size() -> u64 {
+    let end: u64;
+    unsafe {
+        asm!("lea {}, [rip + __kernel_end]", out(reg) end, options(nomem, nostack, preserves_flags));
+    }
+    end - runtime_base()
+}
+
+/// 处理内核自身的 R_X86_64_RELATIVE 重定位
+///
+/// 仅用于不会替内核处理重定位的引导路径（如 Multiboot 2 的可重定位加载）。
+/// 此函数执行前不能访问任何需要重定位的数据（虚表、静态指针等）。
+///
+/// # Safety
+///
+/// 必须在内核入口处、其他代码运行之前调用一次，且 `slide` 必须是实际加载偏移。
+#[cfg(feature = "multiboot2")]
+pub unsafe fn apply_relocations(slide: u64) {
+    if slide == 0 {
+        return;
+    }
+
+    let dynamic: *const Elf64Dyn;
+    asm!("lea {}, [rip + _DYNAMIC]", out(reg) dynamic, options(nomem, nostack, preserves_flags));
+
+    let mut rela = 0u64;
+    let mut rela_size = 0u64;
+    let mut rela_entry_size = core::mem::size_of::<Elf64Rela>() as u64;
+
+    let mut entry = dynamic;
+    while (*entry).d_tag != DT_NULL {
+        match (*entry).d_tag {
+            DT_RELA => rela = (*entry).d_val,
+            DT_RELASZ => rela_size = (*entry).d_val,
+            DT_RELAENT => rela_entry_size = (*entry).d_val,
+            _ => {}
+        }
+        entry = entry.add(1);
+    }
+
+    if rela == 0 || rela_entry_size == 0 {
+        return;
+    }
+
+    // 动态段中记录的是链接时地址，需要加上偏移量
+    let table = rela.wrapping_add(slide);
+    for i in 0..rela_size / rela_entry_size {
+        let rela = &*((table + i * rela_entry_size) as *const Elf64Rela);
+        if rela.r_info as u32 == R_X86_64_RELATIVE {
+            let target = rela.r_offset.wrapping_add(slide) as *mut u64;
+            target.write_volatile((rela.r_addend as u64).wrapping_add(slide));
+        }
+    }
+}
+
+/// 把尚未重定位的映像复制到链接地址（Multiboot 2 路径下的 `nokaslr`）
+///
+/// `is_free` 判断目标区域是否为空闲的可用内存。目标区域与当前映像或当前栈重叠时放弃，
+/// 返回 false，映像留在原处照常自重定位；返回 true 时调用者应跳到副本中重新进入。
+///
+/// # Safety
+///
+/// 必须在内核入口处、`apply_relocations` 之前调用，此时映像中的数据仍是链接时的值。
+#[cfg(feature = "multiboot2")]
+pub unsafe fn move_to_link_base(is_free: impl Fn(u64, u64) -> bool) -> bool {
+    let base = runtime_base();
+    let size = image_size();
+    let (start, end) = (KERNEL_LINK_BASE, KERNEL_LINK_BASE + size);
+    let rsp: u64;
+    asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    let overlaps = |from: u64, to: u64| from < end && start < to;
+    if base == start || overlaps(base, base + size) || overlaps(rsp.saturating_sub(STACK_MARGIN), rsp) || !is_free(start, end) {
+        return false;
+    }
+    core::ptr::copy_nonoverlapping(base as *const u8, start as *mut u8, size as usize);
+    true
+}
+
+/// 记录内核映像布局并输出 KASLR 状态
+pub fn init(boot_info: &dyn BootInfo) {
+    let virtual_base = boot_info.kernel_virtual_base().unwrap_or_else(runtime_base);
+    let layout = LAYOUT.call_once(|| KernelLayout {
+        virtual_base,
+        physical_base: boot_info.kernel_physical_base(),
+        slide: virtual_base.wrapping_sub(KERNEL_LINK_BASE),
+        size: image_size(),
+    });
+
+    log::info!(
+        "Kernel image: virt base {:#x}, phys base {}, slide {:#x}, size {:#x}",
+        layout.virtual_base,
+        PhysBase(layout.physical_base),
+        layout.slide,
+        layout.size
+    );
+
+    if crate::cmdline::has_flag("nokaslr") {
+        if layout.slide == 0 {
+            log::info!("KASLR disabled (nokaslr)");
+        } else if cfg!(feature = "multiboot2") {
+            log::warn!("nokaslr: link address is not free, kernel left at the bootloader's base");
+        } else {
+            // 加载位置在内核运行之前就已由引导加载程序决定
+            log::warn!("nokaslr cannot take effect: the bootloader already placed the kernel; disable KASLR in the bootloader config");
+        }
+    } else if layout.slide != 0 {
+        log::info!("KASLR active");
+    } else {
+        log::info!("KASLR inactive, kernel at its link address");
+    }
+}
+
+/// 获取内核映像布局（未初始化时返回 None）
+pub fn layout() -> Option<&'static KernelLayout> {
+    LAYOUT.r#try()
+}
+
+/// 物理基址的格式化辅助类型
+struct PhysBase(Option<u64>);
+
+impl core::fmt::Display for PhysBase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            Some(addr) => write!(f, "{addr:#x}"),
+            None => write!(f, "unknown"),
+        }
+    }
+}
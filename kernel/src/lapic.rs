@@ -0,0 +1,411 @@
+//! 本地 APIC 模块
+//! 支持 xAPIC（MMIO）与 x2APIC（MSR）两种访问方式，对外提供统一接口：
+//! 启用、LVT 配置、NMI 引脚、EOI、处理器间中断与本地定时器
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use x86_64::registers::model_specific::Msr;
+use crate::acpi::{Polarity, TriggerMode};
+use crate::clockevent::{ClockEvent, EventMode};
+use crate::constants::apic::{LAPIC_SPURIOUS_VECTOR, LOCAL_TIMER_VECTOR};
+use crate::constants::pit::CALIBRATION_US;
+use crate::cpu::{self, Feature};
+use crate::error::{KernelResult, KernelError};
+use crate::interrupts::TrapFrame;
+
+/// IA32_APIC_BASE MSR
+const IA32_APIC_BASE: u32 = 0x1b;
+/// IA32_APIC_BASE：APIC 全局启用
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// IA32_APIC_BASE：x2APIC 模式
+const APIC_BASE_EXTD: u64 = 1 << 10;
+/// IA32_APIC_BASE：基址掩码
+const APIC_BASE_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// 寄存器偏移
+const REG_ID: u32 = 0x20;
+const REG_VERSION: u32 = 0x30;
+const REG_TPR: u32 = 0x80;
+const REG_EOI: u32 = 0xb0;
+const REG_SVR: u32 = 0xf0;
+const REG_ESR: u32 = 0x280;
+const REG_ICR_LOW: u32 = 0x300;
+const REG_ICR_HIGH: u32 = 0x310;
+const REG_LVT_TIMER: u32 = 0x320;
+const REG_LVT_THERMAL: u32 = 0x330;
+const REG_LVT_PERF: u32 = 0x340;
+const REG_LVT_LINT0: u32 = 0x350;
+const REG_LVT_LINT1: u32 = 0x360;
+const REG_LVT_ERROR: u32 = 0x370;
+const REG_TIMER_INITIAL: u32 = 0x380;
+const REG_TIMER_CURRENT: u32 = 0x390;
+const REG_TIMER_DIVIDE: u32 = 0x3e0;
+
+/// x2APIC 寄存器 MSR 基址（MSR = 基址 + MMIO 偏移 / 16）
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+/// ICR：投递未完成（仅 xAPIC）
+const ICR_SEND_PENDING: u32 = 1 << 12;
+/// ICR：NMI 投递模式
+const ICR_DELIVERY_NMI: u32 = 0b100 << 8;
+/// ICR：INIT 投递模式
+const ICR_DELIVERY_INIT: u32 = 0b101 << 8;
+/// ICR：启动（SIPI）投递模式
+const ICR_DELIVERY_STARTUP: u32 = 0b110 << 8;
+/// ICR：电平有效
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+/// ICR：目标简写为除自己以外的所有 CPU
+const ICR_ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+
+/// SVR：APIC 软件启用
+const SVR_ENABLE: u32 = 1 << 8;
+
+/// LVT 字段
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+const LVT_DELIVERY_EXTINT: u32 = 0b111 << 8;
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+const LVT_TIMER_TSC_DEADLINE: u32 = 0b10 << 17;
+
+/// IA32_TSC_DEADLINE MSR：TSC 到达该值时触发定时器中断，写 0 解除
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
+
+/// 定时器分频配置：16 分频
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+/// 本地 APIC 定时器（16 分频后）的频率（Hz），校准前为 0
+static TIMER_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// 单次事件使用 TSC-deadline 模式
+static TSC_DEADLINE: AtomicBool = AtomicBool::new(false);
+
+/// 本地 APIC 访问方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    Disabled = 0,
+    /// 通过 MMIO 访问，APIC ID 为 8 位
+    XApic = 1,
+    /// 通过 MSR 访问，APIC ID 为 32 位
+    X2Apic = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Disabled as u8);
+
+/// xAPIC 寄存器的虚拟地址
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+/// 当前访问方式
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Acquire) {
+        1 => Mode::XApic,
+        2 => Mode::X2Apic,
+        _ => Mode::Disabled,
+    }
+}
+
+/// 读寄存器
+fn read(reg: u32) -> u32 {
+    match mode() {
+        Mode::X2Apic => unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).read() as u32 },
+        _ => {
+            let base = BASE.load(Ordering::Acquire);
+            unsafe { core::ptr::read_volatile((base + reg as u64) as *const u32) }
+        }
+    }
+}
+
+/// 写寄存器
+fn write(reg: u32, value: u32) {
+    match mode() {
+        Mode::X2Apic => unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).write(value as u64) },
+        _ => {
+            let base = BASE.load(Ordering::Acquire);
+            unsafe { core::ptr::write_volatile((base + reg as u64) as *mut u32, value) }
+        }
+    }
+}
+
+/// 启用 BSP 的本地 APIC
+pub fn init() -> KernelResult<()> {
+    if !cpu::has(Feature::Apic) {
+        return Err(KernelError::HardwareError);
+    }
+
+    let apic_base = unsafe { Msr::new(IA32_APIC_BASE).read() };
+    // 固件已切换到 x2APIC 时无法退回 xAPIC（需要先整体禁用 APIC）
+    let firmware_x2apic = apic_base & (APIC_BASE_ENABLE | APIC_BASE_EXTD) == APIC_BASE_ENABLE | APIC_BASE_EXTD;
+    let mode = if firmware_x2apic || (cpu::has(Feature::X2apic) && !crate::cmdline::has_flag("nox2apic")) {
+        Mode::X2Apic
+    } else {
+        Mode::XApic
+    };
+
+    if mode == Mode::XApic {
+        let phys = crate::acpi::madt()
+            .map(|madt| madt.local_apic_address)
+            .unwrap_or(apic_base & APIC_BASE_MASK);
+        let virt = crate::memory::map_mmio(phys, 0x1000)?;
+        BASE.store(virt.as_u64(), Ordering::Release);
+        log::info!("LAPIC: xAPIC mode, MMIO at {phys:#x}");
+    } else {
+        log::info!("LAPIC: x2APIC mode");
+    }
+    MODE.store(mode as u8, Ordering::Release);
+
+    init_local();
+    // 在 I/O APIC 接管之前，8259 的中断经 LINT0 以虚拟线模式送达
+    set_virtual_wire(true);
+    log::info!("LAPIC: id {}, version {:#x}", id(), read(REG_VERSION) & 0xff);
+    Ok(())
+}
+
+/// 按选定的访问方式启用当前 CPU 的本地 APIC
+fn enable() {
+    let mut msr = Msr::new(IA32_APIC_BASE);
+    unsafe {
+        let mut value = msr.read() | APIC_BASE_ENABLE;
+        if mode() == Mode::X2Apic {
+            // 必须在 APIC 已启用的状态下再置位 EXTD
+            msr.write(value);
+            value |= APIC_BASE_EXTD;
+        }
+        msr.write(value);
+    }
+}
+
+/// 初始化当前 CPU 的本地 APIC：屏蔽所有 LVT、按 MADT 配置 NMI 引脚并软件启用
+pub fn init_local() {
+    enable();
+    write(REG_TPR, 0);
+    for reg in [REG_LVT_TIMER, REG_LVT_THERMAL, REG_LVT_PERF, REG_LVT_LINT0, REG_LVT_LINT1, REG_LVT_ERROR] {
+        write(reg, LVT_MASKED);
+    }
+    configure_nmi_pins();
+
+    // 写 ESR 后再读才能得到最新的错误状态，这里只用于清除
+    write(REG_ESR, 0);
+    write(REG_ESR, 0);
+    write(REG_SVR, SVR_ENABLE | LAPIC_SPURIOUS_VECTOR as u32);
+    eoi();
+}
+
+/// 按 MADT 中的 LAPIC NMI 表项设置 LINT0/LINT1
+fn configure_nmi_pins() {
+    let Some(madt) = crate::acpi::madt() else { return };
+    let apic_id = id();
+    let uid = madt.local_apics().find(|lapic| lapic.apic_id == apic_id).map(|lapic| lapic.processor_uid);
+
+    for nmi in madt.local_apic_nmis() {
+        if nmi.processor_uid != u32::MAX && Some(nmi.processor_uid) != uid {
+            continue;
+        }
+        let mut lvt = LVT_DELIVERY_NMI;
+        if nmi.flags.polarity() == Polarity::ActiveLow {
+            lvt |= LVT_ACTIVE_LOW;
+        }
+        // NMI 只能边沿触发，电平标志仅记录
+        if nmi.flags.trigger_mode() == TriggerMode::Level {
+            log::warn!("LAPIC: LINT{} NMI declared level-triggered, using edge", nmi.lint);
+        }
+        match nmi.lint {
+            0 => write(REG_LVT_LINT0, lvt),
+            1 => write(REG_LVT_LINT1, lvt),
+            other => log::warn!("LAPIC: invalid LINT{other} in MADT"),
+        }
+    }
+}
+
+/// 设置 LINT0 的虚拟线模式（ExtINT），NMI 占用 LINT0 时不做修改
+pub fn set_virtual_wire(enabled: bool) {
+    let lint0 = read(REG_LVT_LINT0);
+    if lint0 & (0b111 << 8) == LVT_DELIVERY_NMI && lint0 & LVT_MASKED == 0 {
+        return;
+    }
+    write(REG_LVT_LINT0, if enabled { LVT_DELIVERY_EXTINT } else { LVT_MASKED });
+}
+
+/// 本地 APIC 是否已启用
+pub fn is_enabled() -> bool {
+    mode() != Mode::Disabled
+}
+
+/// 当前 CPU 的 APIC ID（xAPIC 为 8 位，x2APIC 为 32 位）
+pub fn id() -> u32 {
+    match mode() {
+        Mode::X2Apic => read(REG_ID),
+        _ => read(REG_ID) >> 24,
+    }
+}
+
+/// 发送处理器间中断
+/// `command` 为 ICR 低 32 位（向量、投递模式、目标简写等），`destination` 为目标 APIC ID
+pub fn send_ipi(destination: u32, command: u32) {
+    match mode() {
+        // x2APIC 的 ICR 是单个 64 位 MSR，目标 ID 位于高 32 位，写入即发送
+        Mode::X2Apic => unsafe {
+            Msr::new(X2APIC_MSR_BASE + (REG_ICR_LOW >> 4)).write((destination as u64) << 32 | command as u64);
+        },
+        // xAPIC 先写目标（高 8 位），写低位时触发发送，并等待投递完成
+        _ => {
+            write(REG_ICR_HIGH, destination << 24);
+            write(REG_ICR_LOW, command);
+            while read(REG_ICR_LOW) & ICR_SEND_PENDING != 0 {
+                spin_loop();
+            }
+        }
+    }
+}
+
+/// 向除当前 CPU 以外的所有 CPU 发送固定投递的中断
+pub fn send_ipi_all_but_self(vector: u8) {
+    send_ipi(0, ICR_ALL_EXCLUDING_SELF | vector as u32);
+}
+
+/// 向目标 CPU 发送 INIT IPI，使其进入等待 SIPI 的状态
+pub fn send_init(destination: u32) {
+    send_ipi(destination, ICR_DELIVERY_INIT | ICR_LEVEL_ASSERT);
+}
+
+/// 向目标 CPU 发送启动 IPI，使其在实模式下从物理地址 `page << 12` 开始执行
+pub fn send_startup(destination: u32, page: u8) {
+    send_ipi(destination, ICR_DELIVERY_STARTUP | ICR_LEVEL_ASSERT | page as u32);
+}
+
+/// 向目标 CPU 发送 NMI（可在 NMI 上下文中调用）
+///
+/// xAPIC 的 ICR 分两次写入，被打断的代码可能已写好高位、尚未写低位：
+/// 先等它之前的投递完成，发送后恢复原来的高位，被打断的 IPI 仍发往原目标。
+pub fn send_nmi(destination: u32) {
+    if mode() == Mode::X2Apic {
+        send_ipi(destination, ICR_DELIVERY_NMI);
+        return;
+    }
+    let saved = read(REG_ICR_HIGH);
+    while read(REG_ICR_LOW) & ICR_SEND_PENDING != 0 {
+        spin_loop();
+    }
+    send_ipi(destination, ICR_DELIVERY_NMI);
+    write(REG_ICR_HIGH, saved);
+}
+
+/// 把性能计数器溢出中断配置为 NMI（投递后硬件会自动屏蔽该 LVT，处理程序中需重新调用）
+pub fn set_perf_nmi() {
+    write(REG_LVT_PERF, LVT_DELIVERY_NMI);
+}
+
+/// 测量执行 `f` 期间本地 APIC 定时器（16 分频）走过的计数
+pub fn timer_ticks_during(f: impl FnOnce()) -> u32 {
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write(REG_LVT_TIMER, LVT_MASKED);
+    write(REG_TIMER_INITIAL, u32::MAX);
+    f();
+    let elapsed = u32::MAX - read(REG_TIMER_CURRENT);
+    write(REG_TIMER_INITIAL, 0);
+    elapsed
+}
+
+/// 以周期模式启动本地 APIC 定时器（16 分频），每 `count` 个计数产生一次 `vector` 中断
+fn timer_start_periodic(vector: u8, count: u32) {
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+    write(REG_LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+    write(REG_TIMER_INITIAL, count);
+}
+
+/// 校准本地 APIC 定时器（BSP 上调用一次），参照为 HPET 或 PIT 通道 2
+pub fn calibrate_timer() {
+    if !is_enabled() {
+        return;
+    }
+    let mut reference = "";
+    let ticks = timer_ticks_during(|| reference = crate::time::reference_delay_us(CALIBRATION_US));
+    let hz = ticks as u64 * 1_000_000 / CALIBRATION_US;
+    TIMER_HZ.store(hz, Ordering::Relaxed);
+
+    // TSC-deadline 需要已知的 TSC 频率来换算纳秒
+    let deadline = cpu::has(Feature::TscDeadline)
+        && crate::tsc::frequency() != 0
+        && crate::cmdline::value("lapic") != Some("notscdeadline");
+    TSC_DEADLINE.store(deadline, Ordering::Relaxed);
+    log::info!(
+        "LAPIC: timer {} kHz (calibrated against {}), one-shot via {}",
+        hz / 1000,
+        reference,
+        if deadline { "TSC deadline" } else { "initial count" }
+    );
+}
+
+/// 本地 APIC 定时器频率（Hz），未校准时为 0
+pub fn timer_frequency() -> u64 {
+    TIMER_HZ.load(Ordering::Relaxed)
+}
+
+/// 本地定时器中断（由中断分发函数调用，此时中断已关闭）
+pub fn timer_interrupt(frame: &TrapFrame) {
+    crate::softirq::irq_enter();
+    crate::clockevent::handle_event(Some(frame));
+    eoi();
+    crate::softirq::irq_exit();
+}
+
+/// 本地 APIC 定时器作为每个 CPU 的时钟事件设备
+pub struct LapicTimer;
+
+pub static LAPIC_TIMER: LapicTimer = LapicTimer;
+
+impl ClockEvent for LapicTimer {
+    fn name(&self) -> &'static str {
+        if TSC_DEADLINE.load(Ordering::Relaxed) { "lapic-deadline" } else { "lapic" }
+    }
+
+    fn supports(&self, mode: EventMode) -> bool {
+        mode == EventMode::Shutdown || timer_frequency() != 0
+    }
+
+    fn set_periodic(&self, hz: u64) -> KernelResult<()> {
+        if timer_frequency() == 0 || hz == 0 {
+            return Err(KernelError::InvalidParameter);
+        }
+        if TSC_DEADLINE.load(Ordering::Relaxed) {
+            unsafe { Msr::new(IA32_TSC_DEADLINE).write(0) };
+        }
+        timer_start_periodic(LOCAL_TIMER_VECTOR, (timer_frequency() / hz).clamp(1, u32::MAX as u64) as u32);
+        Ok(())
+    }
+
+    fn set_next_event(&self, delta_ns: u64) -> KernelResult<()> {
+        let hz = timer_frequency();
+        if hz == 0 {
+            return Err(KernelError::InvalidParameter);
+        }
+        if TSC_DEADLINE.load(Ordering::Relaxed) {
+            let cycles = crate::tsc::ns_to_cycles(delta_ns);
+            write(REG_LVT_TIMER, LVT_TIMER_TSC_DEADLINE | LOCAL_TIMER_VECTOR as u32);
+            // 切换到 TSC-deadline 模式的 LVT 写入必须先于 WRMSR 生效（SDM 10.5.4.1）
+            unsafe {
+                core::arch::asm!("mfence", options(nostack, preserves_flags));
+                Msr::new(IA32_TSC_DEADLINE).write(crate::tsc::read() + cycles.max(1));
+            }
+        } else {
+            let count = (delta_ns as u128 * hz as u128 / 1_000_000_000).clamp(1, u32::MAX as u128) as u32;
+            write(REG_TIMER_DIVIDE, TIMER_DIVIDE_16);
+            write(REG_LVT_TIMER, LOCAL_TIMER_VECTOR as u32);
+            write(REG_TIMER_INITIAL, count);
+        }
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        write(REG_LVT_TIMER, LVT_MASKED);
+        write(REG_TIMER_INITIAL, 0);
+        if TSC_DEADLINE.load(Ordering::Relaxed) {
+            unsafe { Msr::new(IA32_TSC_DEADLINE).write(0) };
+        }
+    }
+}
+
+/// 发送中断结束信号
+pub fn eoi() {
+    write(REG_EOI, 0);
+}
@@ -150,6 +150,18 @@ impl BootInfo for LimineBootInfo {
     fn command_line(&self) -> Option<&str> {
         None
     }
+
+    fn kernel_virtual_base(&self) -> Option<u64> {
+        None
+    }
+
+    fn kernel_physical_base(&self) -> Option<u64> {
+        None
+    }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// 内核入口点
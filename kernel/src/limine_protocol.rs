@@ -5,7 +5,8 @@
 
 use core::arch::asm;
 use core::panic::PanicInfo;
-use crate::boot_info::{BootInfo, FrameBufferInfo, MemoryRegion};
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::boot_info::{BootInfo, FrameBufferInfo, MemoryRegion, MemoryRegionType, MemoryMapStorage};
 
 /// Stivale2 头魔数
 const STIVALE2_HEADER_MAGIC: u64 = 0x73746976616c6532; // "stivale2"
@@ -13,6 +14,216 @@ const STIVALE2_HEADER_MAGIC: u64 = 0x73746976616c6532; // "stivale2"
 /// Stivale2 引导信息魔数
 const STIVALE2_BOOTLOADER_MAGIC: u64 = 0xc7b1dd30df4c8b88;
 
+/// Limine 原生协议通用魔数
+const LIMINE_COMMON_MAGIC: [u64; 2] = [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b];
+/// 内核地址请求（获取 KASLR 后的实际加载地址）
+const LIMINE_KERNEL_ADDRESS_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0x71ba76863cc55f63, 0xb2644a48c516a487
+];
+/// 内存映射请求
+const LIMINE_MEMMAP_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0x67cf3d9d378a806f, 0xe304acdfc50c3c62
+];
+/// 内核文件请求（获取内核命令行）
+const LIMINE_KERNEL_FILE_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0xad97e90e83f1ed67, 0x31eb5d1c5ff23b69
+];
+/// 高半区直接映射请求（获取物理内存映射偏移）
+const LIMINE_HHDM_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0x48dcf1cb8ad2b852, 0x63984e959a98244b
+];
+/// RSDP 请求（获取 ACPI 根表指针）
+const LIMINE_RSDP_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0xc5e77b6b397e7b43, 0x27637845accdcf3c
+];
+
+/// 多处理器请求（由引导加载程序启动 AP 并让其等待跳转地址）
+const LIMINE_SMP_REQUEST: [u64; 4] = [
+    LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1],
+    0x95a67b819a1b857e, 0xa0b61b723b6a73e0
+];
+
+/// Limine 原生协议请求结构
+#[repr(C)]
+pub struct LimineRequest {
+    id: [u64; 4],
+    revision: u64,
+    response: *mut (),
+}
+
+// Limine 协议要求请求放在静态变量中，响应指针由引导加载程序写入
+unsafe impl Sync for LimineRequest {}
+
+impl LimineRequest {
+    pub const fn new(id: [u64; 4]) -> Self {
+        Self {
+            id,
+            revision: 0,
+            response: core::ptr::null_mut(),
+        }
+    }
+
+    /// 读取响应指针
+    /// 响应由引导加载程序在内核运行前写入，必须使用 volatile 读取以免被常量折叠
+    pub fn response<T>(&self) -> Option<&'static T> {
+        unsafe {
+            let ptr = core::ptr::read_volatile(&self.response) as *const T;
+            ptr.as_ref()
+        }
+    }
+}
+
+/// 多处理器请求结构（比通用请求多一个标志字段）
+#[repr(C)]
+pub struct LimineSmpRequest {
+    request: LimineRequest,
+    /// 位 0：请求引导加载程序启用 x2APIC（不使用，由内核自行切换）
+    flags: u64,
+}
+
+/// 多处理器响应
+#[repr(C)]
+pub struct LimineSmpResponse {
+    revision: u64,
+    flags: u32,
+    pub bsp_lapic_id: u32,
+    cpu_count: u64,
+    cpus: *const *const LimineSmpInfo,
+}
+
+/// 单个处理器的信息，AP 在 `goto_address` 被写入后跳转过去，参数为本结构的地址
+#[repr(C)]
+pub struct LimineSmpInfo {
+    pub processor_id: u32,
+    pub lapic_id: u32,
+    reserved: u64,
+    goto_address: AtomicU64,
+    extra_argument: AtomicU64,
+}
+
+/// AP 的入口函数
+pub type LimineApEntry = extern "C" fn(info: &'static LimineSmpInfo) -> !;
+
+impl LimineSmpInfo {
+    /// 内核通过 `start` 传入的参数
+    pub fn argument(&self) -> u64 {
+        self.extra_argument.load(Ordering::Acquire)
+    }
+
+    /// 让 AP 跳转到 `entry`；参数必须先于跳转地址写入
+    pub fn start(&self, entry: LimineApEntry, argument: u64) {
+        self.extra_argument.store(argument, Ordering::Release);
+        self.goto_address.store(entry as usize as u64, Ordering::Release);
+    }
+}
+
+/// 引导加载程序报告的全部处理器（包括 BSP），未响应 SMP 请求时为 None
+pub fn smp_processors() -> Option<(u32, impl Iterator<Item = &'static LimineSmpInfo>)> {
+    let response = SMP_REQUEST.request.response::<LimineSmpResponse>()?;
+    let cpus = (0..response.cpu_count as usize).filter_map(move |i| unsafe { (*response.cpus.add(i)).as_ref() });
+    Some((response.bsp_lapic_id, cpus))
+}
+
+/// 内核地址响应
+#[repr(C)]
+pub struct LimineKernelAddressResponse {
+    revision: u64,
+    pub physical_base: u64,
+    pub virtual_base: u64,
+}
+
+/// 高半区直接映射响应
+#[repr(C)]
+pub struct LimineHhdmResponse {
+    revision: u64,
+    pub offset: u64,
+}
+
+/// RSDP 响应
+#[repr(C)]
+pub struct LimineRsdpResponse {
+    revision: u64,
+    /// 基础修订版 3 之前为高半区虚拟地址
+    pub address: u64,
+}
+
+/// 内存映射响应
+#[repr(C)]
+pub struct LimineMemmapResponse {
+    revision: u64,
+    entry_count: u64,
+    entries: *const *const LimineMemmapEntry,
+}
+
+/// 内存映射条目
+#[repr(C)]
+pub struct LimineMemmapEntry {
+    pub base: u64,
+    pub length: u64,
+    pub entry_type: u64,
+}
+
+/// 内核文件响应
+#[repr(C)]
+pub struct LimineKernelFileResponse {
+    revision: u64,
+    kernel_file: *const LimineFile,
+}
+
+/// Limine 文件描述结构（只使用前面的字段）
+#[repr(C)]
+pub struct LimineFile {
+    revision: u64,
+    address: *mut u8,
+    size: u64,
+    path: *const u8,
+    cmdline: *const u8,
+}
+
+#[used]
+#[link_section = ".requests"]
+static KERNEL_ADDRESS_REQUEST: LimineRequest = LimineRequest::new(LIMINE_KERNEL_ADDRESS_REQUEST);
+
+#[used]
+#[link_section = ".requests"]
+static HHDM_REQUEST: LimineRequest = LimineRequest::new(LIMINE_HHDM_REQUEST);
+
+#[used]
+#[link_section = ".requests"]
+static MEMMAP_REQUEST: LimineRequest = LimineRequest::new(LIMINE_MEMMAP_REQUEST);
+
+#[used]
+#[link_section = ".requests"]
+static RSDP_REQUEST: LimineRequest = LimineRequest::new(LIMINE_RSDP_REQUEST);
+
+#[used]
+#[link_section = ".requests"]
+static SMP_REQUEST: LimineSmpRequest = LimineSmpRequest {
+    request: LimineRequest::new(LIMINE_SMP_REQUEST),
+    flags: 0,
+};
+
+#[used]
+#[link_section = ".requests"]
+static KERNEL_FILE_REQUEST: LimineRequest = LimineRequest::new(LIMINE_KERNEL_FILE_REQUEST);
+
+/// 将以 NUL 结尾的 C 字符串转换为 &str
+unsafe fn c_str(ptr: *const u8) -> Option<&'static str> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).ok()
+}
+
 /// Stivale2 头结构
 /// 必须在 ELF 文件的前 64KB 内，并且 16 字节对齐
 #[repr(C, align(16))]
@@ -136,11 +347,26 @@ pub extern "C" fn _start() -> ! {
 /// Limine 引导信息结构
 pub struct LimineBootInfo {
     raw_ptr: u64,
+    kernel_address: Option<&'static LimineKernelAddressResponse>,
+    hhdm: Option<&'static LimineHhdmResponse>,
+    rsdp: Option<&'static LimineRsdpResponse>,
+    cmdline: Option<&'static str>,
 }
 
 impl LimineBootInfo {
     pub fn new(ptr: u64) -> Self {
-        Self { raw_ptr: ptr }
+        let cmdline = KERNEL_FILE_REQUEST
+            .response::<LimineKernelFileResponse>()
+            .and_then(|response| unsafe { response.kernel_file.as_ref() })
+            .and_then(|file| unsafe { c_str(file.cmdline) });
+
+        Self {
+            raw_ptr: ptr,
+            kernel_address: KERNEL_ADDRESS_REQUEST.response(),
+            hhdm: HHDM_REQUEST.response(),
+            rsdp: RSDP_REQUEST.response(),
+            cmdline,
+        }
     }
 }
 
@@ -156,30 +382,68 @@ impl BootInfo for LimineBootInfo {
     }
 
     fn memory_regions(&self) -> &[MemoryRegion] {
-        // TODO: 从 Limine 引导信息中解析内存映射
-        &[]
+        static MEMORY_MAP: spin::Once<MemoryMapStorage> = spin::Once::new();
+        MEMORY_MAP.call_once(|| {
+            let Some(response) = MEMMAP_REQUEST.response::<LimineMemmapResponse>() else {
+                return MemoryMapStorage::from_regions(core::iter::empty());
+            };
+            let regions = (0..response.entry_count as usize).filter_map(|i| unsafe {
+                let entry = (*response.entries.add(i)).as_ref()?;
+                Some(MemoryRegion {
+                    start: entry.base,
+                    end: entry.base + entry.length,
+                    region_type: match entry.entry_type {
+                        0 => MemoryRegionType::Usable,
+                        2 => MemoryRegionType::AcpiReclaimable,
+                        3 => MemoryRegionType::AcpiNvs,
+                        4 => MemoryRegionType::BadMemory,
+                        5 => MemoryRegionType::BootloaderReclaimable,
+                        6 => MemoryRegionType::KernelAndModules,
+                        7 => MemoryRegionType::Framebuffer,
+                        _ => MemoryRegionType::Reserved,
+                    },
+                })
+            });
+            MemoryMapStorage::from_regions(regions)
+        }).as_slice()
     }
 
     fn rsdp_address(&self) -> Option<u64> {
-        // TODO: 从 Limine 引导信息中解析 RSDP 地址
-        None
+        let address = self.rsdp?.address;
+        // 响应中是 HHDM 中的虚拟地址，换算回物理地址
+        match self.physical_memory_offset() {
+            Some(offset) if address >= offset => Some(address - offset),
+            _ => Some(address),
+        }
     }
 
     fn command_line(&self) -> Option<&str> {
-        // TODO: 从 Limine 引导信息中解析命令行参数
-        None
+        self.cmdline
+    }
+
+    fn kernel_virtual_base(&self) -> Option<u64> {
+        self.kernel_address.map(|address| address.virtual_base)
+    }
+
+    fn kernel_physical_base(&self) -> Option<u64> {
+        self.kernel_address.map(|address| address.physical_base)
+    }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        self.hhdm.map(|hhdm| hhdm.offset)
     }
 }
 
 /// Panic 处理程序
 #[cfg(feature = "limine")]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
+fn panic(info: &PanicInfo) -> ! {
+    // 先写出暂存的日志，再不经串口锁输出 panic 信息
+    crate::logging::enter_panic();
     unsafe {
         print_early("\n=== KERNEL PANIC ===\n");
-        // 尝试输出 panic 信息（简化版）
-        print_early("Panic occurred!\n");
     }
+    crate::serial::emergency_write(format_args!("{}\n", info));
 
     // 无限循环
     loop {
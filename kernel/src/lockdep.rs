@@ -0,0 +1,289 @@
+//! 锁依赖验证模块（调试特性 `lockdep`）
+//! 记录锁类与每个 CPU 上的加锁顺序，在真正死锁之前报告：
+//! - 加锁顺序反转形成的环（A→B 与 B→A）
+//! - 同一把锁既在硬中断中获取、又在开中断时获取（HARDIRQ-unsafe）
+//! - 同一把锁既在软中断中获取、又在软中断可以打断的进程上下文中获取（SOFTIRQ-unsafe）
+//! - 同一 CPU 上递归获取同一把锁
+//!
+//! 锁类以锁实例的地址区分，报告中给出各次获取的调用位置。
+//! 发现第一个问题后即停止验证，避免报告刷屏。
+
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::constants::lockdep::{MAX_HELD_LOCKS, MAX_LOCK_CLASSES};
+use crate::per_cpu;
+
+/// 获取位置
+type Site = &'static Location<'static>;
+
+/// 验证器是否仍在工作
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// 锁类
+#[derive(Clone, Copy)]
+struct LockClass {
+    /// 锁实例地址
+    key: usize,
+    /// 在硬中断中获取过的位置
+    in_hardirq: Option<Site>,
+    /// 在软中断中获取过的位置
+    in_softirq: Option<Site>,
+    /// 开中断时获取过的位置
+    hardirqs_enabled: Option<Site>,
+    /// 软中断可以打断时获取过的位置
+    softirqs_enabled: Option<Site>,
+}
+
+/// 锁类与依赖图：`edges[a][b]` 表示持有 a 时获取过 b，值为首次获取 b 的位置
+struct Graph {
+    classes: [Option<LockClass>; MAX_LOCK_CLASSES],
+    edges: [[Option<Site>; MAX_LOCK_CLASSES]; MAX_LOCK_CLASSES],
+}
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph {
+    classes: [None; MAX_LOCK_CLASSES],
+    edges: [[None; MAX_LOCK_CLASSES]; MAX_LOCK_CLASSES],
+});
+
+/// 当前 CPU 持有的一把锁
+#[derive(Clone, Copy)]
+struct HeldLock {
+    class: usize,
+    site: Site,
+}
+
+/// 当前 CPU 的持锁栈
+struct HeldLocks {
+    locks: [Option<HeldLock>; MAX_HELD_LOCKS],
+    depth: usize,
+}
+
+per_cpu! {
+    // 当前 CPU 按获取顺序持有的锁
+    static HELD: Mutex<HeldLocks> = Mutex::new(HeldLocks { locks: [None; MAX_HELD_LOCKS], depth: 0 });
+    // 正在执行验证（验证器自身输出日志时获取的锁不再记录）
+    static BUSY: AtomicBool = AtomicBool::new(false);
+}
+
+impl Graph {
+    /// 查找或登记锁类，表满时返回 None
+    fn class(&mut self, key: usize) -> Option<usize> {
+        if let Some(index) = self.classes.iter().position(|c| c.is_some_and(|c| c.key == key)) {
+            return Some(index);
+        }
+        let index = self.classes.iter().position(Option::is_none)?;
+        self.classes[index] = Some(LockClass {
+            key,
+            in_hardirq: None,
+            in_softirq: None,
+            hardirqs_enabled: None,
+            softirqs_enabled: None,
+        });
+        Some(index)
+    }
+
+    /// 按依赖边从 `from` 广度优先搜索 `to`，找到时把路径（不含 `from`）写入 `path` 并返回长度
+    fn path(&self, from: usize, to: usize, path: &mut [usize; MAX_LOCK_CLASSES]) -> Option<usize> {
+        let mut parent = [usize::MAX; MAX_LOCK_CLASSES];
+        let mut queue = [0usize; MAX_LOCK_CLASSES];
+        let (mut head, mut tail) = (0, 1);
+        queue[0] = from;
+        parent[from] = from;
+        while head < tail {
+            let node = queue[head];
+            head += 1;
+            if node == to {
+                let mut len = 0;
+                let mut cur = to;
+                while cur != from {
+                    path[len] = cur;
+                    len += 1;
+                    cur = parent[cur];
+                }
+                path[..len].reverse();
+                return Some(len);
+            }
+            for (next, edge) in self.edges[node].iter().enumerate() {
+                if edge.is_some() && parent[next] == usize::MAX {
+                    parent[next] = node;
+                    queue[tail] = next;
+                    tail += 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// 锁类对应的锁实例地址
+    fn key(&self, class: usize) -> usize {
+        self.classes[class].expect("lock class registered").key
+    }
+}
+
+/// 关闭验证并输出报告标题
+fn report(title: &str) {
+    ENABLED.store(false, Ordering::Relaxed);
+    log::error!("LOCKDEP: {} on CPU{}", title, crate::smp::cpu_index());
+}
+
+/// 输出当前 CPU 持有的锁
+fn report_held(graph: &Graph, held: &HeldLocks) {
+    log::error!("LOCKDEP: CPU{} holds {} lock(s):", crate::smp::cpu_index(), held.depth);
+    for lock in held.locks[..held.depth].iter().flatten() {
+        log::error!("LOCKDEP:   lock {:#x} acquired at {}", graph.key(lock.class), lock.site);
+    }
+    log::error!("LOCKDEP: lock validator turned off");
+}
+
+/// 锁获取前调用（在真正自旋之前），`key` 为锁实例地址；`trylock` 表示不等待的获取（成功之后才调用）
+pub fn acquire(key: usize, site: Site, trylock: bool) {
+    if !ENABLED.load(Ordering::Relaxed) || BUSY.get().swap(true, Ordering::Acquire) {
+        return;
+    }
+    let irqs_enabled = interrupts::are_enabled();
+    interrupts::without_interrupts(|| check_acquire(key, site, irqs_enabled, trylock));
+    BUSY.get().store(false, Ordering::Release);
+}
+
+/// 锁释放后调用
+pub fn release(key: usize) {
+    if BUSY.get().swap(true, Ordering::Acquire) {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        let graph = GRAPH.lock();
+        let Some(class) = graph.classes.iter().position(|c| c.is_some_and(|c| c.key == key)) else { return };
+        let mut held = HELD.get().lock();
+        let depth = held.depth;
+        // 锁可以不按获取顺序释放，从栈顶开始查找
+        if let Some(index) = held.locks[..depth].iter().rposition(|l| l.is_some_and(|l| l.class == class)) {
+            held.locks.copy_within(index + 1..depth, index);
+            held.locks[depth - 1] = None;
+            held.depth -= 1;
+        }
+    });
+    BUSY.get().store(false, Ordering::Release);
+}
+
+fn check_acquire(key: usize, site: Site, irqs_enabled: bool, trylock: bool) {
+    let mut graph = GRAPH.lock();
+    let Some(class) = graph.class(key) else {
+        ENABLED.store(false, Ordering::Relaxed);
+        log::warn!("LOCKDEP: more than {MAX_LOCK_CLASSES} lock classes, lock validator turned off");
+        return;
+    };
+    let mut held = HELD.get().lock();
+
+    // 递归获取
+    if let Some(previous) = held.locks[..held.depth].iter().flatten().find(|l| l.class == class) {
+        report("recursive locking detected");
+        log::error!("LOCKDEP: lock {key:#x} acquired again at {site}");
+        log::error!("LOCKDEP: already held since {}", previous.site);
+        report_held(&graph, &held);
+        return;
+    }
+
+    // 中断安全性：硬中断中获取过的锁不能在开中断时获取，软中断中获取过的锁不能在软中断可以打断时获取
+    let record = graph.classes[class].as_mut().expect("lock class registered");
+    if crate::softirq::in_hardirq() {
+        record.in_hardirq.get_or_insert(site);
+    } else if crate::softirq::in_softirq() {
+        record.in_softirq.get_or_insert(site);
+    }
+    if irqs_enabled {
+        record.hardirqs_enabled.get_or_insert(site);
+        if crate::softirq::softirqs_enabled() {
+            record.softirqs_enabled.get_or_insert(site);
+        }
+    }
+    let record = *record;
+    if let (Some(irq_site), Some(enabled_site)) = (record.in_hardirq, record.hardirqs_enabled) {
+        report("HARDIRQ-unsafe lock usage detected");
+        log::error!("LOCKDEP: lock {key:#x} is taken in hardirq context at {irq_site}");
+        log::error!("LOCKDEP: and with interrupts enabled at {enabled_site}");
+        log::error!("LOCKDEP: an interrupt arriving while it is held there would deadlock; use IrqSafeSpinlock");
+        report_held(&graph, &held);
+        return;
+    }
+    if let (Some(softirq_site), Some(enabled_site)) = (record.in_softirq, record.softirqs_enabled) {
+        report("SOFTIRQ-unsafe lock usage detected");
+        log::error!("LOCKDEP: lock {key:#x} is taken in softirq context at {softirq_site}");
+        log::error!("LOCKDEP: and with softirqs enabled at {enabled_site}");
+        log::error!("LOCKDEP: a softirq running while it is held there would deadlock; wrap it in local_bh_disable/local_bh_enable");
+        report_held(&graph, &held);
+        return;
+    }
+
+    // 加锁顺序：对每把已持有的锁 h 添加边 h→class，若 class 已能到达 h 则成环；
+    // 不等待的获取不会因顺序死锁，既不检查也不添加边
+    let ordered = if trylock { 0 } else { held.depth };
+    for lock in held.locks[..ordered].iter().flatten() {
+        if graph.edges[lock.class][class].is_some() {
+            continue;
+        }
+        let mut path = [0usize; MAX_LOCK_CLASSES];
+        if let Some(len) = graph.path(class, lock.class, &mut path) {
+            let held_key = graph.key(lock.class);
+            report("possible circular locking dependency detected");
+            log::error!("LOCKDEP: acquiring lock {key:#x} at {site}");
+            log::error!("LOCKDEP: while holding lock {:#x} taken at {}", held_key, lock.site);
+            log::error!("LOCKDEP: existing dependency chain:");
+            let mut from = class;
+            for &to in &path[..len] {
+                let edge_site = graph.edges[from][to].expect("edge on path");
+                log::error!("LOCKDEP:   {:#x} -> {:#x} acquired at {}", graph.key(from), graph.key(to), edge_site);
+                from = to;
+            }
+            report_held(&graph, &held);
+            return;
+        }
+        graph.edges[lock.class][class] = Some(site);
+    }
+
+    let depth = held.depth;
+    if depth == MAX_HELD_LOCKS {
+        ENABLED.store(false, Ordering::Relaxed);
+        log::warn!("LOCKDEP: more than {MAX_HELD_LOCKS} locks held, lock validator turned off");
+        return;
+    }
+    held.locks[depth] = Some(HeldLock { class, site });
+    held.depth += 1;
+}
+
+#[test_case]
+fn test_lock_classes_are_keyed_by_address() {
+    static TEST_GRAPH: Mutex<Graph> = Mutex::new(Graph {
+        classes: [None; MAX_LOCK_CLASSES],
+        edges: [[None; MAX_LOCK_CLASSES]; MAX_LOCK_CLASSES],
+    });
+    let mut graph = TEST_GRAPH.lock();
+    let a = graph.class(0x1000).unwrap();
+    let b = graph.class(0x2000).unwrap();
+    assert_ne!(a, b);
+    assert_eq!(graph.class(0x1000), Some(a));
+    assert_eq!(graph.key(b), 0x2000);
+}
+
+#[test_case]
+fn test_inversion_cycle_is_found() {
+    static TEST_GRAPH: Mutex<Graph> = Mutex::new(Graph {
+        classes: [None; MAX_LOCK_CLASSES],
+        edges: [[None; MAX_LOCK_CLASSES]; MAX_LOCK_CLASSES],
+    });
+    let site = Location::caller();
+    let mut graph = TEST_GRAPH.lock();
+    let [a, b, c] = [0xa000, 0xb000, 0xc000].map(|key| graph.class(key).unwrap());
+    // A→B、B→C 已记录
+    graph.edges[a][b] = Some(site);
+    graph.edges[b][c] = Some(site);
+
+    // 持有 C 时获取 A：A 已能到达 C，新边 C→A 会成环
+    let mut path = [0usize; MAX_LOCK_CLASSES];
+    let len = graph.path(a, c, &mut path).expect("cycle not detected");
+    assert_eq!(&path[..len], &[b, c]);
+
+    // 持有 A 时获取 C 与已有顺序一致
+    assert!(graph.path(c, a, &mut path).is_none());
+}
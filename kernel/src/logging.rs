@@ -1,9 +1,17 @@
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
 use crate::{serial_println_safe, kernel_try};
 use log::{Record, Level, Metadata, LevelFilter};
+use crate::constants::logging::{LOG_BUFFER_SIZE, LOG_FLUSH_CHUNK};
 use crate::error::{KernelResult, KernelError};
+use crate::sync::IrqSafeSpinlock;
+use crate::workqueue::Work;
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
+/// 系统正在 panic：日志不再获取任何锁，直接写串口端口
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 pub fn init() -> KernelResult<()> {
     // 确保串口在使用日志框架之前被初始化
     kernel_try!(crate::serial::init_serial());
@@ -26,10 +34,131 @@ impl log::Log for SimpleLogger {
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            serial_println_safe!("[{}] {}", record.level(), record.args());
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let time = Timestamp::now();
+        if PANICKING.load(Ordering::Relaxed) {
+            crate::serial::emergency_write(format_args!("[{}] [{}] {}\n", time, record.level(), record.args()));
+            return;
+        }
+        // 中断上下文中不做缓慢的同步串口 I/O，交给工作队列写出；
+        // 错误总是立即写出——异常与 panic 报告之后不会再有工作队列运行
+        if record.level() != Level::Error && crate::softirq::in_interrupt() {
+            defer(time, record);
+            return;
         }
+        flush_deferred();
+        serial_println_safe!("[{}] [{}] {}", time, record.level(), record.args());
     }
 
     fn flush(&self) {}
-}
\ No newline at end of file
+}
+
+/// 日志时间戳：墙上时间已知时为 UTC 日期时间，否则为启动以来的秒数
+struct Timestamp {
+    realtime_ns: Option<u64>,
+    monotonic_ns: u64,
+}
+
+impl Timestamp {
+    fn now() -> Self {
+        Timestamp { realtime_ns: crate::time::realtime_ns(), monotonic_ns: crate::time::monotonic_ns() }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.realtime_ns {
+            Some(ns) => write!(
+                f,
+                "{}.{:06}",
+                crate::time::DateTime::from_unix(ns / 1_000_000_000),
+                ns % 1_000_000_000 / 1000
+            ),
+            None => write!(
+                f,
+                "{:>5}.{:06}",
+                self.monotonic_ns / 1_000_000_000,
+                self.monotonic_ns % 1_000_000_000 / 1000
+            ),
+        }
+    }
+}
+
+/// 中断上下文中产生的日志暂存区，由工作队列写出到串口
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    len: usize,
+    /// 因暂存区已满而丢弃的日志条数
+    dropped: usize,
+}
+
+impl Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > LOG_BUFFER_SIZE {
+            return Err(fmt::Error);
+        }
+        self.data[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+static DEFERRED: IrqSafeSpinlock<LogBuffer> = IrqSafeSpinlock::new(LogBuffer { data: [0; LOG_BUFFER_SIZE], len: 0, dropped: 0 });
+
+static FLUSH_WORK: Work = Work::new(|_| flush_deferred(), 0);
+
+/// 把日志写入暂存区并安排刷新
+fn defer(time: Timestamp, record: &Record) {
+    {
+        let mut buffer = DEFERRED.lock();
+        let start = buffer.len;
+        // 写不下时回滚，不留下半条日志
+        if writeln!(*buffer, "[{}] [{}] {}", time, record.level(), record.args()).is_err() {
+            buffer.len = start;
+            buffer.dropped += 1;
+        }
+    }
+    crate::workqueue::schedule_work(&FLUSH_WORK);
+}
+
+/// 把暂存区中的日志写出到串口（只能在进程上下文中调用）
+fn flush_deferred() {
+    loop {
+        let mut chunk = [0u8; LOG_FLUSH_CHUNK];
+        let (len, dropped) = {
+            let mut buffer = DEFERRED.lock();
+            let len = buffer.len.min(LOG_FLUSH_CHUNK);
+            chunk[..len].copy_from_slice(&buffer.data[..len]);
+            let total = buffer.len;
+            buffer.data.copy_within(len..total, 0);
+            buffer.len -= len;
+            let dropped = if buffer.len == 0 { core::mem::take(&mut buffer.dropped) } else { 0 };
+            (len, dropped)
+        };
+        crate::serial::write_bytes(&chunk[..len]);
+        if dropped > 0 {
+            serial_println_safe!("[WARN] {} deferred log message(s) dropped", dropped);
+        }
+        if len < LOG_FLUSH_CHUNK {
+            break;
+        }
+    }
+}
+
+/// panic 处理程序在输出报告前调用：写出暂存区中尚未刷新的日志，此后的日志直接写端口
+pub fn enter_panic() {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    // 本 CPU 可能正是在持有暂存区锁时 panic 的，拿不到锁就放弃这部分日志
+    if let Some(mut buffer) = DEFERRED.try_lock() {
+        // 暂存区只含完整的日志，是合法的 UTF-8
+        if let Ok(text) = core::str::from_utf8(&buffer.data[..buffer.len]) {
+            crate::serial::emergency_write(format_args!("{text}"));
+        }
+        buffer.len = 0;
+    }
+}
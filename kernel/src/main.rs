@@ -6,10 +6,43 @@
 
 use crate::constants::qemu::*;
 
+mod percpu;
+mod sync;
+#[cfg(feature = "lockdep")]
+mod lockdep;
 mod serial;
 mod logging;
 mod constants;
 mod error;
+mod cmdline;
+mod kaslr;
+mod cpu;
+mod fpu;
+mod memory;
+mod gdt;
+mod interrupts;
+mod pic;
+mod acpi;
+mod lapic;
+mod ioapic;
+mod irq;
+mod softirq;
+mod workqueue;
+mod pci;
+mod msi;
+mod vectors;
+mod smp;
+mod ipi;
+mod tlb;
+mod pit;
+mod hpet;
+mod tsc;
+mod time;
+mod rtc;
+mod clockevent;
+mod watchdog;
+mod mce;
+mod wx;
 
 // 引导信息抽象层
 pub mod boot_info;
@@ -24,7 +57,9 @@ pub mod multiboot2;
 
 // 默认使用 bootloader_api（向后兼容）
 #[cfg(not(any(feature = "limine", feature = "multiboot2")))]
-use bootloader_api::{BootInfo, entry_point};
+use bootloader_api::{BootInfo, BootloaderConfig, entry_point};
+#[cfg(not(any(feature = "limine", feature = "multiboot2")))]
+use bootloader_api::config::Mapping;
 
 /// 帧缓冲区包装类型
 pub struct FrameBufferWrapper {
@@ -41,9 +76,20 @@ pub struct FrameBufferInfo {
     pub bytes_per_pixel: usize,
 }
 
+/// bootloader_api 引导配置
+#[cfg(not(any(feature = "limine", feature = "multiboot2")))]
+pub static BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    // 内核以 PIE 形式链接，允许引导加载程序随机化加载地址（构建特性 `nokaslr` 关闭）
+    config.mappings.aslr = !cfg!(feature = "nokaslr");
+    // 映射全部物理内存，内核通过它访问页表
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    config
+};
+
 /// 内核主函数 - 被引导加载程序调用 (bootloader_api)
 #[cfg(not(any(feature = "limine", feature = "multiboot2")))]
-entry_point!(kernel_main);
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 
 #[cfg(not(any(feature = "limine", feature = "multiboot2")))]
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
@@ -51,35 +97,39 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let _framebuffer = boot_info.framebuffer.as_mut().expect("No framebuffer provided");
     
     // 调用通用的内核初始化
-    kernel_init_common();
+    let boot_info = boot_info::BootInfoWrapper::BootloaderApi(boot_info);
+    kernel_init_common(&boot_info);
 }
 
 /// Limine 引导入口点
 #[cfg(feature = "limine")]
-pub fn kernel_main_limine(_boot_info: &limine_protocol::LimineBootInfo) -> ! {
+pub fn kernel_main_limine(boot_info: &limine_protocol::LimineBootInfo) -> ! {
     // 使用早期串口输出（不依赖日志系统）
     unsafe {
         limine_protocol::print_early("=== kernel_main_limine ===\n");
     }
     
     // 调用通用的内核初始化
-    kernel_init_common();
+    kernel_init_common(boot_info);
 }
 
 /// Multiboot 2 引导入口点
 #[cfg(feature = "multiboot2")]
-pub fn kernel_main_multiboot2(_boot_info: &multiboot2::Multiboot2BootInfo) -> ! {
+pub fn kernel_main_multiboot2(boot_info: &multiboot2::Multiboot2BootInfo) -> ! {
     // 使用早期串口输出（不依赖日志系统）
     unsafe {
         multiboot2::print_early("=== kernel_main_multiboot2 ===\n");
     }
     
     // 调用通用的内核初始化
-    kernel_init_common();
+    kernel_init_common(boot_info);
 }
 
 /// 通用的内核初始化函数
-fn kernel_init_common() -> ! {
+fn kernel_init_common(boot_info: &dyn boot_info::BootInfo) -> ! {
+    // 日志等路径会通过 GS 查询当前 CPU，每 CPU 区域必须最先建立
+    percpu::init(0);
+
     // 使用早期串口输出调试信息（根据特性标志选择）
     #[cfg(feature = "multiboot2")]
     unsafe {
@@ -125,6 +175,79 @@ fn kernel_init_common() -> ! {
     #[cfg(not(any(feature = "limine", feature = "multiboot2")))]
     log::info!("Kernel initialized with bootloader_api!");
 
+    // 保存命令行并记录内核映像布局
+    cmdline::init(boot_info.command_line());
+    log::info!("Command line: \"{}\"", cmdline::get());
+    kaslr::init(boot_info);
+    cpu::init();
+    fpu::init();
+
+    // 接管页表（建立直接映射）并启用内核映像的 W^X 保护
+    if let Err(e) = memory::init(boot_info) {
+        panic!("Failed to initialize paging: {:?}", e);
+    }
+    tlb::init();
+    gdt::init();
+    interrupts::init_idt();
+    // #MC 的 IST 栈与 IDT 就绪后才能打开 CR4.MCE
+    mce::init();
+    if let Err(e) = wx::init() {
+        panic!("Failed to enforce W^X: {:?}", e);
+    }
+    if !wx::self_test() {
+        panic!("W^X self-test failed: kernel image protections are not enforced");
+    }
+    if fpu::kernel_fpu_usable() && !fpu::self_test() {
+        panic!("FPU self-test failed: kernel SIMD sections corrupt the interrupted state");
+    }
+
+    // 调试开关 `int3_test`：触发一次 int3，确认断点异常可恢复
+    if cmdline::has_flag("int3_test") {
+        x86_64::instructions::interrupts::int3();
+        log::info!("Returned from breakpoint exception");
+    }
+
+    if let Err(e) = acpi::init(boot_info) {
+        log::warn!("ACPI unavailable: {e}");
+    }
+
+    // 重映射 PIC；除非指定 noapic，否则由 LAPIC/IOAPIC 接管外部中断
+    pic::init();
+    let chip: &'static dyn irq::IrqChip = if cmdline::has_flag("noapic") {
+        log::info!("APIC disabled (noapic), using 8259 PIC");
+        &pic::PIC
+    } else {
+        match lapic::init().and_then(|()| ioapic::init()) {
+            Ok(()) => {
+                pic::disable();
+                lapic::set_virtual_wire(false);
+                &ioapic::IOAPIC
+            }
+            Err(e) => {
+                log::warn!("APIC setup failed ({e}), falling back to 8259 PIC");
+                &pic::PIC
+            }
+        }
+    };
+    irq::init(chip);
+    pit::init();
+    hpet::init();
+    tsc::init();
+    time::init();
+    clockevent::init();
+    pci::init();
+    lapic::calibrate_timer();
+    watchdog::init();
+    smp::init();
+    x86_64::instructions::interrupts::enable();
+    log::info!("Interrupts enabled");
+    clockevent::setup_cpu();
+    watchdog::start_cpu();
+
+    // 测试构建在内核初始化完成后运行全部 #[test_case]，结束后退出 QEMU
+    #[cfg(test)]
+    test_main();
+
     // 内核启动完成提示
     let startup_messages = [
         "=== UTOPIA KERNEL STARTED ===",
@@ -146,6 +269,8 @@ fn kernel_init_common() -> ! {
     }
     
     log::info!("All startup messages completed");
+    log::info!("CPUs online {}", smp::online_cpus());
+    log::info!("Interrupts:\n{}", irq::stats());
     
     #[cfg(feature = "multiboot2")]
     unsafe {
@@ -162,10 +287,25 @@ fn kernel_init_common() -> ! {
 }
 
 /// 内核主循环
+/// 同时充当工作线程：处理延迟工作与遗留的软中断，空闲时 hlt
 fn kernel_main_loop() -> ! {
-    // 使用hlt指令的无限循环，避免CPU占用过高
+    use x86_64::instructions::interrupts;
+
     loop {
-        x86_64::instructions::hlt();
+        watchdog::touch();
+        workqueue::run_pending();
+        softirq::run_pending();
+
+        // 关中断后再检查一次，避免在检查与 hlt 之间到来的工作被推迟到下一次中断
+        interrupts::disable();
+        if workqueue::has_pending() || softirq::has_pending() {
+            interrupts::enable();
+        } else {
+            // 空闲期间停止周期节拍，醒来后补记
+            clockevent::idle_enter();
+            interrupts::enable_and_hlt();
+            clockevent::idle_exit();
+        }
     }
 }
 
@@ -174,6 +314,8 @@ fn kernel_main_loop() -> ! {
 #[cfg(not(any(feature = "limine", feature = "multiboot2")))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    // 不经过串口锁与日志暂存区输出，panic 可能发生在持有它们的中断处理程序中
+    logging::enter_panic();
     log::error!("[PANIC] {}", info);
     // 禁用中断并halt
     x86_64::instructions::interrupts::disable();
@@ -184,7 +326,7 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 
 #[cfg(test)]
 fn test_runner(tests: &[&dyn Fn()]) {
-    println!("Running {} tests", tests.len());
+    serial_println!("Running {} tests", tests.len());
     for test in tests {
         test();
     }
@@ -215,8 +357,8 @@ pub fn exit_qemu(exit_code: QemuExitCode) {
 #[cfg(test)]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    println!("[failed]\n");
-    println!("Error: {}\n", info);
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
     loop {}
 }
@@ -0,0 +1,370 @@
+//! 机器检查架构（MCA）模块
+//! 启用各 MCi 寄存器组的错误报告并打开 CR4.MCE：
+//! - #MC 异常经独立的 IST 栈进入，逐个寄存器组读取、解码并紧急输出错误记录，
+//!   按严重程度决定继续运行还是 panic
+//! - 本地定时器周期性轮询可纠正错误（不产生 #MC），记录日志后清除
+//! - 启动时输出上次复位前残留的错误记录，便于诊断“无声重启”
+//!
+//! 命令行参数 `mce=off` 关闭机器检查支持。
+
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::registers::model_specific::Msr;
+use crate::constants::apic::LOCAL_TIMER_HZ;
+use crate::constants::mce::{MAX_MCE_BANKS, MCE_POLL_INTERVAL};
+use crate::cpu::{self, Feature};
+use crate::interrupts::TrapFrame;
+use crate::per_cpu;
+use crate::smp::cpu_index;
+
+/// 全局机器检查 MSR
+const IA32_MCG_CAP: u32 = 0x179;
+const IA32_MCG_STATUS: u32 = 0x17a;
+const IA32_MCG_CTL: u32 = 0x17b;
+/// 第 0 组的 MCi_CTL，每组依次为 CTL/STATUS/ADDR/MISC
+const IA32_MC0_CTL: u32 = 0x400;
+
+/// MCG_CAP：寄存器组数量、MCG_CTL 存在、支持软件错误恢复（S/AR 位有效）
+const MCG_BANK_COUNT: u64 = 0xff;
+const MCG_CTL_P: u64 = 1 << 8;
+const MCG_SER_P: u64 = 1 << 24;
+
+/// MCG_STATUS：可从压栈的 RIP 重新执行、RIP 与错误直接相关、机器检查进行中
+const MCG_RIPV: u64 = 1 << 0;
+const MCG_EIPV: u64 = 1 << 1;
+const MCG_MCIP: u64 = 1 << 2;
+
+/// MCi_STATUS 标志位
+const STATUS_VAL: u64 = 1 << 63;
+const STATUS_OVER: u64 = 1 << 62;
+const STATUS_UC: u64 = 1 << 61;
+const STATUS_EN: u64 = 1 << 60;
+const STATUS_MISCV: u64 = 1 << 59;
+const STATUS_ADDRV: u64 = 1 << 58;
+const STATUS_PCC: u64 = 1 << 57;
+const STATUS_S: u64 = 1 << 56;
+const STATUS_AR: u64 = 1 << 55;
+
+/// 已启用的寄存器组数量（0 表示机器检查未启用）
+static BANKS: AtomicUsize = AtomicUsize::new(0);
+
+/// BSP 读到的 IA32_MCG_CAP
+static CAP: AtomicU64 = AtomicU64::new(0);
+
+/// 正在输出 #MC 报告（广播型 #MC 会让多个 CPU 同时进入）
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+per_cpu! {
+    // 本 CPU 收到的 #MC 次数
+    static EXCEPTIONS: AtomicU64 = AtomicU64::new(0);
+    // 本 CPU 执行的轮询次数
+    static POLLS: AtomicU64 = AtomicU64::new(0);
+    // 下一次轮询的本地节拍数
+    static NEXT_POLL: AtomicU64 = AtomicU64::new(0);
+}
+
+/// 紧急输出一行（#MC 可能打断任何持锁代码，不能走普通日志）
+macro_rules! emergency {
+    ($($arg:tt)*) => {
+        crate::serial::emergency_write(format_args!("[ERROR] MCE: {}\n", format_args!($($arg)*)))
+    };
+}
+
+/// 错误的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    /// 硬件已纠正
+    Corrected,
+    /// 未纠正，但处理器状态未被破坏，无需立即处理
+    Uncorrected,
+    /// 处理器上下文已损坏或无法从被打断处继续
+    Fatal,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Corrected => "corrected",
+            Severity::Uncorrected => "uncorrected, recoverable",
+            Severity::Fatal => "uncorrected, fatal",
+        }
+    }
+}
+
+/// 一条 MCi 错误记录
+struct Record {
+    bank: usize,
+    status: u64,
+    addr: Option<u64>,
+    misc: Option<u64>,
+}
+
+impl Record {
+    /// 读取某个寄存器组，没有有效记录时返回 None
+    fn read(bank: usize) -> Option<Self> {
+        let status = unsafe { bank_msr(bank, 1).read() };
+        if status & STATUS_VAL == 0 {
+            return None;
+        }
+        let addr = (status & STATUS_ADDRV != 0).then(|| unsafe { bank_msr(bank, 2).read() });
+        let misc = (status & STATUS_MISCV != 0).then(|| unsafe { bank_msr(bank, 3).read() });
+        Some(Self { bank, status, addr, misc })
+    }
+
+    /// 清除该寄存器组的记录
+    fn clear(&self) {
+        unsafe { bank_msr(self.bank, 1).write(0) };
+    }
+
+    /// 按 SDM 的判定规则评估严重程度，`mcg_status` 为 #MC 时的 IA32_MCG_STATUS（轮询时为 None）
+    fn severity(&self, mcg_status: Option<u64>) -> Severity {
+        if self.status & STATUS_UC == 0 {
+            return Severity::Corrected;
+        }
+        if self.status & STATUS_PCC != 0 {
+            return Severity::Fatal;
+        }
+        let Some(mcg_status) = mcg_status else { return Severity::Uncorrected };
+        if mcg_status & MCG_RIPV == 0 {
+            return Severity::Fatal;
+        }
+        // 需要立即处理（SRAR）的错误意味着被打断的代码消费了坏数据，内核没有可以隔离的任务
+        let ser = CAP.load(Ordering::Relaxed) & MCG_SER_P != 0;
+        if ser && self.status & STATUS_S != 0 && self.status & STATUS_AR != 0 {
+            return Severity::Fatal;
+        }
+        Severity::Uncorrected
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.status as u16;
+        write!(f, "bank {} status {:#018x}: ", self.bank, self.status)?;
+        write_error_code(f, code)?;
+        write!(f, " (model code {:#06x})", (self.status >> 16) as u16)?;
+        let flags = [
+            (STATUS_OVER, "OVER"),
+            (STATUS_UC, "UC"),
+            (STATUS_EN, "EN"),
+            (STATUS_PCC, "PCC"),
+            (STATUS_S, "S"),
+            (STATUS_AR, "AR"),
+        ];
+        for (bit, name) in flags {
+            if self.status & bit != 0 {
+                write!(f, " {name}")?;
+            }
+        }
+        if let Some(addr) = self.addr {
+            write!(f, " addr {addr:#x}")?;
+        }
+        if let Some(misc) = self.misc {
+            write!(f, " misc {misc:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 寄存器组 `bank` 的第 `index` 个 MSR（0 CTL、1 STATUS、2 ADDR、3 MISC）
+fn bank_msr(bank: usize, index: u32) -> Msr {
+    Msr::new(IA32_MC0_CTL + 4 * bank as u32 + index)
+}
+
+/// 解码 MCA 架构错误码（MCi_STATUS[15:0]）
+fn write_error_code(f: &mut fmt::Formatter<'_>, code: u16) -> fmt::Result {
+    const LEVELS: [&str; 4] = ["L0", "L1", "L2", "generic level"];
+    const TRANSACTIONS: [&str; 4] = ["instruction", "data", "generic", "reserved"];
+    const REQUESTS: [&str; 9] =
+        ["generic", "data read", "data write", "data read", "data write", "instruction fetch", "prefetch", "eviction", "snoop"];
+    const MEMORY: [&str; 5] = ["generic", "read", "write", "address/command", "scrubbing"];
+    const PARTICIPATION: [&str; 4] = ["local processor originated", "local processor responded", "local processor observed", "generic"];
+    const BUS_SPACE: [&str; 4] = ["memory", "reserved", "I/O", "other"];
+
+    let level = LEVELS[(code & 0b11) as usize];
+    let request = |code: u16| REQUESTS.get(((code >> 4) & 0xf) as usize).copied().unwrap_or("reserved request");
+    // 第 12 位为纠正错误过滤标志，分类时忽略
+    let compound = code & !(1 << 12);
+    match code {
+        0x0000 => write!(f, "no error"),
+        0x0001 => write!(f, "unclassified error"),
+        0x0002 => write!(f, "microcode ROM parity error"),
+        0x0003 => write!(f, "external error"),
+        0x0004 => write!(f, "FRC error"),
+        0x0005 => write!(f, "internal parity error"),
+        0x0006 => write!(f, "SMM handler code access violation"),
+        0x0400 => write!(f, "internal timer error"),
+        0x0e0b => write!(f, "I/O error"),
+        _ if code & 0xff00 == 0x0400 => write!(f, "internal unclassified error"),
+        _ if compound & 0xeffc == 0x000c => write!(f, "generic cache hierarchy error ({level})"),
+        _ if compound & 0xeff0 == 0x0010 => {
+            write!(f, "TLB error ({} {level})", TRANSACTIONS[((code >> 2) & 0b11) as usize])
+        }
+        _ if compound & 0xef80 == 0x0080 => {
+            let op = MEMORY.get(((code >> 4) & 0b111) as usize).copied().unwrap_or("reserved");
+            match code & 0xf {
+                0xf => write!(f, "memory controller {op} error"),
+                channel => write!(f, "memory controller {op} error on channel {channel}"),
+            }
+        }
+        _ if compound & 0xef00 == 0x0100 => write!(
+            f,
+            "cache hierarchy {} error ({} {level})",
+            request(code),
+            TRANSACTIONS[((code >> 2) & 0b11) as usize]
+        ),
+        _ if compound & 0xe800 == 0x0800 => write!(
+            f,
+            "bus/interconnect {} error ({}, {} space{}, {level})",
+            request(code),
+            PARTICIPATION[((code >> 9) & 0b11) as usize],
+            BUS_SPACE[((code >> 2) & 0b11) as usize],
+            if code & (1 << 8) != 0 { ", timeout" } else { "" }
+        ),
+        _ => write!(f, "unknown error code {code:#06x}"),
+    }
+}
+
+/// 在 BSP 上检测并启用机器检查（在 `cpu::init` 之后、IDT 加载之后调用）
+pub fn init() {
+    if crate::cmdline::value("mce") == Some("off") {
+        log::info!("MCE: disabled (mce=off)");
+        return;
+    }
+    if !cpu::has(Feature::Mce) || !cpu::has(Feature::Mca) {
+        log::info!("MCE: machine check architecture not supported");
+        return;
+    }
+    let cap = unsafe { Msr::new(IA32_MCG_CAP).read() };
+    let count = (cap & MCG_BANK_COUNT) as usize;
+    let banks = count.min(MAX_MCE_BANKS);
+    CAP.store(cap, Ordering::Relaxed);
+    BANKS.store(banks, Ordering::Relaxed);
+    log::info!(
+        "MCE: {} banks{}{}, polling every {}s",
+        count,
+        if cap & MCG_CTL_P != 0 { ", MCG_CTL" } else { "" },
+        if cap & MCG_SER_P != 0 { ", software error recovery" } else { "" },
+        MCE_POLL_INTERVAL
+    );
+    if count > MAX_MCE_BANKS {
+        log::warn!("MCE: only the first {MAX_MCE_BANKS} banks are used");
+    }
+    enable_local();
+}
+
+/// 在 AP 上启用机器检查（寄存器组属于各个 CPU）
+pub fn init_ap() {
+    if BANKS.load(Ordering::Relaxed) != 0 {
+        enable_local();
+    }
+}
+
+/// 输出并清除残留记录，打开全部寄存器组的错误报告，最后置位 CR4.MCE
+fn enable_local() {
+    let cap = CAP.load(Ordering::Relaxed);
+    // 复位不会清除 MCi_STATUS，此时仍有效的记录来自上一次（可能是无声的）重启
+    for record in (0..banks()).filter_map(Record::read) {
+        log::warn!("MCE: CPU{} logged before boot: {}", cpu_index(), record);
+        record.clear();
+    }
+    unsafe {
+        if cap & MCG_CTL_P != 0 {
+            Msr::new(IA32_MCG_CTL).write(u64::MAX);
+        }
+        for bank in 0..banks() {
+            // P6 系列 Intel 处理器的 MC0_CTL 由固件管理，写入可能产生意外的 #MC
+            if bank == 0 && skip_bank0_ctl() {
+                continue;
+            }
+            bank_msr(bank, 0).write(u64::MAX);
+        }
+        Cr4::update(|flags| flags.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION));
+    }
+}
+
+fn banks() -> usize {
+    BANKS.load(Ordering::Relaxed)
+}
+
+fn skip_bank0_ctl() -> bool {
+    let features = cpu::features();
+    features.vendor() == "GenuineIntel" && features.family == 6 && features.model < 0x1a
+}
+
+/// #MC 异常处理（经 MACHINE_CHECK_IST_INDEX 栈进入，中断已关闭）
+pub fn handle(frame: &TrapFrame) {
+    EXCEPTIONS.get().fetch_add(1, Ordering::Relaxed);
+    let mcg_status = unsafe { Msr::new(IA32_MCG_STATUS).read() };
+    while REPORTING.swap(true, Ordering::Acquire) {
+        spin_loop();
+    }
+    emergency!(
+        "CPU{}: machine check exception at {:#x}, MCG status {:#x}{}{}{}",
+        cpu_index(),
+        frame.rip,
+        mcg_status,
+        if mcg_status & MCG_RIPV != 0 { " RIPV" } else { "" },
+        if mcg_status & MCG_EIPV != 0 { " EIPV" } else { "" },
+        if mcg_status & MCG_MCIP != 0 { " MCIP" } else { "" }
+    );
+    let mut worst = None;
+    for record in (0..banks()).filter_map(Record::read) {
+        let severity = record.severity(Some(mcg_status));
+        emergency!("CPU{}: {} [{}]", cpu_index(), record, severity.name());
+        worst = worst.max(Some(severity));
+        if severity != Severity::Fatal {
+            record.clear();
+        }
+    }
+    // 没有找到记录但也不能从被打断处继续时，同样无法恢复
+    let worst = match worst {
+        None if mcg_status & MCG_RIPV == 0 => Some(Severity::Fatal),
+        worst => worst,
+    };
+    REPORTING.store(false, Ordering::Release);
+
+    match worst {
+        Some(Severity::Fatal) => panic!("Fatal machine check on CPU{} at {:#x}", cpu_index(), frame.rip),
+        None => emergency!("CPU{}: no valid error record found", cpu_index()),
+        Some(severity) => emergency!("CPU{}: {} error, continuing", cpu_index(), severity.name()),
+    }
+    // 清除 MCIP；在此之前再次发生 #MC 会导致处理器关机
+    unsafe { Msr::new(IA32_MCG_STATUS).write(0) };
+}
+
+/// 本地定时器节拍（由 `clockevent::handle_event` 调用）：每 `MCE_POLL_INTERVAL` 秒轮询一次
+///
+/// 动态节拍下节拍数可能跳跃，因此与下一次轮询的节拍数比较而不是取模。
+pub fn timer_tick(ticks: u64) {
+    if banks() == 0 || ticks < NEXT_POLL.get().load(Ordering::Relaxed) {
+        return;
+    }
+    NEXT_POLL.get().store(ticks + MCE_POLL_INTERVAL * LOCAL_TIMER_HZ, Ordering::Relaxed);
+    POLLS.get().fetch_add(1, Ordering::Relaxed);
+    let ser = CAP.load(Ordering::Relaxed) & MCG_SER_P != 0;
+    for record in (0..banks()).filter_map(Record::read) {
+        // 会触发 #MC 的未纠正错误留给异常处理程序
+        if record.status & STATUS_UC != 0 && (!ser || record.status & STATUS_S != 0) {
+            continue;
+        }
+        log::warn!("MCE: CPU{}: {} [{}]", cpu_index(), record, record.severity(None).name());
+        record.clear();
+    }
+}
+
+/// 输出机器检查统计（由 irq::Stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    write!(f, "\nMCE:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", EXCEPTIONS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Machine check exceptions")?;
+    write!(f, "\nMCP:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", POLLS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Machine check polls")
+}
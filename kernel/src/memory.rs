@@ -0,0 +1,367 @@
+//! 内存管理模块
+//! 接管当前页表，建立内核自己的物理内存直接映射，并提供物理帧分配
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Once;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size1GiB, Size2MiB, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+use crate::boot_info::{BootInfo, MemoryMapStorage, MemoryRegion, MemoryRegionType};
+use crate::constants::memory::{DIRECT_MAP_BASE, LOW_MEMORY_LIMIT};
+use crate::cpu::{self, Feature};
+use crate::error::{KernelResult, KernelError};
+use crate::sync::TicketLock;
+use crate::tlb::TlbBatch;
+
+/// 每个 PML4 项覆盖的地址空间大小（512GiB）
+const PML4_ENTRY_SIZE: u64 = 512 * Size1GiB::SIZE;
+
+/// 物理内存映射偏移（直接映射建立后切换为内核自己的偏移）
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// 直接映射区为物理地址预留的窗口大小（按 PML4 项取整）
+static DIRECT_MAP_WINDOW: AtomicU64 = AtomicU64::new(0);
+
+/// 当前活动页表的映射器
+static MAPPER: TicketLock<Option<OffsetPageTable<'static>>> = TicketLock::new(None);
+
+/// 物理帧分配器
+static FRAME_ALLOCATOR: TicketLock<Option<BootFrameAllocator>> = TicketLock::new(None);
+
+/// 内核保存的内存映射（按起始地址排序）
+static MEMORY_MAP: Once<MemoryMapStorage> = Once::new();
+
+/// 启动阶段的物理帧分配器
+/// 按顺序从可用区域中分配，不支持释放；跳过低端内存与内核映像所在的物理内存
+pub struct BootFrameAllocator {
+    regions: &'static [MemoryRegion],
+    region_index: usize,
+    next: u64,
+    reserved_start: u64,
+    reserved_end: u64,
+    allocated: usize,
+}
+
+impl BootFrameAllocator {
+    fn new(regions: &'static [MemoryRegion], reserved_start: u64, reserved_end: u64) -> Self {
+        Self {
+            regions,
+            region_index: 0,
+            next: LOW_MEMORY_LIMIT,
+            reserved_start,
+            reserved_end,
+            allocated: 0,
+        }
+    }
+}
+
+impl BootFrameAllocator {
+    /// 分配 `count` 个物理上连续的帧，返回起始地址
+    fn allocate_contiguous(&mut self, count: u64) -> Option<PhysAddr> {
+        let size = count * Size4KiB::SIZE;
+        while let Some(region) = self.regions.get(self.region_index) {
+            if region.region_type == MemoryRegionType::Usable {
+                let mut addr = align_up(self.next.max(region.start), Size4KiB::SIZE);
+                if addr < self.reserved_end && addr + size > self.reserved_start {
+                    addr = align_up(self.reserved_end, Size4KiB::SIZE);
+                }
+                if addr + size <= region.end {
+                    self.next = addr + size;
+                    self.allocated += count as usize;
+                    return Some(PhysAddr::new(addr));
+                }
+            }
+            self.region_index += 1;
+        }
+        None
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BootFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate_contiguous(1).and_then(|addr| PhysFrame::from_start_address(addr).ok())
+    }
+}
+
+/// 向上对齐
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// 向下对齐
+fn align_down(addr: u64, align: u64) -> u64 {
+    addr & !(align - 1)
+}
+
+/// 初始化内存管理：接管引导加载程序的页表，建立直接映射并切换到直接映射偏移
+pub fn init(boot_info: &dyn BootInfo) -> KernelResult<()> {
+    let offset = boot_info.physical_memory_offset().ok_or(KernelError::PagingFailed)?;
+    PHYSICAL_MEMORY_OFFSET.store(offset, Ordering::Release);
+    let mut mapper = unsafe { active_page_table() };
+
+    let memory_map = MEMORY_MAP.call_once(|| {
+        let mut storage = MemoryMapStorage::from_regions(boot_info.memory_regions().iter().copied());
+        storage.sort();
+        storage
+    });
+    let regions = memory_map.as_slice();
+    if regions.is_empty() {
+        return Err(KernelError::PagingFailed);
+    }
+
+    // 内核映像所在的物理内存不能分配出去
+    let (reserved_start, reserved_end) = crate::kaslr::layout()
+        .and_then(|layout| Some((layout.physical_base?, layout.size)))
+        .map(|(base, size)| (base, base + size))
+        .unwrap_or((0, 0));
+    let mut allocator = BootFrameAllocator::new(regions, reserved_start, reserved_end);
+
+    let direct_map_base = build_direct_map(&mut mapper, &mut allocator, regions)?;
+
+    // 之后的页表访问与地址转换都使用内核自己的直接映射
+    PHYSICAL_MEMORY_OFFSET.store(direct_map_base, Ordering::Release);
+    *MAPPER.lock() = Some(unsafe { active_page_table() });
+    log::info!("Paging: PML4 at {:#x}, {} frames used for page tables",
+        Cr3::read().0.start_address().as_u64(), allocator.allocated);
+    *FRAME_ALLOCATOR.lock() = Some(allocator);
+    Ok(())
+}
+
+/// 基于当前物理内存映射偏移创建活动页表的映射器
+unsafe fn active_page_table() -> OffsetPageTable<'static> {
+    let (level_4_table_frame, _) = Cr3::read();
+    let level_4_table = &mut *phys_to_virt(level_4_table_frame.start_address()).as_mut_ptr::<PageTable>();
+    OffsetPageTable::new(level_4_table, VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Acquire)))
+}
+
+/// 选择直接映射区的起始地址：优先使用 DIRECT_MAP_BASE，被占用时在高半区寻找连续的空闲 PML4 项
+fn direct_map_base(mapper: &mut OffsetPageTable<'static>, size: u64) -> Option<u64> {
+    let entries = size.div_ceil(PML4_ENTRY_SIZE).max(1) as usize;
+    let preferred = ((DIRECT_MAP_BASE >> 39) & 0x1ff) as usize;
+    let table = mapper.level_4_table();
+    let is_free = |first: usize| (first..first + entries).all(|i| i < 511 && table[i].is_unused());
+
+    core::iter::once(preferred)
+        .chain(256..511)
+        .find(|&first| is_free(first))
+        .map(|first| 0xffff_0000_0000_0000 | ((first as u64) << 39))
+}
+
+/// 建立所有 RAM 的直接映射，返回直接映射区的起始地址
+fn build_direct_map(
+    mapper: &mut OffsetPageTable<'static>,
+    allocator: &mut BootFrameAllocator,
+    regions: &[MemoryRegion],
+) -> KernelResult<u64> {
+    let mapped = |region: &&MemoryRegion| {
+        region.region_type.is_ram() || region.region_type == MemoryRegionType::Framebuffer
+    };
+    let max_end = regions.iter().filter(mapped).map(|region| region.end).max().unwrap_or(0);
+    let base = direct_map_base(mapper, max_end).ok_or(KernelError::PagingFailed)?;
+
+    let mut builder = DirectMapBuilder {
+        mapper,
+        allocator,
+        base,
+        use_gib: cpu::has(Feature::Gib1Pages),
+        use_mib: cpu::has(Feature::Pse),
+        counts: [0; 3],
+    };
+
+    // 合并相邻区域，让大页能跨越区域边界
+    let mut current: Option<(u64, u64)> = None;
+    for region in regions.iter().filter(mapped) {
+        let (start, end) = (align_down(region.start, Size4KiB::SIZE), align_up(region.end, Size4KiB::SIZE));
+        current = match current {
+            Some((s, e)) if start <= e => Some((s, e.max(end))),
+            Some((s, e)) => {
+                builder.map_range(s, e)?;
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((s, e)) = current {
+        builder.map_range(s, e)?;
+    }
+
+    DIRECT_MAP_WINDOW.store(max_end.div_ceil(PML4_ENTRY_SIZE).max(1) * PML4_ENTRY_SIZE, Ordering::Release);
+
+    let [gib, mib, kib] = builder.counts;
+    log::info!("Direct map: {base:#x} -> phys 0..{max_end:#x} ({gib} x 1GiB, {mib} x 2MiB, {kib} x 4KiB)");
+    Ok(base)
+}
+
+/// 直接映射构建器
+/// 每个区域尽量使用 1GiB / 2MiB 大页，只有在区域边缘无法对齐时才使用 4KiB 页
+struct DirectMapBuilder<'a> {
+    mapper: &'a mut OffsetPageTable<'static>,
+    allocator: &'a mut BootFrameAllocator,
+    base: u64,
+    use_gib: bool,
+    use_mib: bool,
+    /// 已映射的 1GiB、2MiB、4KiB 页数量
+    counts: [usize; 3],
+}
+
+impl DirectMapBuilder<'_> {
+    const FLAGS: PageTableFlags = PageTableFlags::PRESENT
+        .union(PageTableFlags::WRITABLE)
+        .union(PageTableFlags::NO_EXECUTE)
+        .union(PageTableFlags::GLOBAL);
+
+    /// 映射一段物理地址范围
+    fn map_range(&mut self, start: u64, end: u64) -> KernelResult<()> {
+        let fits = |addr: u64, size: u64| addr % size == 0 && addr + size <= end;
+        let mut addr = start;
+        while addr < end {
+            if self.use_gib && fits(addr, Size1GiB::SIZE) {
+                self.map_page::<Size1GiB>(addr)?;
+                self.counts[0] += 1;
+                addr += Size1GiB::SIZE;
+            } else if self.use_mib && fits(addr, Size2MiB::SIZE) {
+                self.map_page::<Size2MiB>(addr)?;
+                self.counts[1] += 1;
+                addr += Size2MiB::SIZE;
+            } else {
+                self.map_page::<Size4KiB>(addr)?;
+                self.counts[2] += 1;
+                addr += Size4KiB::SIZE;
+            }
+        }
+        Ok(())
+    }
+
+    /// 映射单个页面
+    fn map_page<S: PageSize>(&mut self, phys: u64) -> KernelResult<()>
+    where
+        for<'b> OffsetPageTable<'b>: Mapper<S>,
+    {
+        let page = Page::<S>::from_start_address(VirtAddr::new(self.base + phys))
+            .map_err(|_| KernelError::PagingFailed)?;
+        let frame = PhysFrame::<S>::from_start_address(PhysAddr::new(phys))
+            .map_err(|_| KernelError::PagingFailed)?;
+        // 新建的映射不会在 TLB 中留有旧项，无需刷新
+        unsafe { self.mapper.map_to(page, frame, Self::FLAGS, &mut *self.allocator) }
+            .map_err(|_| KernelError::PagingFailed)?
+            .ignore();
+        Ok(())
+    }
+}
+
+/// 物理地址转换为虚拟地址（直接映射）
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Acquire) + phys.as_u64())
+}
+
+/// 在持有页表锁的情况下访问映射器
+pub fn with_mapper<R>(f: impl FnOnce(&mut OffsetPageTable<'static>) -> R) -> KernelResult<R> {
+    let mut guard = MAPPER.lock();
+    let mapper = guard.as_mut().ok_or(KernelError::PagingFailed)?;
+    Ok(f(mapper))
+}
+
+/// 把直接映射中尚未覆盖的物理地址范围（如保留区中的 ACPI 表、MMIO）补充映射进来
+/// 返回 `phys` 对应的虚拟地址；已映射的页面保持原有属性不变
+pub fn map_physical(phys: u64, size: u64, flags: PageTableFlags) -> KernelResult<VirtAddr> {
+    let start = align_down(phys, Size4KiB::SIZE);
+    let end = align_up(phys + size.max(1), Size4KiB::SIZE);
+    if end > DIRECT_MAP_WINDOW.load(Ordering::Acquire) {
+        return Err(KernelError::PagingFailed);
+    }
+
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE | PageTableFlags::GLOBAL;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().ok_or(KernelError::PagingFailed)?;
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let allocator = allocator.as_mut().ok_or(KernelError::PagingFailed)?;
+
+    for addr in (start..end).step_by(Size4KiB::SIZE as usize) {
+        let virt = phys_to_virt(PhysAddr::new(addr));
+        if mapper.translate_addr(virt).is_some() {
+            continue;
+        }
+        let page = Page::<Size4KiB>::containing_address(virt);
+        let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(addr));
+        unsafe { mapper.map_to(page, frame, flags, allocator) }
+            .map_err(|_| KernelError::PagingFailed)?
+            .flush();
+    }
+    Ok(phys_to_virt(PhysAddr::new(phys)))
+}
+
+/// 对一段虚拟地址范围内的每个 4KiB 页调用 `f`，并在所有 CPU 上刷新修改过的页面
+fn update_pages(
+    virt: VirtAddr,
+    size: u64,
+    mut f: impl FnMut(&mut OffsetPageTable<'static>, Page<Size4KiB>) -> KernelResult<()>,
+) -> KernelResult<()> {
+    let first = Page::<Size4KiB>::containing_address(virt);
+    let last = Page::<Size4KiB>::containing_address(virt + (size.max(1) - 1));
+    let mut batch = TlbBatch::new();
+    let result = with_mapper(|mapper| -> KernelResult<()> {
+        for page in Page::range_inclusive(first, last) {
+            f(mapper, page)?;
+            batch.add(page.start_address());
+        }
+        Ok(())
+    })?;
+    // 出错时已经修改的页面同样需要刷新
+    batch.finish();
+    result
+}
+
+/// 解除一段虚拟地址范围的 4KiB 映射（物理帧不回收），返回前所有 CPU 都已刷新 TLB
+#[allow(dead_code)]
+pub fn unmap(virt: VirtAddr, size: u64) -> KernelResult<()> {
+    update_pages(virt, size, |mapper, page| {
+        let (_, flush) = mapper.unmap(page).map_err(|_| KernelError::PagingFailed)?;
+        flush.ignore();
+        Ok(())
+    })
+}
+
+/// 修改一段虚拟地址范围的页面属性，返回前所有 CPU 都已刷新 TLB
+#[allow(dead_code)]
+pub fn protect(virt: VirtAddr, size: u64, flags: PageTableFlags) -> KernelResult<()> {
+    update_pages(virt, size, |mapper, page| {
+        let flush = unsafe { mapper.update_flags(page, flags) }.map_err(|_| KernelError::PagingFailed)?;
+        flush.ignore();
+        Ok(())
+    })
+}
+
+/// 以不可缓存方式映射设备寄存器（MMIO）
+pub fn map_mmio(phys: u64, size: u64) -> KernelResult<VirtAddr> {
+    map_physical(
+        phys,
+        size,
+        PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH,
+    )
+}
+
+/// 分配 `count` 个物理上连续并清零的页帧（如 AP 的栈），返回起始物理地址
+pub fn allocate_frames(count: u64) -> KernelResult<PhysAddr> {
+    let phys = FRAME_ALLOCATOR
+        .lock()
+        .as_mut()
+        .ok_or(KernelError::PagingFailed)?
+        .allocate_contiguous(count)
+        .ok_or(KernelError::NoResources)?;
+    unsafe {
+        core::ptr::write_bytes(phys_to_virt(phys).as_mut_ptr::<u8>(), 0, (count * Size4KiB::SIZE) as usize);
+    }
+    Ok(phys)
+}
+
+/// 在 1MiB 以下的可用内存中查找一个不低于 `min` 的页（供实模式代码使用）
+pub fn low_memory_page(min: u64) -> Option<u64> {
+    MEMORY_MAP.r#try()?.as_slice().iter()
+        .filter(|region| region.region_type == MemoryRegionType::Usable)
+        .map(|region| (align_up(region.start.max(min), Size4KiB::SIZE), region.end.min(LOW_MEMORY_LIMIT)))
+        .find(|&(start, end)| start + Size4KiB::SIZE <= end)
+        .map(|(start, _)| start)
+}
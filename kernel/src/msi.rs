@@ -0,0 +1,167 @@
+//! PCI MSI / MSI-X 模块
+//! 解析能力结构，从动态向量分配器申请向量，编程消息地址与数据，并管理 MSI-X 表项屏蔽
+
+use crate::error::{KernelResult, KernelError};
+use crate::pci::{PciDevice, CAP_MSI, CAP_MSIX, COMMAND_BUS_MASTER, COMMAND_INTX_DISABLE};
+use crate::vectors::{self, VectorHandler};
+
+/// 消息地址基址（本地 APIC 中断投递区域）
+const MSI_ADDRESS_BASE: u64 = 0xfee0_0000;
+
+/// MSI 控制寄存器位
+const MSI_CTRL_ENABLE: u16 = 1 << 0;
+const MSI_CTRL_MULTI_ENABLE: u16 = 0b111 << 4;
+const MSI_CTRL_64BIT: u16 = 1 << 7;
+const MSI_CTRL_PER_VECTOR_MASK: u16 = 1 << 8;
+
+/// MSI-X 控制寄存器位
+const MSIX_CTRL_TABLE_SIZE: u16 = 0x7ff;
+const MSIX_CTRL_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_CTRL_ENABLE: u16 = 1 << 15;
+
+/// MSI-X 表项：地址低 32 位、地址高 32 位、数据、向量控制
+const MSIX_ENTRY_SIZE: u64 = 16;
+const MSIX_ENTRY_ADDRESS_LOW: u64 = 0;
+const MSIX_ENTRY_ADDRESS_HIGH: u64 = 4;
+const MSIX_ENTRY_DATA: u64 = 8;
+const MSIX_ENTRY_CONTROL: u64 = 12;
+const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+/// 生成投递到当前 CPU 的消息地址与数据（固定投递、边沿触发）
+fn compose_message(vector: u8) -> KernelResult<(u64, u32)> {
+    // 没有中断重映射时消息地址只能携带 8 位 APIC ID
+    let apic_id = crate::lapic::id();
+    if apic_id > 0xff {
+        return Err(KernelError::HardwareError);
+    }
+    Ok((MSI_ADDRESS_BASE | (apic_id as u64) << 12, vector as u32))
+}
+
+/// MSI-X 表的项数
+pub fn msix_table_size(dev: &PciDevice, cap: u8) -> u16 {
+    (dev.read_u16(cap + 2) & MSIX_CTRL_TABLE_SIZE) + 1
+}
+
+/// 为设备启用单向量 MSI，返回分配的向量
+#[allow(dead_code)]
+pub fn enable_msi(dev: &PciDevice, name: &'static str, handler: VectorHandler) -> KernelResult<u8> {
+    let cap = dev.find_capability(CAP_MSI).ok_or(KernelError::InvalidParameter)?;
+    let vector = vectors::allocate(name, handler)?;
+    let (address, data) = compose_message(vector).inspect_err(|_| vectors::free(vector))?;
+
+    // 编程期间保持关闭，只使用一条消息
+    let control = dev.read_u16(cap + 2) & !(MSI_CTRL_ENABLE | MSI_CTRL_MULTI_ENABLE);
+    dev.write_u16(cap + 2, control);
+    dev.write_u32(cap + 4, address as u32);
+    let data_offset = if control & MSI_CTRL_64BIT != 0 {
+        dev.write_u32(cap + 8, (address >> 32) as u32);
+        cap + 12
+    } else {
+        cap + 8
+    };
+    dev.write_u16(data_offset, data as u16);
+    if control & MSI_CTRL_PER_VECTOR_MASK != 0 {
+        // 屏蔽位寄存器紧跟在数据寄存器（及保留字段）之后
+        dev.write_u32(data_offset + 4, 0);
+    }
+
+    dev.enable_command(COMMAND_BUS_MASTER | COMMAND_INTX_DISABLE);
+    dev.write_u16(cap + 2, control | MSI_CTRL_ENABLE);
+    log::info!("MSI: {dev} \"{name}\" -> vector {vector}");
+    Ok(vector)
+}
+
+/// 已启用的 MSI-X 表
+pub struct MsixTable {
+    device: PciDevice,
+    /// 表的虚拟地址
+    base: u64,
+    size: u16,
+}
+
+/// 为设备启用 MSI-X，所有表项初始为屏蔽状态
+#[allow(dead_code)]
+pub fn enable_msix(dev: &PciDevice) -> KernelResult<MsixTable> {
+    let cap = dev.find_capability(CAP_MSIX).ok_or(KernelError::InvalidParameter)?;
+    let size = msix_table_size(dev, cap);
+    let table = dev.read_u32(cap + 4);
+    let bar = dev.memory_bar((table & 0b111) as u8).ok_or(KernelError::InvalidParameter)?;
+    let phys = bar + (table & !0b111) as u64;
+    let base = crate::memory::map_mmio(phys, size as u64 * MSIX_ENTRY_SIZE)?.as_u64();
+
+    // 先屏蔽整个功能再打开 MSI-X，逐项屏蔽后才解除功能屏蔽，期间不会有消息发出
+    let control = dev.read_u16(cap + 2);
+    dev.write_u16(cap + 2, control | MSIX_CTRL_ENABLE | MSIX_CTRL_FUNCTION_MASK);
+    let msix = MsixTable { device: *dev, base, size };
+    for index in 0..size {
+        msix.mask(index);
+    }
+    dev.enable_command(COMMAND_BUS_MASTER | COMMAND_INTX_DISABLE);
+    dev.write_u16(cap + 2, (control | MSIX_CTRL_ENABLE) & !MSIX_CTRL_FUNCTION_MASK);
+
+    log::info!("MSI-X: {dev} enabled, {size} entries, table at {phys:#x}");
+    Ok(msix)
+}
+
+#[allow(dead_code)]
+impl MsixTable {
+    /// 表项数量
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// 表项中某个寄存器的地址
+    fn register(&self, index: u16, offset: u64) -> *mut u32 {
+        (self.base + index as u64 * MSIX_ENTRY_SIZE + offset) as *mut u32
+    }
+
+    /// 为表项分配向量并编程消息，完成后解除屏蔽
+    pub fn set_vector(&self, index: u16, name: &'static str, handler: VectorHandler) -> KernelResult<u8> {
+        if index >= self.size {
+            return Err(KernelError::InvalidParameter);
+        }
+        let vector = vectors::allocate(name, handler)?;
+        let (address, data) = compose_message(vector).inspect_err(|_| vectors::free(vector))?;
+
+        // 只能在表项屏蔽时修改地址与数据
+        self.mask(index);
+        unsafe {
+            self.register(index, MSIX_ENTRY_ADDRESS_LOW).write_volatile(address as u32);
+            self.register(index, MSIX_ENTRY_ADDRESS_HIGH).write_volatile((address >> 32) as u32);
+            self.register(index, MSIX_ENTRY_DATA).write_volatile(data);
+        }
+        self.unmask(index);
+        log::info!("MSI-X: {} entry {index} \"{name}\" -> vector {vector}", self.device);
+        Ok(vector)
+    }
+
+    /// 释放表项：屏蔽并归还向量
+    pub fn release(&self, index: u16, vector: u8) {
+        if index < self.size {
+            self.mask(index);
+        }
+        vectors::free(vector);
+    }
+
+    /// 屏蔽表项
+    pub fn mask(&self, index: u16) {
+        self.update_control(index, |control| control | MSIX_ENTRY_MASKED);
+    }
+
+    /// 解除表项屏蔽
+    pub fn unmask(&self, index: u16) {
+        self.update_control(index, |control| control & !MSIX_ENTRY_MASKED);
+    }
+
+    fn update_control(&self, index: u16, f: impl FnOnce(u32) -> u32) {
+        if index >= self.size {
+            return;
+        }
+        let control = self.register(index, MSIX_ENTRY_CONTROL);
+        unsafe {
+            control.write_volatile(f(control.read_volatile()));
+            // 读回以确保写入已到达设备
+            control.read_volatile();
+        }
+    }
+}
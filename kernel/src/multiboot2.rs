@@ -5,14 +5,23 @@
 
 use core::arch::asm;
 use core::panic::PanicInfo;
-use crate::boot_info::{BootInfo, FrameBufferInfo, PixelFormat, MemoryRegion};
+use crate::boot_info::{BootInfo, FrameBufferInfo, PixelFormat, MemoryRegion, MemoryRegionType, MemoryMapStorage};
+use crate::constants::kernel::KERNEL_LINK_BASE;
 
 /// Multiboot 2 魔数
 const MULTIBOOT2_MAGIC: u32 = 0xe85250d6;
 /// Multiboot 2 架构 - i386
 const MULTIBOOT2_ARCHITECTURE_I386: u32 = 0;
-/// Multiboot 2 头长度
-const MULTIBOOT2_HEADER_LENGTH: u32 = 24;
+/// Multiboot 2 头长度（固定部分 + 可重定位标签 + 结束标签）
+const MULTIBOOT2_HEADER_LENGTH: u32 = 48;
+/// 可重定位头标签类型
+const MULTIBOOT2_HEADER_TAG_RELOCATABLE: u16 = 10;
+/// 可重定位加载的物理地址范围与对齐
+const RELOCATABLE_MIN_ADDR: u32 = 0x100000;
+const RELOCATABLE_MAX_ADDR: u32 = 0x3fff_ffff;
+const RELOCATABLE_ALIGN: u32 = 0x200000;
+/// 加载位置偏好：0 无偏好，1 尽量低，2 尽量高
+const RELOCATABLE_PREFERENCE_NONE: u32 = 0;
 
 /// Multiboot 2 信息标签类型
 #[repr(u32)]
@@ -50,9 +59,23 @@ pub struct Multiboot2Header {
     architecture: u32,
     header_length: u32,
     checksum: u32,
+    relocatable_tag: RelocatableHeaderTag,
     end_tag: Multiboot2HeaderTag,
 }
 
+/// 可重定位头标签
+/// 允许引导加载程序把内核放到其他物理地址，重定位由内核自己完成
+#[repr(C, align(8))]
+struct RelocatableHeaderTag {
+    tag_type: u16,
+    flags: u16,
+    size: u32,
+    min_addr: u32,
+    max_addr: u32,
+    align: u32,
+    preference: u32,
+}
+
 /// Multiboot 2 头标签
 #[repr(C, align(8))]
 struct Multiboot2HeaderTag {
@@ -110,6 +133,14 @@ pub struct MemoryMapTag {
     // 条目跟随在这里
 }
 
+/// 命令行标签
+#[repr(C)]
+pub struct CommandLineTag {
+    pub tag_type: u32,
+    pub size: u32,
+    pub string: [u8; 0], // 以 NUL 结尾的字符串
+}
+
 /// RSDP 标签（ACPI）
 #[repr(C)]
 pub struct RsdpTag {
@@ -165,6 +196,41 @@ impl Multiboot2BootInfo {
         });
         result
     }
+
+    /// [start, end) 是否为空闲的可用内存：整体落在一个可用区域内且不与引导信息重叠
+    /// （直接读取标签，自重定位之前也可调用）
+    fn is_free(&self, start: u64, end: u64) -> bool {
+        let info_start = self.info_ptr as u64;
+        let info_end = info_start + unsafe { (*self.info_ptr).total_size } as u64;
+        if info_start < end && start < info_end {
+            return false;
+        }
+        let Some(tag_ptr) = self.get_tag(TagType::MemoryMap) else { return false };
+        unsafe {
+            let tag = &*(tag_ptr as *const MemoryMapTag);
+            let entries = (tag_ptr as *const u8).add(core::mem::size_of::<MemoryMapTag>());
+            let count = (tag.size as usize - core::mem::size_of::<MemoryMapTag>()) / tag.entry_size as usize;
+            (0..count).any(|i| {
+                let entry = &*(entries.add(i * tag.entry_size as usize) as *const MemoryMapEntry);
+                entry.entry_type == 1 && entry.base_addr <= start && end <= entry.base_addr + entry.length
+            })
+        }
+    }
+}
+
+/// 将可用区域中与 [start, end) 重叠的部分划为引导加载程序占用
+fn split_region(region: MemoryRegion, start: u64, end: u64) -> [Option<MemoryRegion>; 3] {
+    if region.region_type != MemoryRegionType::Usable || end <= region.start || start >= region.end {
+        return [Some(region), None, None];
+    }
+    let piece = |start: u64, end: u64, region_type| {
+        (start < end).then_some(MemoryRegion { start, end, region_type })
+    };
+    [
+        piece(region.start, start.max(region.start), MemoryRegionType::Usable),
+        piece(start.max(region.start), end.min(region.end), MemoryRegionType::BootloaderReclaimable),
+        piece(end.min(region.end), region.end, MemoryRegionType::Usable),
+    ]
 }
 
 impl BootInfo for Multiboot2BootInfo {
@@ -198,8 +264,38 @@ impl BootInfo for Multiboot2BootInfo {
     }
 
     fn memory_regions(&self) -> &[MemoryRegion] {
-        // TODO: 实现内存映射解析
-        &[]
+        static MEMORY_MAP: spin::Once<MemoryMapStorage> = spin::Once::new();
+        MEMORY_MAP.call_once(|| {
+            let Some(tag_ptr) = self.get_tag(TagType::MemoryMap) else {
+                return MemoryMapStorage::from_regions(core::iter::empty());
+            };
+            unsafe {
+                let tag = &*(tag_ptr as *const MemoryMapTag);
+                let entries = (tag_ptr as *const u8).add(core::mem::size_of::<MemoryMapTag>());
+                let count = (tag.size as usize - core::mem::size_of::<MemoryMapTag>()) / tag.entry_size as usize;
+
+                // 引导信息结构本身位于可用内存中，需要单独划出来
+                let info_start = self.info_ptr as u64;
+                let info_end = info_start + (*self.info_ptr).total_size as u64;
+
+                let regions = (0..count).flat_map(|i| {
+                    let entry = &*(entries.add(i * tag.entry_size as usize) as *const MemoryMapEntry);
+                    let region = MemoryRegion {
+                        start: entry.base_addr,
+                        end: entry.base_addr + entry.length,
+                        region_type: match entry.entry_type {
+                            1 => MemoryRegionType::Usable,
+                            3 => MemoryRegionType::AcpiReclaimable,
+                            4 => MemoryRegionType::AcpiNvs,
+                            5 => MemoryRegionType::BadMemory,
+                            _ => MemoryRegionType::Reserved,
+                        },
+                    };
+                    split_region(region, info_start, info_end)
+                });
+                MemoryMapStorage::from_regions(regions.flatten())
+            }
+        }).as_slice()
     }
 
     fn rsdp_address(&self) -> Option<u64> {
@@ -223,8 +319,30 @@ impl BootInfo for Multiboot2BootInfo {
     }
 
     fn command_line(&self) -> Option<&str> {
-        // TODO: 实现命令行解析
-        None
+        let tag_ptr = self.get_tag(TagType::CommandLine)?;
+        unsafe {
+            let tag = &*(tag_ptr as *const CommandLineTag);
+            let len = (tag.size as usize).saturating_sub(8);
+            let bytes = core::slice::from_raw_parts(tag.string.as_ptr(), len);
+            // 去掉结尾的 NUL
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+            core::str::from_utf8(&bytes[..end]).ok()
+        }
+    }
+
+    fn kernel_virtual_base(&self) -> Option<u64> {
+        // Multiboot 2 进入内核时为恒等映射，虚拟基址等于物理基址
+        self.kernel_physical_base()
+    }
+
+    fn kernel_physical_base(&self) -> Option<u64> {
+        // 恒等映射下运行地址即物理地址；nokaslr 搬回链接地址后，加载基址标签已不再准确
+        Some(crate::kaslr::runtime_base())
+    }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        // 恒等映射：物理地址即虚拟地址
+        Some(0)
     }
 }
 
@@ -238,6 +356,15 @@ static MULTIBOOT2_HEADER: Multiboot2Header = Multiboot2Header {
     checksum: (0u32.wrapping_sub(MULTIBOOT2_MAGIC)
         .wrapping_sub(MULTIBOOT2_ARCHITECTURE_I386)
         .wrapping_sub(MULTIBOOT2_HEADER_LENGTH)),
+    relocatable_tag: RelocatableHeaderTag {
+        tag_type: MULTIBOOT2_HEADER_TAG_RELOCATABLE,
+        flags: 0,
+        size: 24,
+        min_addr: RELOCATABLE_MIN_ADDR,
+        max_addr: RELOCATABLE_MAX_ADDR,
+        align: RELOCATABLE_ALIGN,
+        preference: RELOCATABLE_PREFERENCE_NONE,
+    },
     end_tag: Multiboot2HeaderTag {
         tag_type: 0,
         flags: 0,
@@ -266,6 +393,22 @@ pub unsafe fn print_early(s: &str) {
 /// Multiboot 2 入口点
 #[no_mangle]
 pub extern "C" fn _start(magic: u32, info_ptr: *const Multiboot2Info) -> ! {
+    // 可重定位加载时引导加载程序不会处理重定位，必须在访问任何静态指针之前完成
+    if magic == 0x36d76289 {
+        unsafe {
+            let boot_info = Multiboot2BootInfo::new(info_ptr);
+            let slide = crate::kaslr::runtime_base().wrapping_sub(KERNEL_LINK_BASE);
+            // nokaslr：由内核决定最终位置，搬回链接地址后从副本的入口重新进入，副本无需重定位
+            let nokaslr = boot_info.command_line().is_some_and(|cmdline| crate::cmdline::flag_in(cmdline, "nokaslr"));
+            if nokaslr && crate::kaslr::move_to_link_base(|start, end| boot_info.is_free(start, end)) {
+                let entry: extern "C" fn(u32, *const Multiboot2Info) -> ! =
+                    core::mem::transmute((_start as usize).wrapping_sub(slide as usize));
+                entry(magic, info_ptr);
+            }
+            crate::kaslr::apply_relocations(slide);
+        }
+    }
+
     // 直接输出调试信息（不依赖任何初始化）
     unsafe {
         print_early("\n=== Multiboot2 Entry ===\n");
@@ -308,8 +451,9 @@ pub extern "C" fn _start(magic: u32, info_ptr: *const Multiboot2Info) -> ! {
 #[cfg(feature = "multiboot2")]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    // 尝试使用串口输出 panic 信息
-    let _ = crate::serial::_print(format_args!("KERNEL PANIC: {}\n", info));
+    // 先写出暂存的日志，再不经串口锁输出 panic 信息
+    crate::logging::enter_panic();
+    crate::serial::emergency_write(format_args!("KERNEL PANIC: {}\n", info));
 
     // 无限循环
     loop {
@@ -0,0 +1,266 @@
+//! PCI 总线模块
+//! 通过配置机制 #1（0xCF8/0xCFC 端口）访问配置空间，枚举设备并遍历能力链表
+
+use core::fmt;
+use spin::Once;
+use x86_64::instructions::port::Port;
+use crate::constants::pci::*;
+use crate::sync::IrqSafeSpinlock;
+
+/// 配置空间寄存器偏移
+const REG_VENDOR_ID: u8 = 0x00;
+const REG_DEVICE_ID: u8 = 0x02;
+const REG_COMMAND: u8 = 0x04;
+const REG_STATUS: u8 = 0x06;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER_TYPE: u8 = 0x0e;
+const REG_BAR0: u8 = 0x10;
+const REG_CAPABILITIES: u8 = 0x34;
+
+/// 命令寄存器：总线主控（设备发起的 DMA 与 MSI 写入都需要）
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+/// 命令寄存器：禁止 INTx 中断
+pub const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+/// 状态寄存器：存在能力链表
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+/// 能力 ID
+pub const CAP_MSI: u8 = 0x05;
+pub const CAP_MSIX: u8 = 0x11;
+
+/// 0xCF8/0xCFC 是一对寄存器，访问必须串行化
+static CONFIG_LOCK: IrqSafeSpinlock<()> = IrqSafeSpinlock::new(());
+
+/// PCI 设备（总线/设备/功能号与识别信息）
+#[derive(Debug, Clone, Copy)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+}
+
+impl PciDevice {
+    /// 配置空间地址
+    fn address(&self, offset: u8) -> u32 {
+        config_address(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn read_u32(&self, offset: u8) -> u32 {
+        read_config(self.address(offset))
+    }
+
+    pub fn read_u16(&self, offset: u8) -> u16 {
+        (self.read_u32(offset & !3) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        (self.read_u32(offset & !3) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn write_u32(&self, offset: u8, value: u32) {
+        write_config(self.address(offset), value);
+    }
+
+    pub fn write_u16(&self, offset: u8, value: u16) {
+        let aligned = offset & !3;
+        let shift = (offset & 2) * 8;
+        let _guard = CONFIG_LOCK.lock();
+        let old = read_config_locked(self.address(aligned));
+        let new = (old & !(0xffff << shift)) | ((value as u32) << shift);
+        write_config_locked(self.address(aligned), new);
+    }
+
+    /// 设置命令寄存器中的位
+    pub fn enable_command(&self, bits: u16) {
+        let command = self.read_u16(REG_COMMAND);
+        self.write_u16(REG_COMMAND, command | bits);
+    }
+
+    /// 遍历能力链表，返回 (能力 ID, 偏移)
+    pub fn capabilities(&self) -> Capabilities {
+        let next = if self.read_u16(REG_STATUS) & STATUS_CAPABILITIES != 0 {
+            self.read_u8(REG_CAPABILITIES) & !3
+        } else {
+            0
+        };
+        Capabilities { device: *self, next, remaining: MAX_CAPABILITIES }
+    }
+
+    /// 查找指定能力的偏移
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        self.capabilities().find(|&(cap, _)| cap == id).map(|(_, offset)| offset)
+    }
+
+    /// 读取内存 BAR 的物理基址（I/O BAR 返回 None）
+    pub fn memory_bar(&self, index: u8) -> Option<u64> {
+        if index >= 6 {
+            return None;
+        }
+        let offset = REG_BAR0 + index * 4;
+        let low = self.read_u32(offset);
+        if low & 1 != 0 {
+            return None;
+        }
+        let base = (low & !0xf) as u64;
+        // 类型 0b10 为 64 位 BAR，高 32 位在下一个 BAR 中
+        if (low >> 1) & 0b11 == 0b10 && index < 5 {
+            Some(base | (self.read_u32(offset + 4) as u64) << 32)
+        } else {
+            Some(base)
+        }
+    }
+}
+
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// 能力链表迭代器
+pub struct Capabilities {
+    device: PciDevice,
+    next: u8,
+    /// 防止损坏的链表形成环
+    remaining: usize,
+}
+
+impl Iterator for Capabilities {
+    type Item = (u8, u8);
+
+    fn next(&mut self) -> Option<(u8, u8)> {
+        if self.next < 0x40 || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let offset = self.next;
+        let id = self.device.read_u8(offset);
+        self.next = self.device.read_u8(offset + 1) & !3;
+        Some((id, offset))
+    }
+}
+
+/// 计算配置空间地址
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    1 << 31 | (bus as u32) << 16 | (device as u32) << 11 | (function as u32) << 8 | (offset & !3) as u32
+}
+
+fn read_config_locked(address: u32) -> u32 {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(address);
+        Port::<u32>::new(CONFIG_DATA).read()
+    }
+}
+
+fn write_config_locked(address: u32, value: u32) {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(address);
+        Port::<u32>::new(CONFIG_DATA).write(value);
+    }
+}
+
+fn read_config(address: u32) -> u32 {
+    let _guard = CONFIG_LOCK.lock();
+    read_config_locked(address)
+}
+
+fn write_config(address: u32, value: u32) {
+    let _guard = CONFIG_LOCK.lock();
+    write_config_locked(address, value);
+}
+
+/// 枚举到的设备列表
+struct DeviceList {
+    devices: [Option<PciDevice>; MAX_PCI_DEVICES],
+    len: usize,
+}
+
+static DEVICES: Once<DeviceList> = Once::new();
+
+/// 探测某个功能，存在时返回设备信息
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let id = read_config(config_address(bus, device, function, REG_VENDOR_ID));
+    let vendor_id = id as u16;
+    if vendor_id == 0xffff {
+        return None;
+    }
+    let class = read_config(config_address(bus, device, function, REG_CLASS));
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        vendor_id,
+        device_id: (id >> (REG_DEVICE_ID * 8)) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+    })
+}
+
+/// 枚举所有总线上的设备
+pub fn init() {
+    let list = DEVICES.call_once(|| {
+        let mut list = DeviceList { devices: [None; MAX_PCI_DEVICES], len: 0 };
+        for bus in 0..=255u8 {
+            for device in 0..32u8 {
+                let Some(first) = probe(bus, device, 0) else { continue };
+                let header_type = first.read_u8(REG_HEADER_TYPE);
+                // 多功能设备需要继续探测功能 1–7
+                let functions = if header_type & 0x80 != 0 { 8 } else { 1 };
+                for function in 0..functions {
+                    let Some(dev) = probe(bus, device, function) else { continue };
+                    if list.len == MAX_PCI_DEVICES {
+                        log::warn!("PCI: too many devices, ignoring {dev}");
+                        continue;
+                    }
+                    list.devices[list.len] = Some(dev);
+                    list.len += 1;
+                }
+            }
+        }
+        list
+    });
+
+    for dev in devices() {
+        let msi = if dev.find_capability(CAP_MSI).is_some() { " MSI" } else { "" };
+        let msix = dev
+            .find_capability(CAP_MSIX)
+            .map(|cap| crate::msi::msix_table_size(dev, cap))
+            .unwrap_or(0);
+        log::info!(
+            "PCI: {} [{:04x}:{:04x}] class {:02x}.{:02x}.{:02x}{}{}",
+            dev,
+            dev.vendor_id,
+            dev.device_id,
+            dev.class,
+            dev.subclass,
+            dev.prog_if,
+            msi,
+            MsixInfo(msix)
+        );
+    }
+    log::info!("PCI: {} device(s) found", list.len);
+}
+
+/// MSI-X 能力的格式化辅助类型
+struct MsixInfo(u16);
+
+impl fmt::Display for MsixInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 > 0 {
+            write!(f, " MSI-X({})", self.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// 遍历已枚举的设备
+pub fn devices() -> impl Iterator<Item = &'static PciDevice> {
+    DEVICES.r#try().into_iter().flat_map(|list| list.devices[..list.len].iter().flatten())
+}
@@ -0,0 +1,153 @@
+//! 每 CPU 数据模块
+//! 内核态运行时 IA32_GS_BASE 指向当前 CPU 的每 CPU 区域，一条带 gs 前缀的指令即可取得 CPU 编号；
+//! 用户态的 GS 基址保存在 IA32_KERNEL_GS_BASE 中，由中断入口在特权级切换时执行 swapgs 交换。
+//! `per_cpu!` 声明的变量为每个 CPU 保存一份按缓存行对齐的副本，避免 CPU 之间的伪共享
+//!
+//! 每 CPU 区域中保存当前任务，运行队列为每 CPU 变量；中断嵌套深度与各项统计由所属模块
+//! 用 `per_cpu!` 声明（softirq、irq、clockevent 等）。调度器尚未实现，当前任务与运行队列
+//! 目前只是占位，始终为空。
+
+use core::arch::asm;
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+use x86_64::VirtAddr;
+use crate::constants::smp::MAX_CPUS;
+use crate::sync::IrqSafeSpinlock;
+
+/// 每 CPU 区域（GS 基址指向这里，字段通过固定偏移访问）
+#[repr(C)]
+struct PerCpuArea {
+    /// 区域自身的地址，通过 gs:0 取得
+    this: AtomicU64,
+    /// CPU 编号
+    cpu: AtomicUsize,
+    /// 当前任务的任务控制块地址，0 表示没有任务（启动流程或空闲循环）
+    current_task: AtomicU64,
+}
+
+static AREAS: [PerCpuArea; MAX_CPUS] = [const {
+    PerCpuArea { this: AtomicU64::new(0), cpu: AtomicUsize::new(0), current_task: AtomicU64::new(0) }
+}; MAX_CPUS];
+
+/// 调度器运行队列（占位：调度器接入前没有任务入队）
+#[allow(dead_code)]
+pub struct RunQueue {
+    /// 就绪任务数
+    pub nr_running: usize,
+}
+
+crate::per_cpu! {
+    // 本 CPU 的运行队列，中断中唤醒任务时也会访问
+    #[allow(dead_code)]
+    pub static RUN_QUEUE: IrqSafeSpinlock<RunQueue> = IrqSafeSpinlock::new(RunQueue { nr_running: 0 });
+}
+
+/// 建立当前 CPU 的每 CPU 区域并设置 GS 基址
+/// 每个 CPU 必须在访问任何每 CPU 变量（包括记录日志）之前调用一次
+pub fn init(cpu: usize) {
+    let area = &AREAS[cpu];
+    area.this.store(area as *const PerCpuArea as u64, Ordering::Relaxed);
+    area.cpu.store(cpu, Ordering::Relaxed);
+    GsBase::write(VirtAddr::from_ptr(area));
+    // 目前没有用户态，用户 GS 基址为 0
+    KernelGsBase::write(VirtAddr::zero());
+}
+
+/// 当前 CPU 的编号
+#[inline]
+pub fn cpu_id() -> usize {
+    let cpu: usize;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) cpu,
+            const offset_of!(PerCpuArea, cpu),
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+    cpu
+}
+
+/// 当前 CPU 上运行的任务（任务控制块地址），没有任务时为 0
+#[inline]
+#[allow(dead_code)]
+pub fn current_task() -> u64 {
+    let task: u64;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) task,
+            const offset_of!(PerCpuArea, current_task),
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+    task
+}
+
+/// 设置当前 CPU 上运行的任务（由调度器在切换任务时调用）
+#[inline]
+#[allow(dead_code)]
+pub fn set_current_task(task: u64) {
+    unsafe {
+        asm!(
+            "mov gs:[{}], {}",
+            const offset_of!(PerCpuArea, current_task),
+            in(reg) task,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// 按缓存行对齐的单个副本
+#[repr(align(64))]
+pub struct CacheAligned<T>(T);
+
+impl<T> CacheAligned<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+/// 每 CPU 变量，通过 `per_cpu!` 声明
+pub struct PerCpu<T> {
+    slots: [CacheAligned<T>; MAX_CPUS],
+}
+
+impl<T> PerCpu<T> {
+    pub const fn new(slots: [CacheAligned<T>; MAX_CPUS]) -> Self {
+        Self { slots }
+    }
+
+    /// 当前 CPU 的副本
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.slots[cpu_id()].0
+    }
+
+    /// 指定 CPU 的副本（用于汇总统计或跨 CPU 通知）
+    pub fn for_cpu(&self, cpu: usize) -> &T {
+        &self.slots[cpu].0
+    }
+}
+
+/// 声明每 CPU 变量，每个 CPU 各持有一份初始值的副本
+///
+/// ```ignore
+/// per_cpu! {
+///     // 每个 CPU 的中断计数
+///     static COUNT: AtomicU64 = AtomicU64::new(0);
+/// }
+/// COUNT.get().fetch_add(1, Ordering::Relaxed);
+/// ```
+#[macro_export]
+macro_rules! per_cpu {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::percpu::PerCpu<$ty> = $crate::percpu::PerCpu::new(
+                [const { $crate::percpu::CacheAligned::new($init) }; $crate::constants::smp::MAX_CPUS],
+            );
+        )+
+    };
+}
@@ -0,0 +1,127 @@
+//! 8259 可编程中断控制器模块
+//! 将主从 PIC 重映射到向量 32–47，负责屏蔽、EOI 与伪中断（IRQ7/IRQ15）检测
+
+use pic8259::ChainedPics;
+use x86_64::instructions::port::Port;
+use crate::acpi::TriggerMode;
+use crate::constants::pic::*;
+use crate::irq::IrqChip;
+use crate::sync::IrqSafeSpinlock;
+
+/// OCW3：下一次读命令端口返回中断服务寄存器（ISR）
+const OCW3_READ_ISR: u8 = 0x0b;
+
+/// 从片级联到主片的 IRQ 线
+const CASCADE_IRQ: u8 = 2;
+
+/// 每个 PIC 上可能产生伪中断的 IRQ（优先级最低的一条线）
+const SPURIOUS_LINE: u8 = 7;
+
+static PICS: IrqSafeSpinlock<ChainedPics> = IrqSafeSpinlock::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+/// 8259 PIC 中断控制器
+pub struct Pic;
+
+pub static PIC: Pic = Pic;
+
+/// 重映射 PIC，除级联线（IRQ2）外全部屏蔽，等待驱动注册
+pub fn init() {
+    {
+        let mut pics = PICS.lock();
+        unsafe {
+            pics.initialize();
+            pics.write_masks(!(1 << CASCADE_IRQ), 0xff);
+        }
+    }
+    log::info!("PIC remapped to vectors {}-{}", PIC_1_OFFSET, PIC_2_OFFSET + 7);
+}
+
+/// 屏蔽 PIC 的全部中断线（由 I/O APIC 接管后调用）
+/// PIC 仍保持重映射，它产生的伪中断落在 32–47 之间可被识别
+pub fn disable() {
+    unsafe { PICS.lock().disable() };
+    log::info!("PIC masked");
+}
+
+/// 判断向量是否属于 PIC
+pub fn handles(vector: u8) -> bool {
+    (PIC_1_OFFSET..PIC_2_OFFSET + 8).contains(&vector)
+}
+
+/// 读取某个 PIC 的中断服务寄存器
+fn read_isr(command_port: u16) -> u8 {
+    let mut port = Port::<u8>::new(command_port);
+    unsafe {
+        port.write(OCW3_READ_ISR);
+        port.read()
+    }
+}
+
+/// 修改一条 IRQ 线的屏蔽位
+fn set_masked(line: u8, masked: bool) {
+    let mut pics = PICS.lock();
+    unsafe {
+        let mut masks = pics.read_masks();
+        let (chip, bit) = ((line / 8) as usize, 1 << (line % 8));
+        if masked {
+            masks[chip] |= bit;
+        } else {
+            masks[chip] &= !bit;
+        }
+        pics.write_masks(masks[0], masks[1]);
+    }
+}
+
+/// 处理已屏蔽 PIC 上出现的中断（I/O APIC 模式下只应是伪中断）
+pub fn handle_stray(vector: u8) {
+    let line = vector - PIC_1_OFFSET;
+    if !PIC.is_spurious(line) {
+        log::warn!("Stray 8259 IRQ {line} while masked");
+        PIC.eoi(line);
+    }
+    crate::irq::count_spurious();
+}
+
+impl IrqChip for Pic {
+    fn name(&self) -> &'static str {
+        "XT-PIC"
+    }
+
+    fn line_count(&self) -> u8 {
+        PIC_IRQ_COUNT as u8
+    }
+
+    fn vector(&self, line: u8) -> u8 {
+        PIC_1_OFFSET + line
+    }
+
+    fn mask(&self, line: u8) {
+        set_masked(line, true);
+    }
+
+    fn unmask(&self, line: u8) {
+        set_masked(line, false);
+    }
+
+    fn eoi(&self, line: u8) {
+        unsafe { PICS.lock().notify_end_of_interrupt(self.vector(line)) };
+    }
+
+    fn trigger_mode(&self, _line: u8) -> TriggerMode {
+        TriggerMode::Edge
+    }
+
+    /// ISR 中对应位未置位，说明中断在 CPU 确认前已撤销
+    fn is_spurious(&self, line: u8) -> bool {
+        if line == SPURIOUS_LINE {
+            // 主片的伪中断不能发送 EOI
+            read_isr(PIC_1_COMMAND) & (1 << SPURIOUS_LINE) == 0
+        } else if line == SPURIOUS_LINE + 8 && read_isr(PIC_2_COMMAND) & (1 << SPURIOUS_LINE) == 0 {
+            // 从片的伪中断仍需向主片的级联线发送 EOI
+            self.eoi(CASCADE_IRQ);
+            true
+        } else {
+            false
+        }
+    }
+}
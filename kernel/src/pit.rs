@@ -0,0 +1,159 @@
+//! 8253/8254 可编程间隔定时器（PIT）模块
+//! - 通道 0 以可配置的频率周期性产生 IRQ0，推进全局单调节拍计数 `jiffies`
+//!   （HPET 启用传统替换路由后，IRQ0 改由其比较器 0 以相同频率产生）
+//! - 通道 2 以轮询方式做精确延迟（不依赖中断），没有 HPET 时作为 TSC 与本地 APIC 定时器的校准基准
+//!
+//! 命令行参数 `pit_hz=N` 设置节拍频率（默认 `DEFAULT_PIT_HZ`）。
+//! 看门狗把 IRQ0 改为 NMI 投递时，由看门狗的 NMI 处理调用 `tick` 推进节拍。
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+use crate::constants::pit::*;
+use crate::irq::{self, IrqReturn};
+use crate::sync::IrqSafeSpinlock;
+
+/// PIT 接在 IRQ0 上
+const PIT_IRQ: u8 = 0;
+
+/// 命令字：通道 0、先低后高字节、模式 2（频率发生器）、二进制计数
+const CMD_CHANNEL0_RATE: u8 = 0x34;
+/// 命令字：通道 2、先低后高字节、模式 0（计数结束时输出变高）、二进制计数
+const CMD_CHANNEL2_ONESHOT: u8 = 0xb0;
+
+/// 系统控制端口 B：通道 2 门控、扬声器使能、通道 2 输出状态
+const PORT_B_GATE2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+/// 当前节拍频率（Hz），通道 0 未启动时为 0
+static HZ: AtomicU64 = AtomicU64::new(0);
+
+/// 启动以来的节拍数
+static JIFFIES: AtomicU64 = AtomicU64::new(0);
+
+/// 通道 2 同一时刻只能服务一次延迟
+static CHANNEL2: IrqSafeSpinlock<()> = IrqSafeSpinlock::new(());
+
+/// 按命令行或默认频率启动通道 0 并注册 IRQ0（在 `irq::init` 之后、开中断之前调用）
+pub fn init() {
+    let hz = crate::cmdline::value("pit_hz")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|hz| (MIN_PIT_HZ..=MAX_PIT_HZ).contains(hz))
+        .unwrap_or(DEFAULT_PIT_HZ);
+    set_frequency(hz);
+    match irq::register(PIT_IRQ, "timer", timer_irq) {
+        Ok(()) => log::info!("PIT: {} Hz tick on IRQ{PIT_IRQ}", frequency()),
+        Err(e) => log::warn!("PIT: cannot register IRQ{PIT_IRQ}: {e}"),
+    }
+}
+
+/// 重新设置通道 0 的节拍频率（实际频率受 16 位分频系数限制）
+pub fn set_frequency(hz: u64) {
+    let divisor = (PIT_FREQUENCY as u64 / hz.max(1)).clamp(1, u16::MAX as u64) as u16;
+    interrupts::without_interrupts(|| unsafe {
+        Port::<u8>::new(PIT_COMMAND).write(CMD_CHANNEL0_RATE);
+        let mut data = Port::<u8>::new(PIT_CHANNEL0);
+        data.write(divisor as u8);
+        data.write((divisor >> 8) as u8);
+    });
+    HZ.store(PIT_FREQUENCY as u64 / divisor as u64, Ordering::Relaxed);
+}
+
+/// 当前节拍频率（Hz），未启动时为 0
+pub fn frequency() -> u64 {
+    HZ.load(Ordering::Relaxed)
+}
+
+/// 启动以来的节拍数（单调递增）
+pub fn jiffies() -> u64 {
+    JIFFIES.load(Ordering::Relaxed)
+}
+
+/// 把毫秒数换算为节拍数（向上取整）
+pub fn ms_to_jiffies(ms: u64) -> u64 {
+    let hz = frequency().max(1);
+    (ms * hz).div_ceil(1000)
+}
+
+/// 把节拍数换算为毫秒数
+#[allow(dead_code)]
+pub fn jiffies_to_ms(jiffies: u64) -> u64 {
+    jiffies * 1000 / frequency().max(1)
+}
+
+/// 推进一个节拍（IRQ0 处理程序或看门狗的 PIT NMI 调用）
+pub fn tick() {
+    JIFFIES.fetch_add(1, Ordering::Relaxed);
+}
+
+fn timer_irq(_line: u8) -> IrqReturn {
+    tick();
+    IrqReturn::Handled
+}
+
+/// 用通道 2 精确延迟 `us` 微秒（轮询计数结束，关中断时也可使用）
+///
+/// 每段最长 `MAX_ONESHOT_US`，其间持有关中断的通道锁。
+pub fn delay_us(us: u64) {
+    let mut remaining = us;
+    while remaining > 0 {
+        // 16 位计数器一次最多约 54.9 毫秒
+        let chunk = remaining.min(MAX_ONESHOT_US);
+        oneshot((chunk * PIT_FREQUENCY as u64).div_ceil(1_000_000).max(1) as u16);
+        remaining -= chunk;
+    }
+}
+
+/// 让通道 2 从 `count` 倒数到 0 并等待
+fn oneshot(count: u16) {
+    let _guard = CHANNEL2.lock();
+    let mut port_b = Port::<u8>::new(SYSTEM_CONTROL_PORT_B);
+    unsafe {
+        // 打开门控、关闭扬声器；写入命令字时输出变低，装入计数后开始倒数
+        let saved = port_b.read();
+        port_b.write((saved & !PORT_B_SPEAKER) | PORT_B_GATE2);
+        Port::<u8>::new(PIT_COMMAND).write(CMD_CHANNEL2_ONESHOT);
+        let mut data = Port::<u8>::new(PIT_CHANNEL2);
+        data.write(count as u8);
+        data.write((count >> 8) as u8);
+        while port_b.read() & PORT_B_OUT2 == 0 {
+            spin_loop();
+        }
+        port_b.write(saved);
+    }
+}
+
+/// 忙等待 `ms` 毫秒（不依赖中断，可在任意上下文使用）
+#[allow(dead_code)]
+pub fn sleep_ms_busy(ms: u64) {
+    delay_us(ms * 1000);
+}
+
+/// 以 hlt 等待 `ms` 毫秒：节拍未启动或中断关闭时退化为忙等待
+///
+/// 节拍中断只投递到 BSP，其他 CPU 上改为自旋观察 `jiffies`。
+#[allow(dead_code)]
+pub fn sleep_ms(ms: u64) {
+    if frequency() == 0 || !interrupts::are_enabled() {
+        return sleep_ms_busy(ms);
+    }
+    // 当前节拍已过去一部分，多等一个节拍保证不少于 `ms`
+    let target = jiffies() + ms_to_jiffies(ms) + 1;
+    let halt = crate::smp::cpu_index() == 0;
+    loop {
+        // 关中断后检查，避免检查与 hlt 之间到来的节拍被错过
+        interrupts::disable();
+        if jiffies() >= target {
+            interrupts::enable();
+            return;
+        }
+        if halt {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+            spin_loop();
+        }
+    }
+}
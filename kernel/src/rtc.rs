@@ -0,0 +1,204 @@
+//! MC146818 CMOS 实时时钟（RTC）模块
+//! 经 CMOS 端口读取日期与时间：
+//! - 等待更新周期（UIP）结束后读取，两次连续读数一致才采用，避免读到跨秒的半更新值
+//! - 按状态寄存器 B 解码 BCD/二进制与 12/24 小时制
+//! - 世纪取自 FADT 指定的 CMOS 寄存器，没有时按 1970–2069 推断
+//!
+//! RTC 按 UTC 解释。
+
+use x86_64::instructions::port::Port;
+use crate::constants::rtc::*;
+use crate::error::{KernelResult, KernelError};
+use crate::sync::IrqSafeSpinlock;
+use crate::time::DateTime;
+
+/// 时间与日期寄存器
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+
+/// 状态寄存器 A：更新进行中
+const REG_STATUS_A: u8 = 0x0a;
+const STATUS_A_UIP: u8 = 1 << 7;
+
+/// 状态寄存器 B：24 小时制、二进制格式
+const REG_STATUS_B: u8 = 0x0b;
+const STATUS_B_24H: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+
+/// 12 小时制下小时寄存器的下午标志
+const HOURS_PM: u8 = 1 << 7;
+
+/// CMOS 索引与数据端口必须成对访问
+static CMOS: IrqSafeSpinlock<()> = IrqSafeSpinlock::new(());
+
+/// 读取一个 CMOS 寄存器（调用者持有 `CMOS`）；索引最高位为 NMI 屏蔽位，保持为 0
+fn cmos_read(reg: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(reg & 0x7f);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
+
+/// 一次读到的原始寄存器值
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Raw {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// 等待更新周期结束，超时返回 false
+fn wait_update_done() -> bool {
+    for _ in 0..RTC_UIP_TIMEOUT_US / 10 {
+        if cmos_read(REG_STATUS_A) & STATUS_A_UIP == 0 {
+            return true;
+        }
+        crate::pit::delay_us(10);
+    }
+    false
+}
+
+fn read_raw(century_reg: u8) -> KernelResult<Raw> {
+    if !wait_update_done() {
+        return Err(KernelError::HardwareError);
+    }
+    Ok(Raw {
+        second: cmos_read(REG_SECONDS),
+        minute: cmos_read(REG_MINUTES),
+        hour: cmos_read(REG_HOURS),
+        day: cmos_read(REG_DAY),
+        month: cmos_read(REG_MONTH),
+        year: cmos_read(REG_YEAR),
+        century: if century_reg != 0 { cmos_read(century_reg) } else { 0 },
+    })
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value & 0x0f) + (value >> 4) * 10
+}
+
+/// 平台是否有 CMOS RTC（FADT 可以声明没有）
+pub fn is_present() -> bool {
+    crate::acpi::fadt().is_none_or(|fadt| fadt.has_cmos_rtc())
+}
+
+/// 读取当前日期与时间
+pub fn read() -> KernelResult<DateTime> {
+    if !is_present() {
+        return Err(KernelError::HardwareError);
+    }
+    let century_reg = crate::acpi::fadt().map_or(0, |fadt| fadt.century);
+    let (raw, status_b) = {
+        let _guard = CMOS.lock();
+        let mut raw = read_raw(century_reg)?;
+        let mut retries = 0;
+        loop {
+            let again = read_raw(century_reg)?;
+            if again == raw {
+                break;
+            }
+            retries += 1;
+            if retries >= RTC_READ_RETRIES {
+                return Err(KernelError::HardwareError);
+            }
+            raw = again;
+        }
+        (raw, cmos_read(REG_STATUS_B))
+    };
+    decode(raw, status_b)
+}
+
+/// 按状态寄存器 B 解码并校验
+fn decode(raw: Raw, status_b: u8) -> KernelResult<DateTime> {
+    let bcd = status_b & STATUS_B_BINARY == 0;
+    let value = |v: u8| if bcd { bcd_to_binary(v) } else { v };
+
+    let pm = raw.hour & HOURS_PM != 0;
+    let mut hour = value(raw.hour & !HOURS_PM);
+    if status_b & STATUS_B_24H == 0 {
+        // 12 小时制：12 AM 为 0 点，12 PM 为 12 点
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+
+    let year = value(raw.year) as u16;
+    let year = match value(raw.century) {
+        century @ 19..=99 => century as u16 * 100 + year,
+        _ if year < 70 => 2000 + year,
+        _ => 1900 + year,
+    };
+
+    let time = DateTime {
+        year,
+        month: value(raw.month),
+        day: value(raw.day),
+        hour,
+        minute: value(raw.minute),
+        second: value(raw.second),
+    };
+    if time.is_valid() { Ok(time) } else { Err(KernelError::HardwareError) }
+}
+
+#[cfg(test)]
+fn raw(year: u8, month: u8, day: u8, hour: u8, minute: u8, second: u8, century: u8) -> Raw {
+    Raw { second, minute, hour, day, month, year, century }
+}
+
+#[test_case]
+fn test_decode_bcd_24h() {
+    let time = decode(raw(0x26, 0x10, 0x18, 0x23, 0x59, 0x58, 0), STATUS_B_24H).unwrap();
+    assert_eq!(time, DateTime { year: 2026, month: 10, day: 18, hour: 23, minute: 59, second: 58 });
+}
+
+#[test_case]
+fn test_decode_binary_24h() {
+    let time = decode(raw(26, 10, 18, 23, 59, 58, 0), STATUS_B_24H | STATUS_B_BINARY).unwrap();
+    assert_eq!(time, DateTime { year: 2026, month: 10, day: 18, hour: 23, minute: 59, second: 58 });
+}
+
+#[test_case]
+fn test_decode_12h() {
+    let hour = |hour, status_b| decode(raw(0x26, 0x01, 0x01, hour, 0, 0, 0), status_b).unwrap().hour;
+    // BCD
+    assert_eq!(hour(0x12, 0), 0);
+    assert_eq!(hour(0x01, 0), 1);
+    assert_eq!(hour(0x11, 0), 11);
+    assert_eq!(hour(HOURS_PM | 0x12, 0), 12);
+    assert_eq!(hour(HOURS_PM | 0x01, 0), 13);
+    assert_eq!(hour(HOURS_PM | 0x11, 0), 23);
+    // 二进制
+    assert_eq!(hour(12, STATUS_B_BINARY), 0);
+    assert_eq!(hour(HOURS_PM | 12, STATUS_B_BINARY), 12);
+    assert_eq!(hour(HOURS_PM | 11, STATUS_B_BINARY), 23);
+}
+
+#[test_case]
+fn test_decode_century() {
+    let year = |year, century| decode(raw(year, 0x01, 0x01, 0, 0, 0, century), STATUS_B_24H).map(|time| time.year);
+    // 世纪寄存器
+    assert_eq!(year(0x26, 0x20), Ok(2026));
+    assert_eq!(year(0x99, 0x19), Ok(1999));
+    assert_eq!(year(0x05, 0x21), Ok(2105));
+    // 没有世纪寄存器时按 1970–2069 推断
+    assert_eq!(year(0x69, 0), Ok(2069));
+    assert_eq!(year(0x70, 0), Ok(1970));
+    assert_eq!(year(0x99, 0), Ok(1999));
+    // 早于 1970 年的日期无效
+    assert!(year(0x50, 0x19).is_err());
+}
+
+#[test_case]
+fn test_decode_rejects_garbage() {
+    assert!(decode(raw(0x26, 0x13, 0x01, 0, 0, 0, 0), STATUS_B_24H).is_err());
+    assert!(decode(raw(0x26, 0x02, 0x30, 0, 0, 0, 0), STATUS_B_24H).is_err());
+    assert!(decode(raw(0x26, 0x01, 0x01, 0x24, 0, 0, 0), STATUS_B_24H).is_err());
+    assert!(decode(raw(0x26, 0x01, 0x01, 0, 0x60, 0, 0), STATUS_B_24H).is_err());
+    assert!(decode(raw(0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff), 0).is_err());
+}
@@ -1,9 +1,10 @@
 use uart_16550::SerialPort;
-use spin::Mutex;
 use crate::constants::serial::*;
 use crate::error::{KernelResult, KernelError};
+use crate::sync::IrqSafeSpinlock;
 
-pub static SERIAL1: Mutex<Option<SerialPort>> = Mutex::new(None);
+/// COM1；持锁期间关中断，中断处理程序中输出不会与被打断的代码死锁
+pub static SERIAL1: IrqSafeSpinlock<Option<SerialPort>> = IrqSafeSpinlock::new(None);
 
 pub fn init_serial() -> KernelResult<()> {
     let mut serial_port = unsafe { SerialPort::new(COM1_BASE) };
@@ -55,6 +56,23 @@ pub fn _print_safe(args: ::core::fmt::Arguments) {
     }
 }
 
+/// 紧急输出：不获取串口锁直接写端口
+/// 用于 NMI 与看门狗报告——持有串口锁的可能正是卡住的 CPU；与其他输出可能交错
+pub fn emergency_write(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    let mut port = unsafe { SerialPort::new(COM1_BASE) };
+    let _ = port.write_fmt(args);
+}
+
+/// 直接写出原始字节（不会panic）
+pub fn write_bytes(bytes: &[u8]) {
+    if let Some(ref mut serial) = *SERIAL1.lock() {
+        for &byte in bytes {
+            serial.send(byte);
+        }
+    }
+}
+
 /// Prints to the host through the serial interface.
 #[macro_export]
 macro_rules! serial_print {
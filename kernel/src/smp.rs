@@ -0,0 +1,437 @@
+//! 多处理器启动模块
+//! 由 Limine 引导时通过其 SMP 请求释放 AP，否则通过 INIT-SIPI-SIPI 与实模式跳板启动 AP；
+//! 每个 AP 拥有独立的内核栈、IST 栈、GDT/TSS 与本地 APIC 设置，共用同一张 IDT
+
+use core::arch::global_asm;
+use core::fmt;
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use x86_64::registers::control::{Cr0, Cr3, Cr4, Cr4Flags};
+use x86_64::registers::model_specific::Efer;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
+use crate::constants::gdt::{IST_STACK_COUNT, IST_STACK_SIZE};
+use crate::constants::smp::*;
+use crate::error::{KernelResult, KernelError};
+use crate::lapic;
+use crate::memory;
+use crate::time::Instant;
+
+/// 每个 CPU 的启动数据
+struct CpuData {
+    apic_id: AtomicU32,
+    /// 初始化完成、开始处理中断后置位
+    online: AtomicBool,
+    /// 内核栈顶
+    stack_top: AtomicU64,
+    /// IST 栈顶（按 IST 索引排列）
+    ist: [AtomicU64; IST_STACK_COUNT],
+}
+
+impl CpuData {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(0),
+            online: AtomicBool::new(false),
+            stack_top: AtomicU64::new(0),
+            ist: [const { AtomicU64::new(0) }; IST_STACK_COUNT],
+        }
+    }
+}
+
+/// CPU 编号即数组下标，编号 0 为 BSP
+static CPUS: [CpuData; MAX_CPUS] = [const { CpuData::new() }; MAX_CPUS];
+
+/// 已分配编号的 CPU 数量（包括启动失败的 AP）
+static CPU_SLOTS: AtomicUsize = AtomicUsize::new(1);
+
+/// 已上线的 CPU 数量
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+
+/// BSP 的页表与控制寄存器，AP 进入内核后照此设置
+static KERNEL_CR3: AtomicU64 = AtomicU64::new(0);
+static BOOT_CR0: AtomicU64 = AtomicU64::new(0);
+static BOOT_CR4: AtomicU64 = AtomicU64::new(0);
+static BOOT_EFER: AtomicU64 = AtomicU64::new(0);
+
+// 实模式跳板：由 SIPI 在 1MiB 以下的页中启动，直接从实模式进入长模式后跳到 ap_entry64
+// 代码与数据都按相对跳板起点的偏移访问，复制到任意页边界上都能运行
+global_asm!(
+    ".section .rodata.smp_trampoline, \"a\"",
+    ".global smp_trampoline_start",
+    ".global smp_trampoline_data",
+    ".global smp_trampoline_end",
+    ".p2align 4",
+    "smp_trampoline_start:",
+    ".code16",
+    "    cli",
+    "    cld",
+    "    movw %cs, %ax",
+    "    movw %ax, %ds",
+    // CR4.PAE，并加载临时页表（实模式下只能装入 32 位地址）
+    "    movl %cr4, %eax",
+    "    orl $0x20, %eax",
+    "    movl %eax, %cr4",
+    "    movl tramp_cr3 - smp_trampoline_start, %eax",
+    "    movl %eax, %cr3",
+    // EFER.LME | EFER.NXE（内核页表使用了 NX 位）
+    "    movl $0xc0000080, %ecx",
+    "    rdmsr",
+    "    orl $0x900, %eax",
+    "    wrmsr",
+    "    lgdtl tramp_gdtr - smp_trampoline_start",
+    // 同时打开保护模式与分页（PE | ET | WP | PG），并清除复位后置位的 CD/NW
+    "    movl $0x80010011, %eax",
+    "    movl %eax, %cr0",
+    "    ljmpl *tramp_far - smp_trampoline_start",
+    ".code64",
+    "tramp_long:",
+    "    movw $0x10, %ax",
+    "    movw %ax, %ds",
+    "    movw %ax, %es",
+    "    movw %ax, %ss",
+    "    movq tramp_cpu(%rip), %rdi",
+    "    movq tramp_kernel_cr3(%rip), %rsi",
+    "    movq tramp_stack(%rip), %rdx",
+    "    movq tramp_entry(%rip), %rax",
+    "    jmpq *%rax",
+    // 数据区，布局与 TrampolineData 一致；基址相关的字段先存相对偏移，安装时再加上页地址
+    "    .p2align 3",
+    "smp_trampoline_data:",
+    "tramp_gdt:",
+    "    .quad 0",
+    "    .quad 0x00af9a000000ffff",
+    "    .quad 0x00cf92000000ffff",
+    "tramp_gdtr:",
+    "    .word 23",
+    "    .long tramp_gdt - smp_trampoline_start",
+    "    .word 0",
+    "tramp_far:",
+    "    .long tramp_long - smp_trampoline_start",
+    "    .word 0x08",
+    "    .word 0",
+    "tramp_cr3:",
+    "    .long 0",
+    "    .long 0",
+    "tramp_kernel_cr3:",
+    "    .quad 0",
+    "tramp_entry:",
+    "    .quad 0",
+    "tramp_cpu:",
+    "    .quad 0",
+    "tramp_stack:",
+    "    .quad 0",
+    "smp_trampoline_end:",
+    ".previous",
+    "",
+    // AP 的 64 位内核入口：rdi = CPU 编号，rsi = 内核页表，rdx = 栈顶
+    ".section .text.smp, \"ax\"",
+    ".global ap_entry64",
+    "ap_entry64:",
+    "    movq %rsi, %cr3",
+    "    movq %rdx, %rsp",
+    "    xorl %ebp, %ebp",
+    "    call ap_main",
+    "    ud2",
+    ".previous",
+    options(att_syntax)
+);
+
+extern "C" {
+    static smp_trampoline_start: u8;
+    static smp_trampoline_data: u8;
+    static smp_trampoline_end: u8;
+    fn ap_entry64(cpu: usize, cr3: u64, stack: u64) -> !;
+}
+
+/// 跳板末尾的数据区
+#[repr(C, packed)]
+struct TrampolineData {
+    gdt: [u64; 3],
+    gdtr_limit: u16,
+    gdtr_base: u32,
+    _pad0: u16,
+    far_offset: u32,
+    far_selector: u16,
+    _pad1: u16,
+    /// 临时页表（必须低于 4GiB）
+    cr3: u32,
+    _pad2: u32,
+    kernel_cr3: u64,
+    entry: u64,
+    cpu: u64,
+    stack: u64,
+}
+
+/// 当前 CPU 的编号（从每 CPU 区域读取）
+pub fn cpu_index() -> usize {
+    crate::percpu::cpu_id()
+}
+
+/// 已分配编号的 CPU 数量（统计表按此输出各 CPU 的列）
+pub fn cpu_count() -> usize {
+    CPU_SLOTS.load(Ordering::Acquire)
+}
+
+/// 已上线的 CPU 数量
+pub fn online_count() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// 在线 CPU 的 APIC ID，CPU 不存在或未上线时为 None
+pub fn apic_id(cpu: usize) -> Option<u32> {
+    let data = CPUS.get(cpu)?;
+    data.online.load(Ordering::Acquire).then(|| data.apic_id.load(Ordering::Relaxed))
+}
+
+/// 已上线的 CPU 列表
+pub struct OnlineCpus;
+
+/// 获取已上线的 CPU 列表
+pub fn online_cpus() -> OnlineCpus {
+    OnlineCpus
+}
+
+impl fmt::Display for OnlineCpus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", online_count())?;
+        for (index, cpu) in CPUS[..cpu_count()].iter().enumerate() {
+            if cpu.online.load(Ordering::Acquire) {
+                write!(f, " CPU{} (APIC {})", index, cpu.apic_id.load(Ordering::Relaxed))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 微秒级延迟（以 PIT 通道 2 计时）
+fn udelay(us: u64) {
+    crate::pit::delay_us(us);
+}
+
+/// 启动所有 AP（BSP 的中断控制器初始化完成后调用）
+pub fn init() {
+    let bsp = &CPUS[0];
+    if lapic::is_enabled() {
+        bsp.apic_id.store(lapic::id(), Ordering::Relaxed);
+    }
+    bsp.online.store(true, Ordering::Release);
+
+    KERNEL_CR3.store(Cr3::read().0.start_address().as_u64(), Ordering::Relaxed);
+    BOOT_CR0.store(Cr0::read_raw(), Ordering::Relaxed);
+    BOOT_CR4.store(Cr4::read_raw(), Ordering::Relaxed);
+    BOOT_EFER.store(Efer::read_raw(), Ordering::Relaxed);
+
+    let start = Instant::now();
+    if crate::cmdline::has_flag("nosmp") {
+        log::info!("SMP: disabled (nosmp)");
+    } else if !lapic::is_enabled() {
+        log::info!("SMP: local APIC unavailable, running on the BSP only");
+    } else {
+        #[cfg(feature = "limine")]
+        let started = start_limine();
+        #[cfg(not(feature = "limine"))]
+        let started = false;
+        if !started {
+            start_trampoline();
+        }
+    }
+    log::info!("SMP: CPUs online {} ({} ms)", online_cpus(), start.elapsed().as_millis());
+}
+
+/// 为 AP 分配编号、内核栈与 IST 栈
+fn prepare_cpu(apic_id: u32) -> KernelResult<usize> {
+    let cpu = CPU_SLOTS.load(Ordering::Relaxed);
+    if cpu >= MAX_CPUS {
+        return Err(KernelError::NoResources);
+    }
+    let data = &CPUS[cpu];
+    data.apic_id.store(apic_id, Ordering::Relaxed);
+    data.stack_top.store(allocate_stack(AP_STACK_SIZE)?.as_u64(), Ordering::Relaxed);
+    for ist in &data.ist {
+        ist.store(allocate_stack(IST_STACK_SIZE)?.as_u64(), Ordering::Relaxed);
+    }
+    CPU_SLOTS.store(cpu + 1, Ordering::Release);
+    Ok(cpu)
+}
+
+/// 从直接映射中分配一段栈，返回栈顶
+fn allocate_stack(size: usize) -> KernelResult<VirtAddr> {
+    let phys = memory::allocate_frames((size / 4096) as u64)?;
+    Ok(memory::phys_to_virt(phys) + size)
+}
+
+/// 准备并启动一个 AP，等待它上线
+fn start_cpu(apic_id: u32, kick: impl FnOnce(usize)) {
+    let cpu = match prepare_cpu(apic_id) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            log::warn!("SMP: cannot start APIC {apic_id}: {e}");
+            return;
+        }
+    };
+    crate::tsc::stamp_ap_start();
+    kick(cpu);
+
+    let online = &CPUS[cpu].online;
+    for _ in 0..AP_STARTUP_TIMEOUT_US / 100 {
+        if online.load(Ordering::Acquire) {
+            return;
+        }
+        udelay(100);
+    }
+    if !online.load(Ordering::Acquire) {
+        log::warn!("SMP: CPU{cpu} (APIC {apic_id}) did not come online");
+    }
+}
+
+/// 通过 Limine 的 SMP 响应启动 AP；引导加载程序未响应时返回 false
+#[cfg(feature = "limine")]
+fn start_limine() -> bool {
+    let Some((bsp_lapic_id, processors)) = crate::limine_protocol::smp_processors() else {
+        return false;
+    };
+    for info in processors.filter(|info| info.lapic_id != bsp_lapic_id) {
+        start_cpu(info.lapic_id, |cpu| info.start(limine_ap_entry, cpu as u64));
+    }
+    true
+}
+
+/// Limine 释放的 AP 从这里进入（已处于长模式，使用引导加载程序的栈）
+#[cfg(feature = "limine")]
+extern "C" fn limine_ap_entry(info: &'static crate::limine_protocol::LimineSmpInfo) -> ! {
+    let cpu = info.argument() as usize;
+    unsafe {
+        ap_entry64(cpu, KERNEL_CR3.load(Ordering::Relaxed), CPUS[cpu].stack_top.load(Ordering::Relaxed))
+    }
+}
+
+/// 安装实模式跳板，按 MADT 逐个发送 INIT-SIPI-SIPI
+fn start_trampoline() {
+    let Some(madt) = crate::acpi::madt() else {
+        log::warn!("SMP: no MADT, cannot enumerate application processors");
+        return;
+    };
+    let Some(page) = memory::low_memory_page(TRAMPOLINE_MIN_ADDRESS) else {
+        log::warn!("SMP: no free page below 1MiB for the AP trampoline");
+        return;
+    };
+    let data = match trampoline_page_table().map(|table| install_trampoline(page, table)) {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("SMP: cannot set up the AP trampoline: {e}");
+            return;
+        }
+    };
+    log::info!("SMP: AP trampoline at {page:#x}");
+
+    let bsp = CPUS[0].apic_id.load(Ordering::Relaxed);
+    // xAPIC 的 ICR 只能寻址 8 位 APIC ID
+    let reachable = |apic_id: u32| lapic::mode() == lapic::Mode::X2Apic || apic_id <= 0xff;
+    for entry in madt.local_apics().filter(|entry| entry.usable && entry.apic_id != bsp) {
+        if !reachable(entry.apic_id) {
+            log::warn!("SMP: APIC {} is not addressable in xAPIC mode", entry.apic_id);
+            continue;
+        }
+        start_cpu(entry.apic_id, |cpu| {
+            unsafe {
+                (*data).cpu = cpu as u64;
+                (*data).stack = CPUS[cpu].stack_top.load(Ordering::Relaxed);
+            }
+            // AP 读取跳板数据之前必须全部可见
+            fence(Ordering::SeqCst);
+            lapic::send_init(entry.apic_id);
+            udelay(10_000);
+            for _ in 0..2 {
+                lapic::send_startup(entry.apic_id, (page >> 12) as u8);
+                udelay(200);
+            }
+        });
+    }
+}
+
+/// 把跳板复制到低端页并填好数据区，返回数据区指针
+fn install_trampoline(page: u64, table: u64) -> *mut TrampolineData {
+    let start = core::ptr::addr_of!(smp_trampoline_start);
+    let data_offset = core::ptr::addr_of!(smp_trampoline_data) as usize - start as usize;
+    let len = core::ptr::addr_of!(smp_trampoline_end) as usize - start as usize;
+    let target = memory::phys_to_virt(PhysAddr::new(page)).as_mut_ptr::<u8>();
+    unsafe {
+        core::ptr::copy_nonoverlapping(start, target, len);
+        let data = target.add(data_offset) as *mut TrampolineData;
+        (*data).gdtr_base += page as u32;
+        (*data).far_offset += page as u32;
+        (*data).cr3 = table as u32;
+        (*data).kernel_cr3 = KERNEL_CR3.load(Ordering::Relaxed);
+        (*data).entry = ap_entry64 as usize as u64;
+        data
+    }
+}
+
+/// 为跳板准备临时页表：复制内核页表，并将低端 2MiB 恒等映射为可执行
+/// 开启分页后 AP 仍在跳板所在的物理地址上执行，跳转到内核后再切换到内核页表
+fn trampoline_page_table() -> KernelResult<u64> {
+    // PML4、PDPT、PD 各一页；实模式下 CR3 只能装入 32 位地址
+    let frames = memory::allocate_frames(3)?;
+    if frames.as_u64() + 3 * 4096 > 1 << 32 {
+        return Err(KernelError::NoResources);
+    }
+    let table = |index: u64| unsafe { &mut *memory::phys_to_virt(frames + index * 4096).as_mut_ptr::<PageTable>() };
+    let kernel = |phys: PhysAddr| unsafe { &*memory::phys_to_virt(phys).as_ptr::<PageTable>() };
+    let (pml4, pdpt, pd) = (table(0), table(1), table(2));
+
+    // 低端 512GiB 中内核已有的映射（如恒等映射的内核映像）也保留下来
+    let kernel_pml4 = kernel(Cr3::read().0.start_address());
+    for (entry, kernel_entry) in pml4.iter_mut().zip(kernel_pml4.iter()) {
+        *entry = kernel_entry.clone();
+    }
+    if let Ok(frame) = kernel_pml4[0].frame() {
+        for (entry, kernel_entry) in pdpt.iter_mut().zip(kernel(frame.start_address()).iter()) {
+            *entry = kernel_entry.clone();
+        }
+    }
+    if let Ok(frame) = pdpt[0].frame() {
+        for (entry, kernel_entry) in pd.iter_mut().zip(kernel(frame.start_address()).iter()) {
+            *entry = kernel_entry.clone();
+        }
+    }
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    pd[0].set_addr(PhysAddr::new(0), flags | PageTableFlags::HUGE_PAGE);
+    pdpt[0].set_addr(frames + 2 * 4096u64, flags);
+    pml4[0].set_addr(frames + 4096u64, flags);
+    Ok(frames.as_u64())
+}
+
+/// AP 的 Rust 入口（由 ap_entry64 在切换到内核页表与内核栈之后调用）
+#[no_mangle]
+extern "C" fn ap_main(cpu: usize) -> ! {
+    crate::percpu::init(cpu);
+    crate::tsc::check_ap(cpu);
+    let data = &CPUS[cpu];
+    unsafe {
+        Efer::write_raw(BOOT_EFER.load(Ordering::Relaxed));
+        Cr0::write_raw(BOOT_CR0.load(Ordering::Relaxed));
+        // CR4.MCE 等 IDT 就绪后由 mce::init_ap 打开，否则这期间的 #MC 会导致三重错误
+        Cr4::write_raw(BOOT_CR4.load(Ordering::Relaxed) & !Cr4Flags::MACHINE_CHECK_EXCEPTION.bits());
+    }
+    crate::fpu::init_ap();
+    gdt_init(cpu, data);
+    crate::interrupts::load_idt();
+    crate::mce::init_ap();
+    lapic::init_local();
+    crate::clockevent::setup_cpu();
+    crate::watchdog::start_cpu();
+
+    data.online.store(true, Ordering::Release);
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    log::info!("SMP: CPU{cpu} online (APIC {})", lapic::id());
+
+    x86_64::instructions::interrupts::enable();
+    crate::kernel_main_loop()
+}
+
+/// 加载 AP 自己的 GDT 与 TSS
+fn gdt_init(cpu: usize, data: &CpuData) {
+    let ist = core::array::from_fn(|i| VirtAddr::new(data.ist[i].load(Ordering::Relaxed)));
+    crate::gdt::init_ap(cpu, ist);
+}
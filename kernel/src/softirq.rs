@@ -0,0 +1,217 @@
+//! 软中断与 tasklet 模块
+//! 硬中断处理程序只做最少的工作，其余部分通过软中断推迟到中断退出时、开中断的环境中执行
+//!
+//! 与软中断共用数据的进程上下文代码用 `local_bh_disable`/`local_bh_enable` 关闭软中断，
+//! 不必关闭硬中断。
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use x86_64::instructions::interrupts;
+use crate::constants::softirq::*;
+use crate::per_cpu;
+use crate::sync::IrqSafeSpinlock;
+
+/// 软中断向量（按位号排列，位号越小越先执行）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Softirq {
+    /// 执行已调度的 tasklet
+    Tasklet = 0,
+}
+
+impl Softirq {
+    const ALL: [Softirq; 1] = [Softirq::Tasklet];
+
+    fn run(self) {
+        match self {
+            Softirq::Tasklet => run_tasklets(),
+        }
+    }
+}
+
+per_cpu! {
+    // 待处理的软中断位图
+    static PENDING: AtomicU32 = AtomicU32::new(0);
+    // 硬中断嵌套深度
+    static HARDIRQ_DEPTH: AtomicU32 = AtomicU32::new(0);
+    // 是否正在执行软中断
+    static IN_SOFTIRQ: AtomicBool = AtomicBool::new(false);
+    // 软中断关闭区段的嵌套深度
+    static BH_DISABLE_DEPTH: AtomicU32 = AtomicU32::new(0);
+}
+
+/// 在当前 CPU 上触发软中断
+pub fn raise(softirq: Softirq) {
+    PENDING.get().fetch_or(1 << softirq as u32, Ordering::Release);
+}
+
+/// 进入硬中断上下文
+pub fn irq_enter() {
+    HARDIRQ_DEPTH.get().fetch_add(1, Ordering::Relaxed);
+}
+
+/// 离开硬中断上下文；最外层中断退出时执行待处理的软中断
+/// 必须在关中断状态下调用（中断处理程序末尾，EOI 之后）
+pub fn irq_exit() {
+    if HARDIRQ_DEPTH.get().fetch_sub(1, Ordering::Relaxed) == 1
+        && !IN_SOFTIRQ.get().load(Ordering::Relaxed)
+        && BH_DISABLE_DEPTH.get().load(Ordering::Relaxed) == 0
+        && PENDING.get().load(Ordering::Acquire) != 0
+    {
+        do_softirq();
+    }
+}
+
+/// 当前是否处于中断上下文（硬中断或软中断）
+pub fn in_interrupt() -> bool {
+    in_hardirq() || in_softirq()
+}
+
+/// 当前是否处于硬中断上下文
+pub fn in_hardirq() -> bool {
+    HARDIRQ_DEPTH.get().load(Ordering::Relaxed) != 0
+}
+
+/// 当前是否正在执行软中断
+pub fn in_softirq() -> bool {
+    IN_SOFTIRQ.get().load(Ordering::Relaxed)
+}
+
+/// 软中断能否打断当前代码（不考虑硬中断是否打开）
+pub fn softirqs_enabled() -> bool {
+    !in_interrupt() && BH_DISABLE_DEPTH.get().load(Ordering::Relaxed) == 0
+}
+
+/// 关闭当前 CPU 的软中断（可嵌套），必须与 `local_bh_enable` 成对调用
+///
+/// 期间硬中断照常处理，它们触发的软中断推迟到最外层 `local_bh_enable` 时执行。
+#[allow(dead_code)]
+pub fn local_bh_disable() {
+    BH_DISABLE_DEPTH.get().fetch_add(1, Ordering::Relaxed);
+}
+
+/// 结束软中断关闭区段；最外层结束且中断打开时执行期间积压的软中断
+#[allow(dead_code)]
+pub fn local_bh_enable() {
+    let depth = BH_DISABLE_DEPTH.get().fetch_sub(1, Ordering::Relaxed);
+    assert!(depth > 0, "local_bh_enable without local_bh_disable");
+    // 软中断执行时会打开中断，关中断的调用者只能留给下一次中断退出或空闲循环
+    if depth == 1 && interrupts::are_enabled() {
+        run_pending();
+    }
+}
+
+/// 执行待处理的软中断（调用时中断已关闭，返回时同样保持关闭）
+fn do_softirq() {
+    IN_SOFTIRQ.get().store(true, Ordering::Relaxed);
+    // 软中断执行期间可能再次被触发，限制重试次数避免饿死被中断的代码，剩余的留到下一次
+    for _ in 0..MAX_SOFTIRQ_RESTART {
+        let pending = PENDING.get().swap(0, Ordering::Acquire);
+        if pending == 0 {
+            break;
+        }
+        interrupts::enable();
+        for softirq in Softirq::ALL {
+            if pending & (1 << softirq as u32) != 0 {
+                softirq.run();
+            }
+        }
+        interrupts::disable();
+    }
+    IN_SOFTIRQ.get().store(false, Ordering::Relaxed);
+}
+
+/// 在进程上下文（空闲循环）中执行遗留的软中断
+pub fn run_pending() {
+    interrupts::without_interrupts(|| {
+        if softirqs_enabled() && PENDING.get().load(Ordering::Acquire) != 0 {
+            do_softirq();
+        }
+    });
+}
+
+/// 待处理的软中断是否存在
+pub fn has_pending() -> bool {
+    PENDING.get().load(Ordering::Acquire) != 0
+}
+
+/// Tasklet：在软中断上下文中执行的延迟函数
+/// 同一个 tasklet 在任意时刻最多只在一个 CPU 上运行，重复调度会合并为一次
+pub struct Tasklet {
+    name: &'static str,
+    func: fn(usize),
+    data: usize,
+    scheduled: AtomicBool,
+    running: AtomicBool,
+}
+
+impl Tasklet {
+    #[allow(dead_code)]
+    pub const fn new(name: &'static str, func: fn(usize), data: usize) -> Self {
+        Self {
+            name,
+            func,
+            data,
+            scheduled: AtomicBool::new(false),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// 调度 tasklet 在当前 CPU 上执行（可在硬中断中调用）
+    #[allow(dead_code)]
+    pub fn schedule(&'static self) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            enqueue(self);
+        }
+    }
+}
+
+/// 每个 CPU 的 tasklet 队列
+struct TaskletQueue {
+    items: [Option<&'static Tasklet>; TASKLET_QUEUE_LEN],
+    len: usize,
+}
+
+per_cpu! {
+    static TASKLETS: IrqSafeSpinlock<TaskletQueue> = IrqSafeSpinlock::new(TaskletQueue { items: [None; TASKLET_QUEUE_LEN], len: 0 });
+}
+
+/// 把 tasklet 放入当前 CPU 的队列并触发软中断
+fn enqueue(tasklet: &'static Tasklet) {
+    let queued = {
+        let mut queue = TASKLETS.get().lock();
+        let len = queue.len;
+        if len < TASKLET_QUEUE_LEN {
+            queue.items[len] = Some(tasklet);
+            queue.len += 1;
+        }
+        len < TASKLET_QUEUE_LEN
+    };
+    if queued {
+        raise(Softirq::Tasklet);
+    } else {
+        tasklet.scheduled.store(false, Ordering::Release);
+        log::warn!("Tasklet queue full, dropping \"{}\"", tasklet.name);
+    }
+}
+
+/// 执行当前 CPU 队列中的 tasklet
+fn run_tasklets() {
+    let (items, len) = {
+        let mut queue = TASKLETS.get().lock();
+        let taken = (queue.items, queue.len);
+        queue.len = 0;
+        taken
+    };
+
+    for tasklet in items[..len].iter().flatten() {
+        // 正在其他 CPU 上运行，留到下一轮
+        if tasklet.running.swap(true, Ordering::Acquire) {
+            enqueue(tasklet);
+            continue;
+        }
+        // 先清除调度标志，允许 tasklet 在执行期间被重新调度
+        tasklet.scheduled.store(false, Ordering::Release);
+        (tasklet.func)(tasklet.data);
+        tasklet.running.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,379 @@
+//! 同步原语模块
+//! 可嵌套的关中断区段、持有期间关中断的自旋锁、公平的票据锁与读写锁
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
+use crate::per_cpu;
+
+per_cpu! {
+    // 关中断区段的嵌套深度
+    static IRQ_OFF_DEPTH: AtomicUsize = AtomicUsize::new(0);
+    // 最外层区段开始前中断是否打开
+    static IRQ_WAS_ENABLED: AtomicBool = AtomicBool::new(false);
+}
+
+/// 进入关中断区段（可嵌套），必须与 `pop_irq_off` 成对调用
+///
+/// 只有最外层区段记录进入前的中断状态，嵌套区段按任意顺序结束都不会提前开中断。
+pub fn push_irq_off() {
+    let enabled = interrupts::are_enabled();
+    interrupts::disable();
+    if IRQ_OFF_DEPTH.get().fetch_add(1, Ordering::Relaxed) == 0 {
+        IRQ_WAS_ENABLED.get().store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// 结束关中断区段，最外层结束时恢复进入前的中断状态
+pub fn pop_irq_off() {
+    let depth = IRQ_OFF_DEPTH.get().fetch_sub(1, Ordering::Relaxed);
+    assert!(depth > 0, "pop_irq_off without push_irq_off");
+    if depth == 1 && IRQ_WAS_ENABLED.get().load(Ordering::Relaxed) {
+        interrupts::enable();
+    }
+}
+
+/// 关中断区段守卫，离开作用域时调用 `pop_irq_off`
+pub struct IrqOff {
+    // 区段状态属于当前 CPU，守卫不能跨 CPU 传递
+    _not_send: PhantomData<*const ()>,
+}
+
+/// 进入关中断区段并返回守卫
+pub fn irq_off() -> IrqOff {
+    push_irq_off();
+    IrqOff { _not_send: PhantomData }
+}
+
+impl Drop for IrqOff {
+    fn drop(&mut self) {
+        pop_irq_off();
+    }
+}
+
+/// 通知锁依赖验证器即将获取锁（未启用 `lockdep` 特性时为空操作）；`trylock` 表示不等待的获取
+#[inline(always)]
+fn lockdep_acquire<L>(lock: &L, site: &'static Location<'static>, trylock: bool) {
+    #[cfg(feature = "lockdep")]
+    crate::lockdep::acquire(lock as *const L as usize, site, trylock);
+    #[cfg(not(feature = "lockdep"))]
+    let _ = (lock, site, trylock);
+}
+
+/// 通知锁依赖验证器已释放锁
+#[inline(always)]
+fn lockdep_release<L>(lock: &L) {
+    #[cfg(feature = "lockdep")]
+    crate::lockdep::release(lock as *const L as usize);
+    #[cfg(not(feature = "lockdep"))]
+    let _ = lock;
+}
+
+/// 票据锁：按申请顺序获得锁，避免高争用下个别 CPU 饿死
+pub struct TicketLock<T> {
+    next: AtomicU32,
+    serving: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for TicketLock<T> {}
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { next: AtomicU32::new(0), serving: AtomicU32::new(0), data: UnsafeCell::new(data) }
+    }
+
+    /// 取号并等待叫号
+    #[track_caller]
+    pub fn lock(&self) -> TicketGuard<'_, T> {
+        lockdep_acquire(self, Location::caller(), false);
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+        while self.serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        TicketGuard { lock: self }
+    }
+
+    /// 锁空闲时立即获取，否则返回 None
+    ///
+    /// 不等待就不会因加锁顺序死锁：锁依赖验证器记录持有与中断安全性，但不检查顺序。
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<TicketGuard<'_, T>> {
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.next
+            .compare_exchange(serving, serving.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        lockdep_acquire(self, Location::caller(), true);
+        Some(TicketGuard { lock: self })
+    }
+}
+
+/// 票据锁守卫，离开作用域时叫下一个号
+pub struct TicketGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+}
+
+impl<T> Deref for TicketGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for TicketGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for TicketGuard<'_, T> {
+    fn drop(&mut self) {
+        // 只有持有者会推进叫号，无需原子加
+        let next = self.lock.serving.load(Ordering::Relaxed).wrapping_add(1);
+        self.lock.serving.store(next, Ordering::Release);
+        lockdep_release(self.lock);
+    }
+}
+
+/// 持有期间关闭本地中断的自旋锁，中断处理程序与进程上下文可安全共用
+///
+/// 基于票据锁实现；释放锁之后才恢复中断状态。
+pub struct IrqSafeSpinlock<T> {
+    inner: TicketLock<T>,
+}
+
+impl<T> IrqSafeSpinlock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { inner: TicketLock::new(data) }
+    }
+
+    /// 关中断并获取锁
+    #[track_caller]
+    pub fn lock(&self) -> IrqSafeGuard<'_, T> {
+        let irq = irq_off();
+        IrqSafeGuard { guard: self.inner.lock(), _irq: irq }
+    }
+
+    /// 锁空闲时关中断并获取，否则返回 None
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<IrqSafeGuard<'_, T>> {
+        let irq = irq_off();
+        Some(IrqSafeGuard { guard: self.inner.try_lock()?, _irq: irq })
+    }
+}
+
+/// `IrqSafeSpinlock` 的守卫（字段按声明顺序析构：先解锁，后恢复中断）
+pub struct IrqSafeGuard<'a, T> {
+    guard: TicketGuard<'a, T>,
+    _irq: IrqOff,
+}
+
+impl<T> Deref for IrqSafeGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSafeGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+/// 写者持有锁
+const WRITER: u32 = 1 << 31;
+/// 有写者在等待，新读者暂缓进入（写者优先，避免写者饿死）
+const WRITER_WAITING: u32 = 1 << 30;
+/// 读者计数
+const READERS: u32 = WRITER_WAITING - 1;
+
+/// 读写自旋锁：允许多个读者并发，写者独占
+///
+/// 不关中断；在中断处理程序中读取的数据，写者需自行处于关中断区段内。
+pub struct RwLock<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { state: AtomicU32::new(0), data: UnsafeCell::new(data) }
+    }
+
+    /// 获取读锁
+    #[track_caller]
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        lockdep_acquire(self, Location::caller(), false);
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & (WRITER | WRITER_WAITING) == 0
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return RwLockReadGuard { lock: self };
+            }
+            spin_loop();
+        }
+    }
+
+    /// 获取写锁
+    #[track_caller]
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        lockdep_acquire(self, Location::caller(), false);
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & (WRITER | READERS) == 0 {
+                // 拿到锁时清除等待位，其他仍在等待的写者会重新设置
+                if self
+                    .state
+                    .compare_exchange_weak(state, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return RwLockWriteGuard { lock: self };
+                }
+            } else if state & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+            spin_loop();
+        }
+    }
+}
+
+/// 读锁守卫
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+        lockdep_release(self.lock);
+    }
+}
+
+/// 写锁守卫
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_and(!WRITER, Ordering::Release);
+        lockdep_release(self.lock);
+    }
+}
+
+#[test_case]
+fn test_nested_irq_off_restores_outer_state() {
+    let was_enabled = interrupts::are_enabled();
+    interrupts::enable();
+    push_irq_off();
+    push_irq_off();
+    assert!(!interrupts::are_enabled());
+    pop_irq_off();
+    assert!(!interrupts::are_enabled(), "inner section re-enabled interrupts");
+    pop_irq_off();
+    assert!(interrupts::are_enabled());
+
+    // 守卫可以不按嵌套顺序析构
+    let outer = irq_off();
+    let inner = irq_off();
+    drop(outer);
+    assert!(!interrupts::are_enabled());
+    drop(inner);
+    assert!(interrupts::are_enabled());
+
+    // 进入前已关中断时，结束后保持关闭
+    interrupts::disable();
+    drop(irq_off());
+    assert!(!interrupts::are_enabled());
+    if was_enabled {
+        interrupts::enable();
+    }
+}
+
+#[test_case]
+fn test_irq_safe_spinlock_disables_interrupts_while_held() {
+    let lock = IrqSafeSpinlock::new(0);
+    let was_enabled = interrupts::are_enabled();
+    interrupts::enable();
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        assert!(!interrupts::are_enabled());
+        assert!(lock.try_lock().is_none());
+    }
+    assert!(interrupts::are_enabled());
+    assert_eq!(lock.try_lock().map(|guard| *guard), Some(1));
+    if !was_enabled {
+        interrupts::disable();
+    }
+}
+
+#[test_case]
+fn test_ticket_lock_serves_in_order() {
+    let lock = TicketLock::new(());
+    for _ in 0..3 {
+        drop(lock.lock());
+    }
+    assert_eq!(lock.next.load(Ordering::Relaxed), 3);
+    assert_eq!(lock.serving.load(Ordering::Relaxed), 3);
+    let guard = lock.lock();
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+    assert!(lock.try_lock().is_some());
+}
+
+#[test_case]
+fn test_rwlock_readers_share_writers_exclude() {
+    let lock = RwLock::new(0);
+    {
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(*first + *second, 0);
+        assert_eq!(lock.state.load(Ordering::Relaxed), 2);
+    }
+    {
+        let mut writer = lock.write();
+        *writer = 7;
+        assert_eq!(lock.state.load(Ordering::Relaxed), WRITER);
+    }
+    assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+    assert_eq!(*lock.read(), 7);
+    assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,357 @@
+//! 时钟源模块
+//! 在可用的计时硬件中选择一个作为单调时钟：
+//! - `tsc`：不变 TSC，读取开销最小、精度最高
+//! - `hpet`：HPET 64 位主计数器，纳秒级精度
+//! - `jiffies`：全局节拍计数，精度为一个节拍
+//!
+//! 默认选择精度最高的可用时钟源，命令行参数 `clocksource=NAME` 可以指定。
+//! TSC 运行中被判定为不稳定时自动回退到次优的时钟源，单调时钟保持连续。
+//! `monotonic_ns` 与 `Instant` 供日志、性能分析、超时与调度使用。
+//!
+//! 墙上时间：启动时读取一次 CMOS RTC，此后由单调时钟推进，`now` 返回 UNIX 时间戳。
+
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use core::time::Duration;
+
+/// 时钟源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    Jiffies = 0,
+    Hpet = 1,
+    Tsc = 2,
+}
+
+impl ClockSource {
+    /// 按优先级从高到低排列
+    const ALL: [ClockSource; 3] = [ClockSource::Tsc, ClockSource::Hpet, ClockSource::Jiffies];
+
+    /// `clocksource=` 使用的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            ClockSource::Jiffies => "jiffies",
+            ClockSource::Hpet => "hpet",
+            ClockSource::Tsc => "tsc",
+        }
+    }
+
+    /// 当前硬件上能否使用
+    fn is_usable(self) -> bool {
+        match self {
+            ClockSource::Jiffies => crate::pit::frequency() != 0,
+            ClockSource::Hpet => crate::hpet::is_available() && crate::hpet::counter_is_64bit(),
+            ClockSource::Tsc => crate::tsc::is_stable(),
+        }
+    }
+
+    /// 读取当前时间（纳秒）
+    fn read_ns(self) -> u64 {
+        match self {
+            ClockSource::Jiffies => {
+                let hz = crate::pit::frequency().max(1);
+                (crate::pit::jiffies() as u128 * 1_000_000_000 / hz as u128) as u64
+            }
+            ClockSource::Hpet => crate::hpet::ticks_to_ns(crate::hpet::counter()),
+            ClockSource::Tsc => crate::tsc::cycles_to_ns(crate::tsc::read()),
+        }
+    }
+}
+
+impl fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 当前时钟源
+static SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Jiffies as u8);
+
+/// 切换时钟源时补偿的偏移（纳秒），保证单调时钟不回退
+static OFFSET_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 单调时钟零点对应的 UNIX 时间（纳秒），墙上时间未知时为 0
+static BOOT_UNIX_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 选择时钟源（在 PIT、HPET 与 TSC 初始化之后调用）
+pub fn init() {
+    let best = ClockSource::ALL.into_iter().find(|source| source.is_usable()).unwrap_or(ClockSource::Jiffies);
+    let source = match crate::cmdline::value("clocksource") {
+        None => best,
+        Some(name) => match ClockSource::ALL.into_iter().find(|source| source.name() == name) {
+            Some(source) if source.is_usable() => source,
+            Some(source) => {
+                log::warn!("clocksource: {source} requested but unusable, using {best}");
+                best
+            }
+            None => {
+                log::warn!("clocksource: unknown clock source \"{name}\", using {best}");
+                best
+            }
+        },
+    };
+    SOURCE.store(source as u8, Ordering::Relaxed);
+    log::info!("clocksource: switched to {source}");
+    sync_wall_clock();
+}
+
+/// 从 RTC 读取墙上时间，与当前单调时钟对齐
+fn sync_wall_clock() {
+    match crate::rtc::read() {
+        Ok(time) => {
+            let unix_ns = time.to_unix() * 1_000_000_000;
+            BOOT_UNIX_NS.store(unix_ns.saturating_sub(monotonic_ns()).max(1), Ordering::Relaxed);
+            log::info!("RTC: {time} UTC (UNIX {})", time.to_unix());
+        }
+        Err(e) => log::warn!("RTC: cannot read wall-clock time ({e})"),
+    }
+}
+
+/// 当前时钟源
+pub fn clocksource() -> ClockSource {
+    match SOURCE.load(Ordering::Relaxed) {
+        1 => ClockSource::Hpet,
+        2 => ClockSource::Tsc,
+        _ => ClockSource::Jiffies,
+    }
+}
+
+/// 当前时钟源不再可用（由 `tsc::mark_unstable` 调用）：切换到次优的时钟源
+pub fn clocksource_unstable() {
+    let old = clocksource();
+    if old.is_usable() {
+        return;
+    }
+    let new = ClockSource::ALL.into_iter().find(|source| source.is_usable()).unwrap_or(ClockSource::Jiffies);
+    // 新时钟源从旧时钟源的当前读数接着走
+    let now = monotonic_ns();
+    OFFSET_NS.store(now.saturating_sub(new.read_ns()), Ordering::Relaxed);
+    SOURCE.store(new as u8, Ordering::Relaxed);
+    log::warn!("clocksource: {old} unstable, switched to {new}");
+}
+
+/// 单调时钟（纳秒），起点为时钟源开始计数的时刻
+pub fn monotonic_ns() -> u64 {
+    clocksource().read_ns() + OFFSET_NS.load(Ordering::Relaxed)
+}
+
+/// 墙上时间（自 UNIX 纪元起的纳秒），RTC 未读取时为 None
+pub fn realtime_ns() -> Option<u64> {
+    match BOOT_UNIX_NS.load(Ordering::Relaxed) {
+        0 => None,
+        boot => Some(boot + monotonic_ns()),
+    }
+}
+
+/// 当前 UNIX 时间戳（秒），RTC 未读取时为 None
+#[allow(dead_code)]
+pub fn now() -> Option<u64> {
+    realtime_ns().map(|ns| ns / 1_000_000_000)
+}
+
+/// 以最精确的可用基准忙等待 `us` 微秒，返回所用基准的名称（供校准其他计时硬件）
+pub fn reference_delay_us(us: u64) -> &'static str {
+    if crate::hpet::is_available() && crate::hpet::counter_is_64bit() {
+        let start = crate::hpet::counter();
+        let ticks = us * crate::hpet::frequency() / 1_000_000;
+        while crate::hpet::counter().wrapping_sub(start) < ticks {
+            core::hint::spin_loop();
+        }
+        "HPET"
+    } else {
+        crate::pit::delay_us(us);
+        "PIT"
+    }
+}
+
+/// 单调时钟上的一个时刻
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// 当前时刻
+    pub fn now() -> Self {
+        Instant(monotonic_ns())
+    }
+
+    /// 从 `earlier` 到该时刻经过的时间（`earlier` 更晚时为 0）
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// 从该时刻到现在经过的时间
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// 该时刻之后 `duration` 的时刻，溢出时为 None
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        u64::try_from(duration.as_nanos()).ok().and_then(|ns| self.0.checked_add(ns)).map(Instant)
+    }
+
+    /// 单调时钟的纳秒读数
+    #[allow(dead_code)]
+    pub fn as_nanos(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// 公历日期与时间（UTC）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// 各字段是否在合法范围内（不早于 1970 年）
+    pub fn is_valid(&self) -> bool {
+        self.year >= 1970
+            && (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// 换算为 UNIX 时间戳（秒）
+    pub fn to_unix(self) -> u64 {
+        days_from_civil(self.year as u64, self.month as u64, self.day as u64) * 86_400
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// 由 UNIX 时间戳（秒）换算
+    pub fn from_unix(secs: u64) -> Self {
+        let (year, month, day) = civil_from_days(secs / 86_400);
+        let rem = secs % 86_400;
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// 公历日期到 1970-01-01 起的天数（以 3 月为年首，400 年为一个周期）
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// 1970-01-01 起的天数到公历日期（`days_from_civil` 的逆运算）
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test_case]
+fn test_unix_epoch_and_known_dates() {
+    let date = |year, month, day, hour, minute, second| DateTime { year, month, day, hour, minute, second };
+    let known = [
+        (0, date(1970, 1, 1, 0, 0, 0)),
+        (951_782_400, date(2000, 2, 29, 0, 0, 0)),
+        (1_700_000_000, date(2023, 11, 14, 22, 13, 20)),
+        (2_147_483_647, date(2038, 1, 19, 3, 14, 7)),
+        (4_102_444_800, date(2100, 1, 1, 0, 0, 0)),
+    ];
+    for (secs, time) in known {
+        assert_eq!(time.to_unix(), secs, "{time}");
+        assert_eq!(DateTime::from_unix(secs), time, "{secs}");
+    }
+}
+
+#[test_case]
+fn test_civil_days_round_trip() {
+    // 覆盖 1970–2200 年，含 2000（闰）与 2100（不闰）的世纪年
+    let mut previous = None;
+    for days in 0..84_000 {
+        let (year, month, day) = civil_from_days(days);
+        assert_eq!(days_from_civil(year, month, day), days);
+        let time = DateTime::from_unix(days * 86_400);
+        assert!(time.is_valid(), "{time}");
+        if let Some((prev_year, prev_month, prev_day)) = previous {
+            let next_day = (year, month, day) == (prev_year, prev_month, prev_day + 1);
+            let next_month = (year, month, day) == (prev_year, prev_month + 1, 1);
+            let next_year = (year, month, day) == (prev_year + 1, 1, 1);
+            assert!(next_day || next_month || next_year, "{year}-{month}-{day}");
+        }
+        previous = Some((year, month, day));
+    }
+}
+
+#[test_case]
+fn test_date_validation() {
+    let date = |year, month, day| DateTime { year, month, day, hour: 0, minute: 0, second: 0 };
+    assert!(date(2024, 2, 29).is_valid());
+    assert!(date(2000, 2, 29).is_valid());
+    assert!(!date(2023, 2, 29).is_valid());
+    assert!(!date(2100, 2, 29).is_valid());
+    assert!(!date(2024, 4, 31).is_valid());
+    assert!(!date(2024, 13, 1).is_valid());
+    assert!(!date(2024, 1, 0).is_valid());
+    assert!(!date(1969, 12, 31).is_valid());
+    assert!(!DateTime { hour: 24, ..date(2024, 1, 1) }.is_valid());
+    assert!(!DateTime { minute: 60, ..date(2024, 1, 1) }.is_valid());
+    assert!(!DateTime { second: 60, ..date(2024, 1, 1) }.is_valid());
+}
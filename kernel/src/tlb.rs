@@ -0,0 +1,119 @@
+//! TLB 管理模块
+//! 本地刷新（INVLPG / INVPCID）与基于远程调用的批量 TLB 击落
+//!
+//! 目前只有内核一个地址空间，不启用 PCID（CR4.PCIDE）；INVPCID 在未启用 PCID 时
+//! 同样可以刷新全部 TLB 项（包括全局页）。
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::instructions::tlb::{self, InvPicdCommand};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::VirtAddr;
+use crate::constants::tlb::TLB_BATCH_SIZE;
+use crate::cpu::{self, Feature};
+use crate::per_cpu;
+
+/// CPU 支持 INVPCID
+static INVPCID: AtomicBool = AtomicBool::new(false);
+
+per_cpu! {
+    // 响应 TLB 击落的次数
+    static SHOOTDOWNS: AtomicU64 = AtomicU64::new(0);
+}
+
+/// 检测 INVPCID（在 BSP 上调用）
+pub fn init() {
+    let invpcid = cpu::has(Feature::Invpcid);
+    INVPCID.store(invpcid, Ordering::Relaxed);
+    log::info!("TLB: full flushes via {}", if invpcid { "INVPCID" } else { "CR4.PGE toggle" });
+}
+
+/// 刷新当前 CPU 上单个页面的 TLB 项（包括全局页）
+pub fn flush_page(addr: VirtAddr) {
+    tlb::flush(addr);
+}
+
+/// 刷新当前 CPU 上的全部 TLB 项（包括全局页）
+pub fn flush_all() {
+    if INVPCID.load(Ordering::Relaxed) {
+        unsafe { tlb::flush_pcid(InvPicdCommand::All) };
+        return;
+    }
+    // 切换 CR4.PGE 会清除所有 TLB 项，重新加载 CR3 则保留全局页
+    let cr4 = Cr4::read();
+    if cr4.contains(Cr4Flags::PAGE_GLOBAL) {
+        unsafe {
+            Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(cr4);
+        }
+    } else {
+        tlb::flush_all();
+    }
+}
+
+/// 一批待刷新的页面
+/// 修改页表时逐页记录，修改完成后一次性刷新本地 TLB 并击落其他 CPU 上的对应项；
+/// 超过容量时退化为全部刷新
+pub struct TlbBatch {
+    pages: [u64; TLB_BATCH_SIZE],
+    count: usize,
+    full: bool,
+}
+
+impl TlbBatch {
+    pub const fn new() -> Self {
+        Self { pages: [0; TLB_BATCH_SIZE], count: 0, full: false }
+    }
+
+    /// 记录一个需要刷新的页面
+    pub fn add(&mut self, addr: VirtAddr) {
+        match self.pages.get_mut(self.count) {
+            Some(slot) => {
+                *slot = addr.as_u64();
+                self.count += 1;
+            }
+            None => self.full = true,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0 && !self.full
+    }
+
+    /// 在当前 CPU 上执行刷新
+    fn flush_local(&self) {
+        if self.full {
+            flush_all();
+        } else {
+            for &page in &self.pages[..self.count] {
+                flush_page(VirtAddr::new(page));
+            }
+        }
+    }
+
+    /// 刷新本地 TLB 并击落其他在线 CPU 上的对应项，返回时所有 CPU 都已完成刷新
+    pub fn finish(self) {
+        if self.is_empty() {
+            return;
+        }
+        self.flush_local();
+        // 批次位于发起者的栈上，远程调用返回前一直有效
+        crate::ipi::call_on_others(remote_flush, &self as *const TlbBatch as usize);
+    }
+}
+
+/// TLB 击落的远程端
+fn remote_flush(batch: usize) {
+    let batch = unsafe { &*(batch as *const TlbBatch) };
+    batch.flush_local();
+    SHOOTDOWNS.get().fetch_add(1, Ordering::Relaxed);
+}
+
+/// 输出 TLB 击落的统计（由 ipi::write_stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    write!(f, "\nTLB:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", SHOOTDOWNS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  TLB shootdowns")
+}
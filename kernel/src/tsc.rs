@@ -0,0 +1,162 @@
+//! 时间戳计数器（TSC）模块
+//! 确定 TSC 频率并把周期数换算为纳秒，供单调时钟与 TSC-deadline 定时器使用。
+//! 频率按以下顺序获取：
+//! - CPUID 叶 0x15（晶振频率与 TSC/晶振比，晶振频率缺失时由叶 0x16 的基准频率推出）
+//! - 虚拟机监控器的计时信息叶 0x40000010（KVM、VMware 等）
+//! - 以 HPET 或 PIT 通道 2 为基准校准
+//!
+//! 只有不变 TSC（CPUID 0x80000007 EDX[8]）才作为时钟源；
+//! 命令行参数 `tsc=reliable` 跳过该检查，`tsc=unstable` 禁止使用。
+//! AP 上线时检查其 TSC 是否落后于 BSP，发现不同步即标记为不稳定，时钟源随之回退。
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+use crate::constants::pit::CALIBRATION_US;
+use crate::cpu::{self, Feature};
+
+/// 虚拟机监控器计时信息叶：EAX 为 TSC 频率（kHz）
+const HYPERVISOR_TIMING_LEAF: u32 = 0x4000_0010;
+
+/// 纳秒换算的定点小数位数
+const NS_SHIFT: u32 = 32;
+
+/// TSC 频率（Hz），未知时为 0
+static HZ: AtomicU64 = AtomicU64::new(0);
+
+/// 周期数换算为纳秒的乘数：ns = cycles * MULT >> NS_SHIFT
+static MULT: AtomicU64 = AtomicU64::new(0);
+
+/// TSC 可以作为时钟源
+static STABLE: AtomicBool = AtomicBool::new(false);
+
+/// BSP 启动 AP 之前读取的 TSC，AP 上线时不应小于它
+static AP_START_STAMP: AtomicU64 = AtomicU64::new(0);
+
+/// 确定 TSC 频率与稳定性（在 HPET 初始化之后、选择时钟源之前调用）
+pub fn init() {
+    if !cpu::has(Feature::Tsc) {
+        log::info!("TSC: not present");
+        return;
+    }
+    let (hz, source) = match frequency_from_cpuid() {
+        Some(found) => found,
+        None => match frequency_from_hypervisor() {
+            Some(hz) => (hz, "hypervisor"),
+            None => calibrate(),
+        },
+    };
+    if hz == 0 {
+        log::warn!("TSC: cannot determine frequency");
+        return;
+    }
+    HZ.store(hz, Ordering::Relaxed);
+    MULT.store(((1_000_000_000u128 << NS_SHIFT) / hz as u128) as u64, Ordering::Relaxed);
+
+    let stable = match crate::cmdline::value("tsc") {
+        Some("reliable") => true,
+        Some("unstable") => false,
+        _ => cpu::has(Feature::InvariantTsc),
+    };
+    STABLE.store(stable, Ordering::Relaxed);
+    log::info!(
+        "TSC: {}.{:03} MHz ({}), {}",
+        hz / 1_000_000,
+        hz / 1000 % 1000,
+        source,
+        if stable { "invariant" } else { "unstable, not used as clock source" }
+    );
+}
+
+/// CPUID 叶 0x15：TSC = 晶振频率 * EBX / EAX，同时返回频率的来源
+fn frequency_from_cpuid() -> Option<(u64, &'static str)> {
+    let features = cpu::features();
+    if features.max_leaf < 0x15 {
+        return None;
+    }
+    let r = cpu::cpuid(0x15, 0);
+    let (denominator, numerator) = (r.eax as u64, r.ebx as u64);
+    if denominator == 0 || numerator == 0 {
+        return None;
+    }
+    let (crystal, source) = match r.ecx as u64 {
+        // 晶振频率未枚举：由叶 0x16 的处理器基准频率（MHz）反推
+        0 if features.max_leaf >= 0x16 => {
+            let base_mhz = (cpu::cpuid(0x16, 0).eax & 0xffff) as u64;
+            (base_mhz * 1_000_000 * denominator / numerator, "CPUID 0x15/0x16")
+        }
+        crystal => (crystal, "CPUID 0x15"),
+    };
+    Some((crystal * numerator / denominator, source)).filter(|&(hz, _)| hz != 0)
+}
+
+/// 虚拟机监控器提供的 TSC 频率
+fn frequency_from_hypervisor() -> Option<u64> {
+    if cpu::features().max_hypervisor_leaf < HYPERVISOR_TIMING_LEAF {
+        return None;
+    }
+    let khz = cpu::cpuid(HYPERVISOR_TIMING_LEAF, 0).eax as u64;
+    Some(khz * 1000).filter(|&hz| hz != 0)
+}
+
+/// 以 HPET 或 PIT 通道 2 为基准测量 TSC 频率
+fn calibrate() -> (u64, &'static str) {
+    let (cycles, reference) = interrupts::without_interrupts(|| {
+        let start = read();
+        let reference = crate::time::reference_delay_us(CALIBRATION_US);
+        (read() - start, reference)
+    });
+    let hz = cycles * 1_000_000 / CALIBRATION_US;
+    (hz, if reference == "HPET" { "calibrated against HPET" } else { "calibrated against PIT" })
+}
+
+/// 读取 TSC；lfence 保证之前的指令已经执行完毕，时间戳不会被提前
+pub fn read() -> u64 {
+    unsafe {
+        core::arch::asm!("lfence", options(nostack, preserves_flags));
+        core::arch::x86_64::_rdtsc()
+    }
+}
+
+/// TSC 频率（Hz），未知时为 0
+pub fn frequency() -> u64 {
+    HZ.load(Ordering::Relaxed)
+}
+
+/// TSC 能否作为时钟源
+pub fn is_stable() -> bool {
+    STABLE.load(Ordering::Relaxed)
+}
+
+/// 把周期数换算为纳秒
+pub fn cycles_to_ns(cycles: u64) -> u64 {
+    ((cycles as u128 * MULT.load(Ordering::Relaxed) as u128) >> NS_SHIFT) as u64
+}
+
+/// 把纳秒换算为周期数
+pub fn ns_to_cycles(ns: u64) -> u64 {
+    (ns as u128 * frequency() as u128 / 1_000_000_000) as u64
+}
+
+/// 标记 TSC 不稳定，时钟源回退到 HPET 或 PIT
+pub fn mark_unstable(reason: fmt::Arguments) {
+    if STABLE.swap(false, Ordering::Relaxed) {
+        log::warn!("TSC: marked unstable ({reason})");
+        crate::time::clocksource_unstable();
+    }
+}
+
+/// BSP 即将启动一个 AP 时调用
+pub fn stamp_ap_start() {
+    AP_START_STAMP.store(read(), Ordering::Release);
+}
+
+/// AP 上线时调用：其 TSC 若落后于 BSP 启动它之前的读数，说明各 CPU 的 TSC 不同步
+pub fn check_ap(cpu: usize) {
+    let now = read();
+    let stamp = AP_START_STAMP.load(Ordering::Acquire);
+    if is_stable() && now < stamp {
+        let behind = cycles_to_ns(stamp - now);
+        mark_unstable(format_args!("CPU{cpu} TSC is {behind} ns behind the BSP"));
+    }
+}
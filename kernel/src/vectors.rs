@@ -0,0 +1,97 @@
+//! 动态中断向量分配模块
+//! 为 MSI/MSI-X 等不经过中断控制器的中断分配 IDT 向量，并分发到注册的处理函数
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
+use crate::constants::smp::MAX_CPUS;
+use crate::constants::vectors::*;
+use crate::error::{KernelResult, KernelError};
+use crate::per_cpu;
+use crate::sync::RwLock;
+
+/// 动态向量的数量
+const DYNAMIC_VECTOR_COUNT: usize = (DYNAMIC_VECTOR_END - DYNAMIC_VECTOR_START) as usize;
+
+/// 向量处理函数，参数为向量号
+pub type VectorHandler = fn(vector: u8);
+
+/// 已分配向量的使用者
+#[derive(Clone, Copy)]
+struct VectorAction {
+    name: &'static str,
+    handler: VectorHandler,
+}
+
+/// 每个动态向量的处理函数；中断路径只读，分配与释放时在关中断下写入
+static ACTIONS: [RwLock<Option<VectorAction>>; DYNAMIC_VECTOR_COUNT] =
+    [const { RwLock::new(None) }; DYNAMIC_VECTOR_COUNT];
+
+per_cpu! {
+    // 每个动态向量的中断次数
+    static COUNTS: [AtomicU64; DYNAMIC_VECTOR_COUNT] = [const { AtomicU64::new(0) }; DYNAMIC_VECTOR_COUNT];
+}
+
+/// 分配一个空闲向量并注册处理函数
+pub fn allocate(name: &'static str, handler: VectorHandler) -> KernelResult<u8> {
+    without_interrupts(|| {
+        for (i, slot) in ACTIONS.iter().enumerate() {
+            let mut slot = slot.write();
+            if slot.is_none() {
+                *slot = Some(VectorAction { name, handler });
+                for cpu in 0..MAX_CPUS {
+                    COUNTS.for_cpu(cpu)[i].store(0, Ordering::Relaxed);
+                }
+                return Ok(DYNAMIC_VECTOR_START + i as u8);
+            }
+        }
+        Err(KernelError::NoResources)
+    })
+}
+
+/// 释放向量
+pub fn free(vector: u8) {
+    if let Some(index) = index(vector) {
+        without_interrupts(|| *ACTIONS[index].write() = None);
+    }
+}
+
+/// 向量在动态区中的下标
+fn index(vector: u8) -> Option<usize> {
+    (DYNAMIC_VECTOR_START..DYNAMIC_VECTOR_END)
+        .contains(&vector)
+        .then(|| (vector - DYNAMIC_VECTOR_START) as usize)
+}
+
+/// 判断向量是否属于动态分配区
+pub fn handles(vector: u8) -> bool {
+    index(vector).is_some()
+}
+
+/// 动态向量中断入口（由中断分发函数调用，此时中断已关闭）
+pub fn handle(vector: u8) {
+    let Some(index) = index(vector) else { return };
+    crate::softirq::irq_enter();
+    COUNTS.get()[index].fetch_add(1, Ordering::Relaxed);
+    let action = *ACTIONS[index].read();
+    match action {
+        Some(action) => (action.handler)(vector),
+        None => log::warn!("Interrupt on unallocated vector {vector}"),
+    }
+    // MSI 直接投递到本地 APIC，EOI 也只需发给本地 APIC
+    crate::lapic::eoi();
+    crate::softirq::irq_exit();
+}
+
+/// 输出已分配向量的统计（由 irq::Stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    for (i, slot) in ACTIONS.iter().enumerate() {
+        let Some(action) = without_interrupts(|| *slot.read()) else { continue };
+        write!(f, "\n{:>3}:", DYNAMIC_VECTOR_START as usize + i)?;
+        for cpu in 0..cpus {
+            write!(f, " {:>10}", COUNTS.for_cpu(cpu)[i].load(Ordering::Relaxed))?;
+        }
+        write!(f, "  {:>8} {:>3}-edge  {}", "PCI-MSI", DYNAMIC_VECTOR_START as usize + i, action.name)?;
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use core::fmt;
-use spin::Mutex;
+use crate::sync::IrqSafeSpinlock;
 use bootloader_api::info::{FrameBuffer, PixelFormat};
 use crate::constants::vga::*;
 use crate::font::get_char_data;
@@ -298,7 +298,7 @@ impl FrameBufferWriter {
 
 }
 
-static WRITER: Mutex<Option<FrameBufferWriter>> = Mutex::new(None);
+static WRITER: IrqSafeSpinlock<Option<FrameBufferWriter>> = IrqSafeSpinlock::new(None);
 
 impl fmt::Write for FrameBufferWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -0,0 +1,384 @@
+//! 死锁看门狗模块
+//! - 软死锁：每个 CPU 的本地定时器中断检查内核主循环是否仍在推进
+//! - 硬死锁：NMI（性能计数器溢出，或经 I/O APIC 以 NMI 投递的 PIT 节拍）检查本地定时器中断是否仍在到来，
+//!   长时间没有说明该 CPU 在关中断状态下卡住
+//!
+//! 发现死锁时在卡住的 CPU 上输出寄存器与回溯。命令行参数：
+//! - `nowatchdog`：关闭看门狗
+//! - `nosoftlockup`：只关闭软死锁检测
+//! - `nmi_watchdog=perf|pit|0`：选择 NMI 源（默认优先使用性能计数器）
+//! - `watchdog_thresh=N`：硬死锁阈值（秒），软死锁阈值为其两倍
+//! - `watchdog_panic`：报告后 panic（NMI 中报告的硬死锁直接停机，不经过 panic 处理程序）
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+use crate::constants::apic::LOCAL_TIMER_HZ;
+use crate::constants::watchdog::*;
+use crate::interrupts::TrapFrame;
+use crate::per_cpu;
+use crate::smp::cpu_index;
+
+/// 性能监控 MSR
+const IA32_PMC0: u32 = 0xc1;
+const IA32_PERFEVTSEL0: u32 = 0x186;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+const IA32_PERF_GLOBAL_OVF_CTRL: u32 = 0x390;
+
+/// PERFEVTSEL：未停机核心周期事件，统计内核与用户态，溢出时产生中断
+const EVENT_UNHALTED_CORE_CYCLES: u64 = 0x3c;
+const EVTSEL_USR: u64 = 1 << 16;
+const EVTSEL_OS: u64 = 1 << 17;
+const EVTSEL_INT: u64 = 1 << 20;
+const EVTSEL_EN: u64 = 1 << 22;
+
+/// 写 PMC0 时只有低 32 位有效（按第 31 位符号扩展），周期不能超过 2^31 - 1
+const MAX_PERF_PERIOD: u64 = (1 << 31) - 1;
+
+/// 硬死锁检测的 NMI 源
+#[derive(Debug, Clone, Copy)]
+enum NmiSource {
+    /// 未启用硬死锁检测
+    Off,
+    /// 每个 CPU 的性能计数器溢出 NMI，各自检查自己
+    Perf { period: u64, period_ms: u64, width: u8, version: u8 },
+    /// PIT 节拍经 I/O APIC 以 NMI 投递到 BSP，由 BSP 推进 jiffies 并检查全部 CPU
+    Pit,
+}
+
+/// 启动时确定的看门狗配置
+struct Config {
+    nmi: NmiSource,
+    /// 软死锁阈值（定时器中断次数，0 表示关闭）
+    soft_ticks: u64,
+    /// 硬死锁阈值（毫秒）
+    hard_ms: u64,
+    /// 报告后 panic
+    panic: bool,
+}
+
+static CONFIG: Once<Config> = Once::new();
+
+/// 正在输出死锁报告（多个 CPU 同时报告时串行输出）
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// BSP 收到的 PIT NMI 次数
+static PIT_NMIS: AtomicU64 = AtomicU64::new(0);
+
+per_cpu! {
+    // 主循环最近一次推进时的定时器中断次数
+    static TOUCHED: AtomicU64 = AtomicU64::new(0);
+    // 本轮软死锁已报告
+    static SOFT_REPORTED: AtomicBool = AtomicBool::new(false);
+    // 硬死锁检查上次看到的定时器中断次数
+    static LAST_TICKS: AtomicU64 = AtomicU64::new(0);
+    // 定时器中断停止推进的累计时间（毫秒）
+    static STALLED_MS: AtomicU64 = AtomicU64::new(0);
+    // 本轮硬死锁已报告
+    static HARD_REPORTED: AtomicBool = AtomicBool::new(false);
+    // 其他 CPU 请求本 CPU 在 NMI 中输出现场
+    static DUMP_REQUEST: AtomicBool = AtomicBool::new(false);
+    // 看门狗处理的 NMI 次数
+    static NMI_COUNTS: AtomicU64 = AtomicU64::new(0);
+}
+
+/// 紧急输出一行（不获取串口锁）
+macro_rules! emergency {
+    ($($arg:tt)*) => {
+        crate::serial::emergency_write(format_args!("[ERROR] {}\n", format_args!($($arg)*)))
+    };
+}
+
+/// 解析命令行并测量 CPU 频率（在 BSP 上、校准本地定时器之后、启动 AP 之前调用）
+pub fn init() {
+    if crate::cmdline::has_flag("nowatchdog") {
+        log::info!("Watchdog: disabled (nowatchdog)");
+        return;
+    }
+    if crate::lapic::timer_frequency() == 0 {
+        log::info!("Watchdog: disabled, local APIC timer unavailable");
+        return;
+    }
+
+    let thresh = crate::cmdline::value("watchdog_thresh")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_WATCHDOG_THRESH);
+
+    let cpu_hz = crate::tsc::frequency();
+
+    let nmi = select_nmi_source(cpu_hz);
+    let soft_ticks = if crate::cmdline::has_flag("nosoftlockup") { 0 } else { 2 * thresh * LOCAL_TIMER_HZ };
+    let panic = crate::cmdline::has_flag("watchdog_panic");
+    CONFIG.call_once(|| Config { nmi, soft_ticks, hard_ms: thresh * 1000, panic });
+
+    log::info!(
+        "Watchdog: CPU ~{} MHz, hard lockup {}s via {}, soft lockup {}",
+        cpu_hz / 1_000_000,
+        thresh,
+        match nmi {
+            NmiSource::Off => "nothing (disabled)",
+            NmiSource::Perf { .. } => "perf counter NMI",
+            NmiSource::Pit => "PIT NMI",
+        },
+        if soft_ticks == 0 { "disabled" } else { "enabled" }
+    );
+}
+
+/// 按 `nmi_watchdog=` 与硬件能力选择硬死锁检测的 NMI 源
+fn select_nmi_source(cpu_hz: u64) -> NmiSource {
+    let requested = crate::cmdline::value("nmi_watchdog");
+    let perf = crate::cpu::features()
+        .perfmon
+        .filter(|pm| pm.cycles_event && pm.counters > 0 && cpu_hz > 0)
+        .map(|pm| {
+            let period = cpu_hz.min(MAX_PERF_PERIOD);
+            NmiSource::Perf { period, period_ms: period * 1000 / cpu_hz, width: pm.width, version: pm.version }
+        });
+    match requested {
+        Some("0") => NmiSource::Off,
+        Some("pit") => pit_nmi(),
+        Some("perf") => perf.unwrap_or_else(|| {
+            log::warn!("Watchdog: no architectural perf counters, hard lockup detection disabled");
+            NmiSource::Off
+        }),
+        Some(other) => {
+            log::warn!("Watchdog: unknown nmi_watchdog={other}, using default");
+            perf.unwrap_or_else(pit_nmi)
+        }
+        None => perf.unwrap_or_else(pit_nmi),
+    }
+}
+
+/// 把 ISA IRQ0 改为 NMI 投递，成功时使用 PIT 作为 NMI 源
+fn pit_nmi() -> NmiSource {
+    if crate::pit::frequency() == 0 {
+        log::warn!("Watchdog: PIT tick not running, hard lockup detection disabled");
+        return NmiSource::Off;
+    }
+    match crate::ioapic::route_nmi(0) {
+        Ok(()) => NmiSource::Pit,
+        Err(e) => {
+            log::warn!("Watchdog: cannot route PIT as NMI ({e}), hard lockup detection disabled");
+            NmiSource::Off
+        }
+    }
+}
+
+/// 在当前 CPU 上启动看门狗（BSP 在打开中断后调用，AP 在上线时调用）
+pub fn start_cpu() {
+    let Some(config) = CONFIG.r#try() else { return };
+    let ticks = crate::clockevent::ticks(cpu_index());
+    TOUCHED.get().store(ticks, Ordering::Relaxed);
+    LAST_TICKS.get().store(ticks, Ordering::Relaxed);
+
+    match config.nmi {
+        NmiSource::Perf { period, version, .. } => unsafe {
+            Msr::new(IA32_PERFEVTSEL0).write(0);
+            write_perf_period(period);
+            Msr::new(IA32_PERFEVTSEL0)
+                .write(EVENT_UNHALTED_CORE_CYCLES | EVTSEL_USR | EVTSEL_OS | EVTSEL_INT | EVTSEL_EN);
+            if version >= 2 {
+                let mut global = Msr::new(IA32_PERF_GLOBAL_CTRL);
+                global.write(global.read() | 1);
+            }
+            crate::lapic::set_perf_nmi();
+        },
+        NmiSource::Pit | NmiSource::Off => {}
+    }
+}
+
+/// 让 PMC0 在 `period` 个周期后溢出
+fn write_perf_period(period: u64) {
+    unsafe { Msr::new(IA32_PMC0).write(period.wrapping_neg() & 0xffff_ffff) };
+}
+
+/// 主循环推进时调用，表明本 CPU 没有软死锁
+pub fn touch() {
+    TOUCHED.get().store(crate::clockevent::ticks(cpu_index()), Ordering::Relaxed);
+    SOFT_REPORTED.get().store(false, Ordering::Relaxed);
+}
+
+/// 本地定时器节拍（由 `clockevent::handle_event` 调用），`ticks` 为本 CPU 的节拍数
+pub fn timer_tick(frame: Option<&TrapFrame>, ticks: u64) {
+    let Some(config) = CONFIG.r#try() else { return };
+    let stuck = ticks - TOUCHED.get().load(Ordering::Relaxed);
+    if config.soft_ticks != 0 && stuck > config.soft_ticks && !SOFT_REPORTED.get().swap(true, Ordering::Relaxed) {
+        let secs = stuck / LOCAL_TIMER_HZ;
+        report(config, format_args!("soft lockup - CPU{} stuck for {}s", cpu_index(), secs), frame, false);
+    }
+}
+
+/// NMI 入口：返回 true 表示该 NMI 由看门狗处理
+pub fn nmi(frame: &TrapFrame) -> bool {
+    let cpu = cpu_index();
+    if DUMP_REQUEST.get().swap(false, Ordering::Relaxed) {
+        NMI_COUNTS.get().fetch_add(1, Ordering::Relaxed);
+        if let Some(config) = CONFIG.r#try() {
+            report_hard(config, cpu, frame);
+        }
+        return true;
+    }
+    let Some(config) = CONFIG.r#try() else { return false };
+    match config.nmi {
+        NmiSource::Perf { period, period_ms, width, version } => {
+            // 计数器从负数开始计数，最高位清零说明已经溢出
+            let counter = unsafe { Msr::new(IA32_PMC0).read() };
+            if counter & (1 << (width - 1)) != 0 {
+                return false;
+            }
+            write_perf_period(period);
+            if version >= 2 {
+                unsafe { Msr::new(IA32_PERF_GLOBAL_OVF_CTRL).write(1) };
+            }
+            crate::lapic::set_perf_nmi();
+            NMI_COUNTS.get().fetch_add(1, Ordering::Relaxed);
+            check_cpu(config, cpu, period_ms, Some(frame));
+            true
+        }
+        NmiSource::Pit if cpu == 0 => {
+            NMI_COUNTS.get().fetch_add(1, Ordering::Relaxed);
+            // IRQ0 已改为 NMI 投递，节拍由这里推进；每秒检查一次
+            crate::pit::tick();
+            if (PIT_NMIS.fetch_add(1, Ordering::Relaxed) + 1) % crate::pit::frequency() == 0 {
+                for other in 0..crate::smp::cpu_count() {
+                    if crate::smp::apic_id(other).is_some() {
+                        check_cpu(config, other, 1000, (other == cpu).then_some(frame));
+                    }
+                }
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// 检查某个 CPU 的定时器中断是否仍在推进；`frame` 为该 CPU 被打断的现场（检查其他 CPU 时为 None）
+fn check_cpu(config: &Config, cpu: usize, elapsed_ms: u64, frame: Option<&TrapFrame>) {
+    let ticks = crate::clockevent::ticks(cpu);
+    if LAST_TICKS.for_cpu(cpu).swap(ticks, Ordering::Relaxed) != ticks {
+        STALLED_MS.for_cpu(cpu).store(0, Ordering::Relaxed);
+        HARD_REPORTED.for_cpu(cpu).store(false, Ordering::Relaxed);
+        return;
+    }
+    let stalled = STALLED_MS.for_cpu(cpu).fetch_add(elapsed_ms, Ordering::Relaxed) + elapsed_ms;
+    if stalled < config.hard_ms || HARD_REPORTED.for_cpu(cpu).swap(true, Ordering::Relaxed) {
+        return;
+    }
+    match frame {
+        Some(frame) => report_hard(config, cpu, frame),
+        None => {
+            // 让卡住的 CPU 在自己的 NMI 中输出现场
+            DUMP_REQUEST.for_cpu(cpu).store(true, Ordering::Relaxed);
+            if let Some(apic_id) = crate::smp::apic_id(cpu) {
+                crate::lapic::send_nmi(apic_id);
+            }
+        }
+    }
+}
+
+fn report_hard(config: &Config, cpu: usize, frame: &TrapFrame) {
+    let stalled = STALLED_MS.for_cpu(cpu).load(Ordering::Relaxed);
+    report(config, format_args!("hard LOCKUP - CPU{cpu} has not taken timer interrupts for {stalled} ms"), Some(frame), true);
+}
+
+/// 输出死锁报告：原因、寄存器与回溯（没有现场时只输出原因）；`in_nmi` 表示在 NMI 中调用
+fn report(config: &Config, reason: fmt::Arguments, frame: Option<&TrapFrame>, in_nmi: bool) {
+    // 其他 CPU 正在报告时稍等，但不无限等待（对方可能就卡在报告中）
+    for _ in 0..10_000_000 {
+        if !REPORTING.swap(true, Ordering::Acquire) {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    emergency!("WATCHDOG: {}", reason);
+    if let Some(frame) = frame {
+        let mut out = EmergencyWriter;
+        let _ = writeln!(out, "{frame}");
+        backtrace(frame);
+    }
+    REPORTING.store(false, Ordering::Release);
+    if config.panic && in_nmi {
+        // NMI 可能打断了持有串口锁或日志锁的代码，不能进入 panic 处理程序，只紧急输出后停机
+        emergency!("Kernel panic - not syncing: watchdog: {}", reason);
+        x86_64::instructions::interrupts::disable();
+        loop {
+            x86_64::instructions::hlt();
+        }
+    }
+    if config.panic {
+        panic!("watchdog: {}", reason);
+    }
+}
+
+/// 紧急输出的 fmt::Write 适配
+struct EmergencyWriter;
+
+impl Write for EmergencyWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::serial::emergency_write(format_args!("{s}"));
+        Ok(())
+    }
+}
+
+extern "C" {
+    static __text_start: u8;
+    static __text_end: u8;
+}
+
+/// 地址是否落在内核代码段内
+fn is_kernel_text(addr: u64) -> bool {
+    let start = core::ptr::addr_of!(__text_start) as u64;
+    let end = core::ptr::addr_of!(__text_end) as u64;
+    (start..end).contains(&addr)
+}
+
+/// 输出被打断代码的回溯
+///
+/// 先沿 RBP 帧链回溯（内核未强制保留帧指针，链可能很短）；
+/// 再扫描 RSP 所在页中看起来像返回地址的值，以 `?` 标记这些不可靠的条目。
+fn backtrace(frame: &TrapFrame) {
+    emergency!("Call trace:");
+    emergency!("  [<{:#018x}>]", frame.rip);
+
+    let page_end = (frame.rsp | 0xfff) + 1;
+    let mut rbp = frame.rbp;
+    for _ in 0..BACKTRACE_DEPTH {
+        if rbp < frame.rsp || rbp + 16 > page_end || rbp % 8 != 0 {
+            break;
+        }
+        let (next, ret) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+        if !is_kernel_text(ret) {
+            break;
+        }
+        emergency!("  [<{:#018x}>]", ret);
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+
+    let mut shown = 0;
+    let mut addr = (frame.rsp + 7) & !7;
+    while addr + 8 <= page_end && shown < BACKTRACE_DEPTH {
+        let value = unsafe { *(addr as *const u64) };
+        if is_kernel_text(value) {
+            emergency!("  ? [<{:#018x}>] (stack {:#x})", value, addr);
+            shown += 1;
+        }
+        addr += 8;
+    }
+    if let Some(layout) = crate::kaslr::layout() {
+        emergency!("Kernel image base {:#x} (slide {:#x})", crate::kaslr::runtime_base(), layout.slide);
+    }
+}
+
+/// 输出看门狗 NMI 的统计（由 irq::Stats 调用）
+pub fn write_stats(f: &mut fmt::Formatter<'_>, cpus: usize) -> fmt::Result {
+    write!(f, "\nNMI:")?;
+    for cpu in 0..cpus {
+        write!(f, " {:>10}", NMI_COUNTS.for_cpu(cpu).load(Ordering::Relaxed))?;
+    }
+    write!(f, "  Watchdog NMIs")
+}
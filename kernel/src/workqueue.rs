@@ -0,0 +1,113 @@
+//! 工作队列模块
+//! 把耗时的工作推迟到进程上下文执行，工作函数可以睡眠等待、做同步 I/O
+//!
+//! 内核还没有调度器，工作线程的角色由每个 CPU 的空闲循环承担：
+//! 空闲循环在 `hlt` 之前调用 [`run_pending`] 处理排队的工作
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::constants::softirq::WORK_QUEUE_LEN;
+use crate::sync::IrqSafeSpinlock;
+
+/// 一项延迟工作
+/// 已在队列中时再次提交会被忽略
+pub struct Work {
+    func: fn(usize),
+    data: usize,
+    pending: AtomicBool,
+}
+
+impl Work {
+    pub const fn new(func: fn(usize), data: usize) -> Self {
+        Self { func, data, pending: AtomicBool::new(false) }
+    }
+}
+
+/// 工作队列（环形缓冲区）
+pub struct WorkQueue {
+    name: &'static str,
+    inner: IrqSafeSpinlock<Ring>,
+}
+
+struct Ring {
+    items: [Option<&'static Work>; WORK_QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl WorkQueue {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            inner: IrqSafeSpinlock::new(Ring { items: [None; WORK_QUEUE_LEN], head: 0, len: 0 }),
+        }
+    }
+
+    /// 提交工作（可在中断上下文中调用），返回 false 表示已在队列中或队列已满
+    pub fn queue(&self, work: &'static Work) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let queued = {
+            let mut ring = self.inner.lock();
+            let full = ring.len == WORK_QUEUE_LEN;
+            if !full {
+                let tail = (ring.head + ring.len) % WORK_QUEUE_LEN;
+                ring.items[tail] = Some(work);
+                ring.len += 1;
+            }
+            !full
+        };
+        if !queued {
+            work.pending.store(false, Ordering::Release);
+        }
+        queued
+    }
+
+    /// 取出下一项工作
+    fn pop(&self) -> Option<&'static Work> {
+        let mut ring = self.inner.lock();
+        if ring.len == 0 {
+            return None;
+        }
+        let head = ring.head;
+        ring.head = (head + 1) % WORK_QUEUE_LEN;
+        ring.len -= 1;
+        ring.items[head].take()
+    }
+
+    /// 队列中是否有待处理的工作
+    pub fn has_pending(&self) -> bool {
+        self.inner.lock().len != 0
+    }
+
+    /// 依次执行队列中的工作（只能在进程上下文中调用），返回执行的数量
+    pub fn run(&self) -> usize {
+        debug_assert!(!crate::softirq::in_interrupt(), "workqueue {} run from interrupt", self.name);
+        let mut count = 0;
+        while let Some(work) = self.pop() {
+            // 先清除标志，允许工作在执行期间被再次提交
+            work.pending.store(false, Ordering::Release);
+            (work.func)(work.data);
+            count += 1;
+        }
+        count
+    }
+}
+
+/// 系统默认工作队列
+pub static SYSTEM_WQ: WorkQueue = WorkQueue::new("events");
+
+/// 向系统工作队列提交工作
+pub fn schedule_work(work: &'static Work) -> bool {
+    SYSTEM_WQ.queue(work)
+}
+
+/// 工作线程主体：处理系统工作队列中的全部工作
+pub fn run_pending() -> usize {
+    SYSTEM_WQ.run()
+}
+
+/// 系统工作队列中是否有待处理的工作
+pub fn has_pending() -> bool {
+    SYSTEM_WQ.has_pending()
+}
@@ -0,0 +1,204 @@
+//! 内核映像 W^X 保护模块
+//! 按 linker.ld 导出的段边界设置页面权限，并通过自检确认保护生效
+
+use core::arch::{asm, global_asm};
+use core::ptr::addr_of;
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::{Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB, Translate};
+use x86_64::VirtAddr;
+use crate::cpu::{self, Feature};
+use crate::error::KernelResult;
+use crate::tlb::TlbBatch;
+
+// 链接脚本导出的段边界（均按 4KiB 对齐）
+extern "C" {
+    static __kernel_start: u8;
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __data_start: u8;
+    static __data_end: u8;
+}
+
+/// 自检使用的只读数据（写入会触发页错误）
+#[used]
+#[link_section = ".rodata.wx_probe"]
+static WX_PROBE_RODATA: u8 = 0;
+
+/// 自检使用的数据段代码（内容为 `ret`，执行会触发页错误）
+#[used]
+#[link_section = ".data.wx_probe"]
+static mut WX_PROBE_CODE: [u8; 1] = [0xC3];
+
+// 自检探针：发生预期中的页错误时由页错误处理程序跳转到对应的 fixup 标签
+global_asm!(
+    ".section .text.wx_probe, \"ax\"",
+    ".global wx_probe_write",
+    ".global wx_probe_write_insn",
+    ".global wx_probe_write_fixup",
+    ".global wx_probe_exec",
+    ".global wx_probe_exec_fixup",
+    // rdi = 目标地址，写回原值，不会改变内容
+    "wx_probe_write:",
+    "    mov al, byte ptr [rdi]",
+    "wx_probe_write_insn:",
+    "    mov byte ptr [rdi], al",
+    "    xor eax, eax",
+    "    ret",
+    "wx_probe_write_fixup:",
+    "    mov eax, 1",
+    "    ret",
+    // rdi = 目标地址，故障时丢弃 call 压入的返回地址
+    "wx_probe_exec:",
+    "    call rdi",
+    "    xor eax, eax",
+    "    ret",
+    "wx_probe_exec_fixup:",
+    "    add rsp, 8",
+    "    mov eax, 1",
+    "    ret",
+    ".previous",
+);
+
+extern "C" {
+    /// 尝试写入目标地址，返回 1 表示发生了页错误
+    fn wx_probe_write(addr: *const u8) -> u32;
+    /// 尝试执行目标地址，返回 1 表示发生了页错误
+    fn wx_probe_exec(addr: *const u8) -> u32;
+}
+
+/// 段权限
+#[derive(Debug, Clone, Copy)]
+enum Protection {
+    /// 只读可执行（RX）
+    Executable,
+    /// 只读不可执行（R+NX）
+    ReadOnly,
+    /// 可读写不可执行（RW+NX）
+    Writable,
+}
+
+impl Protection {
+    fn apply(self, flags: PageTableFlags) -> PageTableFlags {
+        let base = flags - PageTableFlags::WRITABLE - PageTableFlags::NO_EXECUTE;
+        match self {
+            Protection::Executable => base,
+            Protection::ReadOnly => base | PageTableFlags::NO_EXECUTE,
+            Protection::Writable => base | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        }
+    }
+}
+
+/// 启用 W^X 保护
+pub fn init() -> KernelResult<()> {
+    let nx = cpu::has(Feature::Nx);
+    unsafe {
+        if nx {
+            Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        } else {
+            log::warn!("W^X: CPU does not support NX, data pages stay executable");
+        }
+        // 让内核态写入同样遵守只读页属性
+        Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+    }
+
+    let sections = [
+        (".headers", addr_of!(__kernel_start), addr_of!(__text_start), Protection::ReadOnly),
+        (".text", addr_of!(__text_start), addr_of!(__text_end), Protection::Executable),
+        (".rodata", addr_of!(__rodata_start), addr_of!(__rodata_end), Protection::ReadOnly),
+        (".data/.bss", addr_of!(__data_start), addr_of!(__data_end), Protection::Writable),
+    ];
+
+    let mut batch = TlbBatch::new();
+    crate::memory::with_mapper(|mapper| {
+        for (name, start, end, protection) in sections {
+            let (start, end) = (start as u64, end as u64);
+            let (updated, huge) = protect(mapper, start, end, protection, &mut batch);
+            log::info!("W^X: {name:<10} {start:#x}-{end:#x} {protection:?} ({updated} pages)");
+            if huge > 0 {
+                log::warn!("W^X: {name} has {huge} page(s) inside huge mappings, left unchanged");
+            }
+        }
+    })?;
+    batch.finish();
+    Ok(())
+}
+
+/// 设置地址范围内所有 4KiB 页面的权限，返回（已更新页数，位于大页中的页数）
+/// 修改过的页面记入 `batch`，由调用者统一刷新
+fn protect(
+    mapper: &mut OffsetPageTable<'static>,
+    start: u64,
+    end: u64,
+    protection: Protection,
+    batch: &mut TlbBatch,
+) -> (usize, usize) {
+    if start >= end {
+        return (0, 0);
+    }
+
+    let first = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+    let last = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+    let (mut updated, mut huge) = (0, 0);
+
+    for page in Page::range_inclusive(first, last) {
+        match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(_), flags, .. } => {
+                let result = unsafe { mapper.update_flags(page, protection.apply(flags)) };
+                if let Ok(flush) = result {
+                    flush.ignore();
+                    batch.add(page.start_address());
+                    updated += 1;
+                }
+            }
+            TranslateResult::Mapped { .. } => huge += 1,
+            _ => {}
+        }
+    }
+    (updated, huge)
+}
+
+/// 获取汇编标签的运行时地址
+macro_rules! label_address {
+    ($label:literal) => {{
+        let addr: u64;
+        unsafe {
+            asm!(concat!("lea {}, [rip + ", $label, "]"), out(reg) addr, options(nomem, nostack, preserves_flags));
+        }
+        addr
+    }};
+}
+
+/// 查找预期故障的修复地址
+/// 由页错误处理程序调用，只对自检探针中的指令生效
+pub fn fault_fixup(rip: u64) -> Option<u64> {
+    if rip == label_address!("wx_probe_write_insn") {
+        Some(label_address!("wx_probe_write_fixup"))
+    } else if rip == addr_of!(WX_PROBE_CODE) as u64 {
+        Some(label_address!("wx_probe_exec_fixup"))
+    } else {
+        None
+    }
+}
+
+/// W^X 自检：写入 .rodata 与执行 .data 都必须触发页错误
+pub fn self_test() -> bool {
+    let write_faulted = unsafe { wx_probe_write(addr_of!(WX_PROBE_RODATA)) } != 0;
+    let exec_faulted = unsafe { wx_probe_exec(addr_of!(WX_PROBE_CODE) as *const u8) } != 0;
+
+    if write_faulted {
+        log::info!("W^X self-test: write to .rodata faulted as expected");
+    } else {
+        log::error!("W^X self-test: write to .rodata did NOT fault");
+    }
+    if exec_faulted {
+        log::info!("W^X self-test: execute from .data faulted as expected");
+    } else {
+        log::error!("W^X self-test: execute from .data did NOT fault");
+    }
+
+    write_faulted && exec_faulted
+}